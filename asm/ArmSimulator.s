@@ -170,6 +170,7 @@ unknown_instruction:
     mov r11, #1
 
 _exit:
+    mov r0, #0      // exit status
     mov r7, #1
     svc #0
 
@@ -23,6 +23,7 @@ _start:
     ldr r0, =output
     bl write_stdout
 
+    mov r0, #0      // exit status
     mov r7, #1      // syscall - exit
     svc #0
 
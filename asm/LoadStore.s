@@ -27,6 +27,7 @@ _start:
 	str r3, [r1]
 	strb r2, [r1]
 
+	mov r0, #0
 	mov r7, #1
 	svc #0
 
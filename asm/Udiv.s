@@ -13,6 +13,7 @@ _start:
     mov r1, #0
     mov r2, #8
     bl udiv
+    mov r0, #0
     mov r7, #1
     svc #0
 
@@ -1,4 +1,9 @@
 #!/usr/bin/env bash
 
-arm-none-eabi-as -march=armv7-a --gdwarf2 -o $1.s.o $1.s
-arm-none-eabi-ld -e _start -u _start -o $1.s.elf $1.s.o
\ No newline at end of file
+# without the GNU toolchain, the emulator's own assembler builds the program instead
+if command -v arm-none-eabi-as > /dev/null; then
+    arm-none-eabi-as -march=armv7-a --gdwarf2 -o $1.s.o $1.s
+    arm-none-eabi-ld -e _start -u _start -o $1.s.elf $1.s.o
+else
+    cargo run -q -- asm $1.s -o $1.s.elf
+fi
@@ -0,0 +1,2032 @@
+use std::collections::HashMap;
+
+use ux::{u12, u24, u4, u5};
+
+use crate::{context::{CpuContext, Endianness}, decoding, error::EmulatorError, file::Symbol, instructions::*};
+
+// a program assembled from source: its code and data, which start at address, and the labels it defines
+#[derive(Clone, Debug)]
+pub struct AssembledProgram {
+    pub address: u32,
+    pub entry_point: u32,           // _start, or the start of the program if there is no such label
+    pub data: Vec<u8>,
+    pub symbols: Vec<Symbol>,
+}
+
+pub fn encode(instruction: &Instruction) -> Result<u32, EmulatorError> {
+    encode_instruction(instruction).map_err(EmulatorError::InvalidAssembly)
+}
+
+// assembles GNU-style assembly for the instructions the emulator supports into one block of memory starting at address;
+// whatever the .section directives say, code and data end up in the order they're written
+pub fn assemble(source: &str, address: u32) -> Result<AssembledProgram, EmulatorError> {
+    let statements = parse_statements(source);
+    let mut assembler = Assembler::create(address);
+
+    assembler.lay_out(&statements).map_err(EmulatorError::InvalidAssembly)?;
+    let data = assembler.emit(&statements).map_err(EmulatorError::InvalidAssembly)?;
+    let entry_point = assembler.symbols.get(ENTRY_POINT_SYMBOL).copied().unwrap_or(address);
+
+    Ok(AssembledProgram {
+        address,
+        entry_point,
+        data,
+        symbols: assembler.labels,
+    })
+}
+
+fn encode_instruction((condition, data): &Instruction) -> Result<u32, String> {
+    let encoded_instruction = match data {
+        InstructionData::Add(args, flags) => encode_read_write_arguments(ADD_OPCODE, args, flags)?,
+        InstructionData::AddWithCarry(args, flags) => encode_read_write_arguments(ADD_WITH_CARRY_OPCODE, args, flags)?,
+        InstructionData::And(args, flags) => encode_read_write_arguments(AND_OPCODE, args, flags)?,
+        InstructionData::BitClear(args, flags) => encode_read_write_arguments(BIT_CLEAR_OPCODE, args, flags)?,
+        InstructionData::Branch(offset, link_flag) => encode_branch(*offset, link_flag)?,
+        InstructionData::BranchExchange(register) => BRANCH_EXCHANGE_VALUE | encode_register(*register),
+        InstructionData::Breakpoint(immediate) => encode_breakpoint(*immediate),
+        InstructionData::Compare(args) => encode_read_arguments(COMPARE_OPCODE, args)?,
+        InstructionData::CompareNegative(args) => encode_read_arguments(COMPARE_NEGATIVE_OPCODE, args)?,
+        InstructionData::ExclusiveOr(args, flags) => encode_read_write_arguments(EXCLUSIVE_OR_OPCODE, args, flags)?,
+        InstructionData::Load(args) => encode_load(args)?,
+        InstructionData::LoadMultiple(args) => encode_block_transfer(args, true)?,
+        InstructionData::Move(args, flags) => encode_write_arguments(MOVE_OPCODE, args, flags)?,
+        InstructionData::MoveHalfWord(args) => MOVE_HALFWORD_VALUE | encode_large_immediate_arguments(args),
+        InstructionData::MoveHalfWordTop(args) => MOVE_HALFWORD_TOP_VALUE | encode_large_immediate_arguments(args),
+        InstructionData::MoveNot(args, flags) => encode_write_arguments(MOVE_NOT_OPCODE, args, flags)?,
+        InstructionData::MoveStatusToRegister(register) => MOVE_STATUS_TO_REGISTER_VALUE | encode_register(*register) << 12,
+        InstructionData::Multiply(args, flags) => encode_multiply(MULTIPLY_OPCODE, flags, args.destination_register, u4::new(0), args.second_operand_register, args.first_operand_register),
+        InstructionData::MultiplyAccumulate(args, flags) => encode_multiply(MULTIPLY_ACCUMULATE_OPCODE, flags, args.destination_register, args.accumulate_register, args.second_operand_register, args.first_operand_register),
+        InstructionData::MultiplySubtract(args) => encode_multiply(MULTIPLY_SUBTRACT_OPCODE, &UpdateStatusFlags::DoNotUpdateStatusFlags, args.destination_register, args.accumulate_register, args.second_operand_register, args.first_operand_register),
+        InstructionData::Or(args, flags) => encode_read_write_arguments(OR_OPCODE, args, flags)?,
+        InstructionData::ReverseSubtract(args, flags) => encode_read_write_arguments(REVERSE_SUBTRACT_OPCODE, args, flags)?,
+        InstructionData::ReverseSubtractWithCarry(args, flags) => encode_read_write_arguments(REVERSE_SUBTRACT_WITH_CARRY_OPCODE, args, flags)?,
+        InstructionData::SetEndianness(endianness) => return encode_set_endianness(condition, endianness),
+        InstructionData::SignedDivide(args) => SIGNED_DIVIDE_VALUE | encode_divide_arguments(args),
+        InstructionData::SignedMultiplyAccumulateLong(args, flags) => encode_long_multiply(SIGNED_MULTIPLY_ACCUMULATE_LONG_OPCODE, flags, args),
+        InstructionData::SignedMultiplyLong(args, flags) => encode_long_multiply(SIGNED_MULTIPLY_LONG_OPCODE, flags, args),
+        InstructionData::SupervisorCall(immediate) => SUPERVISOR_CALL_VALUE | u32::from(*immediate),
+        InstructionData::Store(args) => encode_store(args)?,
+        InstructionData::StoreMultiple(args) => encode_block_transfer(args, false)?,
+        InstructionData::Subtract(args, flags) => encode_read_write_arguments(SUBTRACT_OPCODE, args, flags)?,
+        InstructionData::SubtractWithCarry(args, flags) => encode_read_write_arguments(SUBTRACT_WITH_CARRY_OPCODE, args, flags)?,
+        InstructionData::Test(args) => encode_read_arguments(TEST_OPCODE, args)?,
+        InstructionData::TestEquivalence(args) => encode_read_arguments(TEST_EQUIVALENCE_OPCODE, args)?,
+        InstructionData::UnsignedDivide(args) => UNSIGNED_DIVIDE_VALUE | encode_divide_arguments(args),
+        InstructionData::UnsignedMultiplyAccumulateAccumulateLong(args) => encode_long_multiply(UNSIGNED_MULTIPLY_ACCUMULATE_ACCUMULATE_LONG_OPCODE, &UpdateStatusFlags::DoNotUpdateStatusFlags, args),
+        InstructionData::UnsignedMultiplyAccumulateLong(args, flags) => encode_long_multiply(UNSIGNED_MULTIPLY_ACCUMULATE_LONG_OPCODE, flags, args),
+        InstructionData::UnsignedMultiplyLong(args, flags) => encode_long_multiply(UNSIGNED_MULTIPLY_LONG_OPCODE, flags, args),
+    };
+
+    Ok(encode_condition(condition) << 28 | encoded_instruction)
+}
+
+// SETEND is one of the unconditional instructions, which use condition 0b1111
+fn encode_set_endianness(condition: &Condition, endianness: &Endianness) -> Result<u32, String> {
+    if !matches!(condition, Condition::Always) {
+        return Err(String::from("SETEND cannot be conditional"));
+    }
+
+    match endianness {
+        Endianness::Little => Ok(SET_ENDIANNESS_VALUE),
+        Endianness::Big => Ok(SET_ENDIANNESS_VALUE | SET_ENDIANNESS_BIG_ENDIAN_BIT),
+    }
+}
+
+fn encode_condition(condition: &Condition) -> u32 {
+    let condition = match condition {
+        Condition::Equal => EQUAL_CONDITION,
+        Condition::NotEqual => NOT_EQUAL_CONDITION,
+        Condition::CarrySet => CARRY_SET_CONDITION,
+        Condition::CarryClear => CARRY_CLEAR_CONDITION,
+        Condition::Negative => NEGATIVE_CONDITION,
+        Condition::Positive => POSITIVE_CONDITION,
+        Condition::Overflow => OVERFLOW_CONDITION,
+        Condition::NoOverflow => NO_OVERFLOW_CONDITION,
+        Condition::UnsignedHigher => UNSIGNED_HIGHER_CONDITION,
+        Condition::UnsignedLowerOrSame => UNSIGNED_LOWER_OR_SAME_CONDITION,
+        Condition::GreaterThanOrEqual => GREATER_THAN_OR_EQUAL_CONDITION,
+        Condition::LessThan => LESS_THAN_CONDITION,
+        Condition::GreaterThan => GREATER_THAN_CONDITION,
+        Condition::LessThanOrEqual => LESS_THAN_OR_EQUAL_CONDITION,
+        Condition::Always => ALWAYS_CONDITION,
+    };
+
+    condition as u32
+}
+
+fn encode_update_status_flag(flags: &UpdateStatusFlags) -> u32 {
+    match flags {
+        UpdateStatusFlags::UpdateStatusFlags => UPDATE_STATUS_BIT,
+        UpdateStatusFlags::DoNotUpdateStatusFlags => 0,
+    }
+}
+
+fn encode_register(register: Register) -> u32 {
+    u8::from(register) as u32
+}
+
+fn encode_read_write_arguments(opcode: u8, args: &ReadWriteDataArguments, flags: &UpdateStatusFlags) -> Result<u32, String> {
+    let (source_register, destination_register, operand) = match args {
+        ReadWriteDataArguments::Immediate(args) => (args.source_register, args.destination_register, encode_shifted_immediate(args.immediate, args.rotate)?),
+        ReadWriteDataArguments::Register(args) => (args.source_register, args.destination_register, encode_register_shift_arguments(args.operand_register, &args.shift_type, &args.shift_operand)?),
+    };
+
+    Ok((opcode as u32) << 21 | encode_update_status_flag(flags) | encode_register(source_register) << 16 | encode_register(destination_register) << 12 | operand)
+}
+
+// TST, TEQ, CMP and CMN only read a register, and always set the flags
+fn encode_read_arguments(opcode: u8, args: &DataArguments) -> Result<u32, String> {
+    let (register, operand) = encode_data_arguments(args)?;
+
+    Ok((opcode as u32) << 21 | UPDATE_STATUS_BIT | register << 16 | operand)
+}
+
+// MOV and MVN only write a register
+fn encode_write_arguments(opcode: u8, args: &DataArguments, flags: &UpdateStatusFlags) -> Result<u32, String> {
+    let (register, operand) = encode_data_arguments(args)?;
+
+    Ok((opcode as u32) << 21 | encode_update_status_flag(flags) | register << 12 | operand)
+}
+
+fn encode_data_arguments(args: &DataArguments) -> Result<(u32, u32), String> {
+    match args {
+        DataArguments::Immediate(args) => Ok((encode_register(args.register), encode_shifted_immediate(args.immediate, args.rotate)?)),
+        DataArguments::Register(args) => Ok((encode_register(args.register), encode_register_shift_arguments(args.operand_register, &args.shift_type, &args.shift_operand)?)),
+    }
+}
+
+// the instruction holds an 8-bit value and half the amount to rotate it right by. The rotation the instruction was decoded
+// with is kept if it still works; otherwise the smallest one that does is used, as GNU as does
+fn encode_shifted_immediate(immediate: u32, rotate: u8) -> Result<u32, String> {
+    let rotate = if rotate.is_multiple_of(2) && rotate < 32 && immediate.rotate_left(rotate as u32) <= 0xff {
+        rotate
+    } else {
+        get_immediate_rotation(immediate).ok_or_else(|| format!("Immediate {:#x} cannot be encoded as a rotated 8-bit value", immediate))?
+    };
+
+    Ok(IMMEDIATE_MODE_BIT | (rotate as u32) << 7 | immediate.rotate_left(rotate as u32))
+}
+
+fn get_immediate_rotation(immediate: u32) -> Option<u8> {
+    (0..32).step_by(2).find(|&rotate| immediate.rotate_left(rotate) <= 0xff).map(|rotate| rotate as u8)
+}
+
+fn encode_register_shift_arguments(operand_register: Register, shift_type: &ShiftType, shift_operand: &ShiftOperand) -> Result<u32, String> {
+    let shift = match (shift_type, shift_operand) {
+        (ShiftType::RotateRightExtended, _) => 0,
+        (ShiftType::RotateRight, ShiftOperand::Immediate(amount)) if *amount == u5::new(0) => return Err(String::from("ROR #0 cannot be encoded; it means RRX")),
+        (_, ShiftOperand::Immediate(amount)) => (u8::from(*amount) as u32) << 7,
+        (_, ShiftOperand::Register(register)) => encode_register(*register) << 8 | SHIFT_REGISTER_BIT,
+    };
+
+    Ok(shift | encode_shift_type(shift_type) | encode_register(operand_register))
+}
+
+fn encode_shift_type(shift_type: &ShiftType) -> u32 {
+    let shift_type = match shift_type {
+        ShiftType::LogicalShiftLeft => SHIFT_TYPE_LOGICAL_SHIFT_LEFT,
+        ShiftType::LogicalShiftRight => SHIFT_TYPE_LOGICAL_SHIFT_RIGHT,
+        ShiftType::ArithmeticShiftRight => SHIFT_TYPE_ARITHMETIC_SHIFT_RIGHT,
+        ShiftType::RotateRight | ShiftType::RotateRightExtended => SHIFT_TYPE_ROTATE_RIGHT,
+    };
+
+    shift_type as u32
+}
+
+fn encode_large_immediate_arguments(args: &LargeImmediateArguments) -> u32 {
+    let immediate = args.immediate as u32;
+
+    (immediate & 0xf000) << 4 | encode_register(args.register) << 12 | (immediate & 0x0fff)
+}
+
+fn encode_multiply(opcode: u8, flags: &UpdateStatusFlags, high_register: Register, low_register: Register, second_operand_register: Register, first_operand_register: Register) -> u32 {
+    MULTIPLY_VALUE
+        | (opcode as u32) << 21
+        | encode_update_status_flag(flags)
+        | encode_register(high_register) << 16
+        | encode_register(low_register) << 12
+        | encode_register(second_operand_register) << 8
+        | encode_register(first_operand_register)
+}
+
+fn encode_long_multiply(opcode: u8, flags: &UpdateStatusFlags, args: &LongMultiplyArguments) -> u32 {
+    encode_multiply(opcode, flags, args.destination_register_high, args.destination_register_low, args.second_operand_register, args.first_operand_register)
+}
+
+fn encode_divide_arguments(args: &DivideArguments) -> u32 {
+    encode_register(args.destination_register) << 16 | encode_register(args.divisor_register) << 8 | encode_register(args.dividend_register)
+}
+
+// the offset is relative to the branch itself; the instruction stores it in words, relative to PC + 8
+fn encode_branch(offset: i32, link_flag: &BranchLinkFlag) -> Result<u32, String> {
+    let adjusted_offset = offset as i64 - 8;
+
+    if adjusted_offset % 4 != 0 {
+        return Err(format!("Branch offset {} is not a multiple of 4", offset));
+    }
+
+    if !(-(1 << 25)..(1 << 25)).contains(&adjusted_offset) {
+        return Err(format!("Branch offset {} is out of range", offset));
+    }
+
+    let link = match link_flag {
+        BranchLinkFlag::LinkReturnAddress => BRANCH_LINK_BIT,
+        BranchLinkFlag::DoNotLinkReturnAddress => 0,
+    };
+
+    Ok(BRANCH_INSTRUCTION_CLASS | link | ((adjusted_offset >> 2) as u32 & 0x00ffffff))
+}
+
+// BKPT splits its 16-bit immediate into bits 19-8 and 3-0
+fn encode_breakpoint(immediate: u16) -> u32 {
+    let immediate = immediate as u32;
+
+    BREAKPOINT_VALUE | (immediate & 0xfff0) << 4 | (immediate & 0x000f)
+}
+
+fn encode_load(args: &LoadArguments) -> Result<u32, String> {
+    let common_arguments = &args.common_arguments;
+
+    match args.data_size {
+        LoadDataSize::Word => encode_regular_load_store(common_arguments, true, false),
+        LoadDataSize::Byte => encode_regular_load_store(common_arguments, true, true),
+        LoadDataSize::UnsignedHalfWord => encode_extra_load_store(common_arguments, true, 0b01),
+        LoadDataSize::SignedByte => encode_extra_load_store(common_arguments, true, 0b10),
+        LoadDataSize::SignedHalfWord => encode_extra_load_store(common_arguments, true, 0b11),
+        // LDRD and STRD live in the store half of the encoding space
+        LoadDataSize::DoubleWord => {
+            decoding::validate_doubleword_registers(common_arguments)?;
+            encode_extra_load_store(common_arguments, false, 0b10)
+        },
+    }
+}
+
+fn encode_store(args: &StoreArguments) -> Result<u32, String> {
+    let common_arguments = &args.common_arguments;
+
+    match args.data_size {
+        StoreDataSize::Word => encode_regular_load_store(common_arguments, false, false),
+        StoreDataSize::Byte => encode_regular_load_store(common_arguments, false, true),
+        StoreDataSize::HalfWord => encode_extra_load_store(common_arguments, false, 0b01),
+        StoreDataSize::DoubleWord => {
+            decoding::validate_doubleword_registers(common_arguments)?;
+            encode_extra_load_store(common_arguments, false, 0b11)
+        },
+    }
+}
+
+fn encode_regular_load_store(args: &LoadStoreArguments, load_operation: bool, data_size_is_byte: bool) -> Result<u32, String> {
+    let offset = match &args.offset {
+        LoadStoreOffset::Immediate(immediate) => u16::from(*immediate) as u32,
+        LoadStoreOffset::Register(offset) => {
+            REGISTER_OFFSET_BIT | encode_register_shift_arguments(offset.register, &offset.shift_type, &ShiftOperand::Immediate(offset.shift_operand))?
+        },
+    };
+    let byte = if data_size_is_byte { BYTE_BIT } else { 0 };
+
+    Ok(LOAD_STORE_IMMEDIATE_INSTRUCTION_CLASS | encode_load_store_arguments(args, load_operation) | byte | offset)
+}
+
+fn encode_extra_load_store(args: &LoadStoreArguments, load_operation: bool, data_size: u32) -> Result<u32, String> {
+    let offset = match &args.offset {
+        LoadStoreOffset::Immediate(immediate) => {
+            let immediate = u16::from(*immediate) as u32;
+            if immediate > 0xff {
+                return Err(format!("Offset {} is out of range; halfword, signed and doubleword transfers take 8-bit offsets", immediate));
+            }
+
+            EXTRA_LOAD_STORE_IMMEDIATE_BIT | (immediate & 0xf0) << 4 | (immediate & 0x0f)
+        },
+        LoadStoreOffset::Register(offset) => {
+            if !matches!(offset.shift_type, ShiftType::LogicalShiftLeft) || offset.shift_operand != u5::new(0) {
+                return Err(String::from("Halfword, signed and doubleword transfers cannot shift their offset register"));
+            }
+
+            encode_register(offset.register)
+        },
+    };
+
+    Ok(EXTRA_LOAD_STORE_VALUE | encode_load_store_arguments(args, load_operation) | data_size << 5 | offset)
+}
+
+// post-indexed transfers always write back, but the W bit stays clear for them; set, it selects the unprivileged variants
+fn encode_load_store_arguments(args: &LoadStoreArguments, load_operation: bool) -> u32 {
+    let (pre_indexed, write_back) = match (&args.indexing_type, &args.write_back) {
+        (LoadStoreIndexingType::PreIndexed, LoadStoreWriteBackFlag::WriteBack) => (PRE_INDEXED_BIT, WRITE_BACK_BIT),
+        (LoadStoreIndexingType::PreIndexed, LoadStoreWriteBackFlag::DoNotWriteBack) => (PRE_INDEXED_BIT, 0),
+        (LoadStoreIndexingType::PostIndexed, _) => (0, 0),
+    };
+    let positive = if let LoadStoreOffsetDirection::Positive = args.offset_direction { POSITIVE_OFFSET_BIT } else { 0 };
+    let load = if load_operation { LOAD_BIT } else { 0 };
+
+    pre_indexed | positive | write_back | load | encode_register(args.address_register) << 16 | encode_register(args.value_register) << 12
+}
+
+fn encode_block_transfer(args: &BlockTransferArguments, load_operation: bool) -> Result<u32, String> {
+    if args.register_list == 0 {
+        return Err(String::from("Block transfer with empty register list"));
+    }
+
+    let addressing_mode = match args.addressing_mode {
+        BlockTransferAddressingMode::IncrementAfter => POSITIVE_OFFSET_BIT,
+        BlockTransferAddressingMode::IncrementBefore => PRE_INDEXED_BIT | POSITIVE_OFFSET_BIT,
+        BlockTransferAddressingMode::DecrementAfter => 0,
+        BlockTransferAddressingMode::DecrementBefore => PRE_INDEXED_BIT,
+    };
+    let write_back = if let LoadStoreWriteBackFlag::WriteBack = args.write_back { WRITE_BACK_BIT } else { 0 };
+    let load = if load_operation { LOAD_BIT } else { 0 };
+
+    Ok(BLOCK_TRANSFER_INSTRUCTION_CLASS | addressing_mode | write_back | load | encode_register(args.address_register) << 16 | args.register_list as u32)
+}
+
+// one line of source: its labels, and the instruction or directive that follows them, if any
+struct Statement {
+    line_number: usize,
+    labels: Vec<String>,
+    mnemonic: String,               // lowercase; empty for a line with only labels
+    operands: String,
+}
+
+// numeric labels such as 1: can be defined any number of times; 1b refers to the closest one before, 1f to the closest
+// one after
+struct LocalLabel {
+    name: String,
+    index: usize,                   // the statement the label is on
+    address: u32,
+}
+
+// a word in a literal pool, holding the value of the =<expression> operand of an LDR
+struct Literal {
+    address: u32,
+    index: usize,                   // the LDR's statement
+}
+
+// assembling takes two passes: the first works out the address of every statement and label, the second encodes them
+struct Assembler {
+    address: u32,
+    end_address: u32,
+    addresses: Vec<u32>,            // the address of each statement
+    symbols: HashMap<String, u32>,  // labels and .equ constants
+    labels: Vec<Symbol>,
+    local_labels: Vec<LocalLabel>,
+    literals: Vec<Literal>,
+}
+
+impl Assembler {
+    fn create(address: u32) -> Assembler {
+        Assembler {
+            address,
+            end_address: address,
+            addresses: Vec::new(),
+            symbols: HashMap::new(),
+            labels: Vec::new(),
+            local_labels: Vec::new(),
+            literals: Vec::new(),
+        }
+    }
+
+    fn lay_out(&mut self, statements: &[Statement]) -> Result<(), String> {
+        let mut address = self.address;
+        let mut pending_literals = Vec::new();
+
+        for (index, statement) in statements.iter().enumerate() {
+            address = self.lay_out_statement(statement, index, address, &mut pending_literals)
+                .map_err(|message| format!("line {}: {}", statement.line_number, message))?;
+        }
+
+        // literals that weren't placed by a .ltorg go at the end of the program
+        self.end_address = self.place_literals(address, &mut pending_literals);
+
+        Ok(())
+    }
+
+    fn lay_out_statement(&mut self, statement: &Statement, index: usize, address: u32, pending_literals: &mut Vec<usize>) -> Result<u32, String> {
+        for label in statement.labels.iter() {
+            self.define_label(label, index, address)?;
+        }
+
+        self.addresses.push(address);
+
+        let operands = split_operands(&statement.operands);
+        let size = match statement.mnemonic.as_str() {
+            "" => 0,
+            ".word" | ".long" | ".4byte" => 4 * operands.len() as u32,
+            ".short" | ".hword" | ".2byte" => 2 * operands.len() as u32,
+            ".byte" => operands.len() as u32,
+            ".ascii" => parse_strings(&operands)?.iter().map(|s| s.len() as u32).sum(),
+            ".asciz" | ".string" => parse_strings(&operands)?.iter().map(|s| s.len() as u32 + 1).sum(),
+            ".space" | ".skip" => self.evaluate(get_operand(&operands, 0)?, index)?,
+            ".align" | ".p2align" => {
+                let exponent = if operands.is_empty() { DEFAULT_ALIGNMENT_EXPONENT } else { self.evaluate(&operands[0], index)? };
+                if exponent > MAXIMUM_ALIGNMENT_EXPONENT {
+                    return Err(format!("Alignment 2^{} is too large", exponent));
+                }
+
+                get_padding(address, 1 << exponent)
+            },
+            ".balign" => {
+                let alignment = self.evaluate(get_operand(&operands, 0)?, index)?;
+                if !alignment.is_power_of_two() || alignment > 1 << MAXIMUM_ALIGNMENT_EXPONENT {
+                    return Err(format!("Alignment {} is not a power of two up to 2^{}", alignment, MAXIMUM_ALIGNMENT_EXPONENT));
+                }
+
+                get_padding(address, alignment)
+            },
+            ".equ" | ".set" => {
+                let value = self.evaluate(get_operand(&operands, 1)?, index)?;
+                self.define_symbol(get_operand(&operands, 0)?, value)?;
+                0
+            },
+            ".ltorg" | ".pool" => return Ok(self.place_literals(address, pending_literals)),
+            ".code" if operands.len() == 1 && operands[0] == "32" => 0,
+            mnemonic if IGNORED_DIRECTIVES.contains(&mnemonic) => 0,
+            mnemonic if mnemonic.starts_with('.') => return Err(format!("Unsupported directive {}", mnemonic)),
+            mnemonic => {
+                if !address.is_multiple_of(4) {
+                    return Err(format!("Instruction at unaligned address {:#x}; use .align", address));
+                }
+
+                if mnemonic.starts_with("ldr") && operands.last().is_some_and(|o| o.starts_with('=')) {
+                    pending_literals.push(index);
+                }
+
+                4
+            },
+        };
+
+        address.checked_add(size).ok_or_else(|| String::from("Program does not fit in the address space"))
+    }
+
+    fn place_literals(&mut self, address: u32, pending_literals: &mut Vec<usize>) -> u32 {
+        if pending_literals.is_empty() {
+            return address;
+        }
+
+        let mut address = address.wrapping_add(get_padding(address, 4));
+
+        for index in pending_literals.drain(..) {
+            self.literals.push(Literal { address, index });
+            address = address.wrapping_add(4);
+        }
+
+        address
+    }
+
+    fn define_label(&mut self, name: &str, index: usize, address: u32) -> Result<(), String> {
+        if name.bytes().all(|b| b.is_ascii_digit()) {
+            self.local_labels.push(LocalLabel { name: String::from(name), index, address });
+            return Ok(());
+        }
+
+        self.define_symbol(name, address)?;
+        self.labels.push(Symbol { name: String::from(name), address, size: 0 });
+
+        Ok(())
+    }
+
+    fn define_symbol(&mut self, name: &str, value: u32) -> Result<(), String> {
+        if !is_label_name(name) {
+            return Err(format!("Invalid symbol name {}", name));
+        }
+
+        if self.symbols.insert(String::from(name), value).is_some() {
+            return Err(format!("Symbol {} is already defined", name));
+        }
+
+        Ok(())
+    }
+
+    fn emit(&self, statements: &[Statement]) -> Result<Vec<u8>, String> {
+        let mut data = vec![0u8; self.end_address.wrapping_sub(self.address) as usize];
+
+        for (index, statement) in statements.iter().enumerate() {
+            let offset = self.addresses[index].wrapping_sub(self.address) as usize;
+
+            self.emit_statement(statement, index, &mut data[offset..])
+                .map_err(|message| format!("line {}: {}", statement.line_number, message))?;
+        }
+
+        for literal in self.literals.iter() {
+            let statement = &statements[literal.index];
+            let operands = split_operands(&statement.operands);
+            let expression = operands.last().map(|o| &o[1..]).unwrap_or_default();
+            let value = self.evaluate(expression, literal.index)
+                .map_err(|message| format!("line {}: {}", statement.line_number, message))?;
+            let offset = literal.address.wrapping_sub(self.address) as usize;
+
+            data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+        }
+
+        Ok(data)
+    }
+
+    fn emit_statement(&self, statement: &Statement, index: usize, output: &mut [u8]) -> Result<(), String> {
+        let operands = split_operands(&statement.operands);
+
+        match statement.mnemonic.as_str() {
+            ".word" | ".long" | ".4byte" => {
+                for (operand, output) in operands.iter().zip(output.chunks_mut(4)) {
+                    output.copy_from_slice(&self.evaluate(operand, index)?.to_le_bytes());
+                }
+            },
+            ".short" | ".hword" | ".2byte" => {
+                for (operand, output) in operands.iter().zip(output.chunks_mut(2)) {
+                    let value = self.evaluate_sized(operand, 16, index)?;
+                    output.copy_from_slice(&(value as u16).to_le_bytes());
+                }
+            },
+            ".byte" => {
+                for (operand, output) in operands.iter().zip(output.iter_mut()) {
+                    *output = self.evaluate_sized(operand, 8, index)? as u8;
+                }
+            },
+            ".ascii" | ".asciz" | ".string" => {
+                // the output is zero-filled, so skipping a byte after each string terminates it
+                let terminator_size = if statement.mnemonic == ".ascii" { 0 } else { 1 };
+                let mut offset = 0;
+
+                for string in parse_strings(&operands)? {
+                    output[offset..offset + string.len()].copy_from_slice(&string);
+                    offset += string.len() + terminator_size;
+                }
+            },
+            ".space" | ".skip" if operands.len() > 1 => {
+                let size = self.evaluate(&operands[0], index)? as usize;
+                let fill = self.evaluate_sized(&operands[1], 8, index)? as u8;
+
+                output[..size].iter_mut().for_each(|b| *b = fill);
+            },
+            "" => {},
+            mnemonic if mnemonic.starts_with('.') => {},
+            _ => {
+                let instruction = self.parse_instruction(statement, &operands, index)?;
+                output[..4].copy_from_slice(&encode_instruction(&instruction)?.to_le_bytes());
+            },
+        }
+
+        Ok(())
+    }
+
+    fn parse_instruction(&self, statement: &Statement, operands: &[String], index: usize) -> Result<Instruction, String> {
+        let (mnemonic, condition, flags) = parse_mnemonic(&statement.mnemonic)?;
+
+        let data = match mnemonic {
+            Mnemonic::Add | Mnemonic::AddWithCarry | Mnemonic::And | Mnemonic::BitClear | Mnemonic::ExclusiveOr | Mnemonic::Or
+                | Mnemonic::ReverseSubtract | Mnemonic::ReverseSubtractWithCarry | Mnemonic::Subtract | Mnemonic::SubtractWithCarry => {
+                self.parse_read_write_instruction(mnemonic, flags, operands, index)?
+            },
+            Mnemonic::Move | Mnemonic::MoveNot => self.parse_move_instruction(mnemonic, flags, operands, index)?,
+            Mnemonic::Shift(shift_name) => self.parse_shift_instruction(shift_name, flags, operands, index)?,
+            Mnemonic::Compare | Mnemonic::CompareNegative | Mnemonic::Test | Mnemonic::TestEquivalence => self.parse_compare_instruction(mnemonic, operands, index)?,
+            Mnemonic::MoveHalfWord | Mnemonic::MoveHalfWordTop => {
+                let (register, immediate) = match operands {
+                    [register, immediate] => (parse_register(register)?, self.parse_large_immediate(immediate, index)?),
+                    _ => return Err(String::from("Expected a register and an immediate")),
+                };
+                let args = LargeImmediateArguments { register, immediate };
+
+                if let Mnemonic::MoveHalfWord = mnemonic { InstructionData::MoveHalfWord(args) } else { InstructionData::MoveHalfWordTop(args) }
+            },
+            Mnemonic::Multiply | Mnemonic::MultiplyAccumulate | Mnemonic::MultiplySubtract => parse_multiply_instruction(mnemonic, flags, operands)?,
+            Mnemonic::UnsignedMultiplyLong | Mnemonic::UnsignedMultiplyAccumulateLong | Mnemonic::UnsignedMultiplyAccumulateAccumulateLong
+                | Mnemonic::SignedMultiplyLong | Mnemonic::SignedMultiplyAccumulateLong => parse_long_multiply_instruction(mnemonic, flags, operands)?,
+            Mnemonic::SignedDivide | Mnemonic::UnsignedDivide => {
+                // the destination may be left out, in which case it's the dividend
+                let args = match operands {
+                    [dividend, divisor] => DivideArguments { destination_register: parse_register(dividend)?, dividend_register: parse_register(dividend)?, divisor_register: parse_register(divisor)? },
+                    [destination, dividend, divisor] => DivideArguments { destination_register: parse_register(destination)?, dividend_register: parse_register(dividend)?, divisor_register: parse_register(divisor)? },
+                    _ => return Err(String::from("Expected a destination, a dividend and a divisor register")),
+                };
+
+                if let Mnemonic::SignedDivide = mnemonic { InstructionData::SignedDivide(args) } else { InstructionData::UnsignedDivide(args) }
+            },
+            Mnemonic::Branch | Mnemonic::BranchLink => {
+                let target = self.evaluate(get_single_operand(operands)?, index)?;
+                let offset = target.wrapping_sub(self.addresses[index]) as i32;
+                let link_flag = if let Mnemonic::BranchLink = mnemonic { BranchLinkFlag::LinkReturnAddress } else { BranchLinkFlag::DoNotLinkReturnAddress };
+
+                InstructionData::Branch(offset, link_flag)
+            },
+            Mnemonic::BranchExchange => InstructionData::BranchExchange(parse_register(get_single_operand(operands)?)?),
+            Mnemonic::Load(size) | Mnemonic::Store(size) => self.parse_load_store_instruction(mnemonic, size, operands, index)?,
+            Mnemonic::LoadMultiple { before, increment } | Mnemonic::StoreMultiple { before, increment } => {
+                let (address_register, register_list) = match operands {
+                    [address_register, register_list] => (address_register, parse_register_list(register_list)?),
+                    _ => return Err(String::from("Expected a base register and a register list")),
+                };
+                let (address_register, write_back) = match address_register.strip_suffix('!') {
+                    Some(register) => (parse_register(register)?, LoadStoreWriteBackFlag::WriteBack),
+                    None => (parse_register(address_register)?, LoadStoreWriteBackFlag::DoNotWriteBack),
+                };
+                let args = BlockTransferArguments {
+                    addressing_mode: get_addressing_mode(before, increment),
+                    write_back,
+                    address_register,
+                    register_list,
+                };
+
+                if let Mnemonic::LoadMultiple { .. } = mnemonic { InstructionData::LoadMultiple(args) } else { InstructionData::StoreMultiple(args) }
+            },
+            Mnemonic::Push | Mnemonic::Pop => parse_stack_instruction(mnemonic, operands)?,
+            Mnemonic::Address => self.parse_address_instruction(operands, index)?,
+            Mnemonic::SupervisorCall => {
+                let immediate = self.parse_immediate(get_single_operand(operands)?, index)?;
+                if immediate > 0x00ffffff {
+                    return Err(format!("Supervisor call number {:#x} does not fit in 24 bits", immediate));
+                }
+
+                InstructionData::SupervisorCall(u24::new(immediate))
+            },
+            Mnemonic::Breakpoint => {
+                let immediate = if operands.is_empty() { 0 } else { self.parse_immediate(get_single_operand(operands)?, index)? };
+                if immediate > 0xffff {
+                    return Err(format!("Breakpoint number {:#x} does not fit in 16 bits", immediate));
+                }
+
+                InstructionData::Breakpoint(immediate as u16)
+            },
+            Mnemonic::MoveStatusToRegister => match operands {
+                [register, status_register] if matches!(status_register.to_lowercase().as_str(), "apsr" | "cpsr") => InstructionData::MoveStatusToRegister(parse_register(register)?),
+                _ => return Err(String::from("Expected a register and APSR")),
+            },
+            Mnemonic::SetEndianness => match get_single_operand(operands)?.to_lowercase().as_str() {
+                "be" => InstructionData::SetEndianness(Endianness::Big),
+                "le" => InstructionData::SetEndianness(Endianness::Little),
+                _ => return Err(String::from("Expected BE or LE")),
+            },
+            // MOV r0, r0 is the traditional ARM no-op
+            Mnemonic::NoOperation if operands.is_empty() => InstructionData::Move(DataArguments::Register(RegisterDataArguments {
+                register: u4::new(0),
+                operand_register: u4::new(0),
+                shift_type: ShiftType::LogicalShiftLeft,
+                shift_operand: ShiftOperand::Immediate(u5::new(0)),
+            }), UpdateStatusFlags::DoNotUpdateStatusFlags),
+            Mnemonic::NoOperation => return Err(String::from("NOP does not take operands")),
+        };
+
+        Ok((condition, data))
+    }
+
+    // the first operand may be left out, in which case it's the destination: ADD r0, #1 is ADD r0, r0, #1
+    fn parse_read_write_instruction(&self, mnemonic: Mnemonic, flags: UpdateStatusFlags, operands: &[String], index: usize) -> Result<InstructionData, String> {
+        let (destination, source, operand) = match operands {
+            [destination, _] => (destination, destination, &operands[1..]),
+            [destination, _, shift] if is_shift(shift) => (destination, destination, &operands[1..]),
+            [destination, source, _, ..] => (destination, source, &operands[2..]),
+            _ => return Err(String::from("Expected a destination register and an operand")),
+        };
+        let destination_register = parse_register(destination)?;
+        let source_register = parse_register(source)?;
+
+        let (mnemonic, args) = match self.parse_operand(operand, index)? {
+            Operand::Immediate(value) => {
+                let (mnemonic, value) = get_encodable_alternative(mnemonic, value);
+                let (immediate, carry, rotate) = create_shifted_immediate(value)?;
+
+                (mnemonic, ReadWriteDataArguments::Immediate(ReadWriteImmediateDataArguments { source_register, destination_register, immediate, carry, rotate }))
+            },
+            Operand::Register(operand_register, shift_type, shift_operand) => {
+                (mnemonic, ReadWriteDataArguments::Register(ReadWriteRegisterDataArguments { source_register, destination_register, operand_register, shift_type, shift_operand }))
+            },
+        };
+
+        let data = match mnemonic {
+            Mnemonic::Add => InstructionData::Add(args, flags),
+            Mnemonic::AddWithCarry => InstructionData::AddWithCarry(args, flags),
+            Mnemonic::And => InstructionData::And(args, flags),
+            Mnemonic::BitClear => InstructionData::BitClear(args, flags),
+            Mnemonic::ExclusiveOr => InstructionData::ExclusiveOr(args, flags),
+            Mnemonic::Or => InstructionData::Or(args, flags),
+            Mnemonic::ReverseSubtract => InstructionData::ReverseSubtract(args, flags),
+            Mnemonic::ReverseSubtractWithCarry => InstructionData::ReverseSubtractWithCarry(args, flags),
+            Mnemonic::Subtract => InstructionData::Subtract(args, flags),
+            Mnemonic::SubtractWithCarry => InstructionData::SubtractWithCarry(args, flags),
+            _ => unreachable!("{:?} is not a data processing instruction", mnemonic),
+        };
+
+        Ok(data)
+    }
+
+    fn parse_move_instruction(&self, mnemonic: Mnemonic, flags: UpdateStatusFlags, operands: &[String], index: usize) -> Result<InstructionData, String> {
+        let (register, operand) = match operands.split_first() {
+            Some((register, operand)) if !operand.is_empty() => (parse_register(register)?, operand),
+            _ => return Err(String::from("Expected a destination register and an operand")),
+        };
+
+        let (mnemonic, args) = match self.parse_operand(operand, index)? {
+            Operand::Immediate(value) => {
+                let (mnemonic, value) = get_encodable_alternative(mnemonic, value);
+
+                // like GNU as, fall back to MOVW for a 16-bit value that can't be encoded otherwise
+                if get_immediate_rotation(value).is_none() && value <= 0xffff && matches!((mnemonic, &flags), (Mnemonic::Move, UpdateStatusFlags::DoNotUpdateStatusFlags)) {
+                    return Ok(InstructionData::MoveHalfWord(LargeImmediateArguments { register, immediate: value as u16 }));
+                }
+
+                let (immediate, carry, rotate) = create_shifted_immediate(value)?;
+                (mnemonic, DataArguments::Immediate(ImmediateDataArguments { register, immediate, carry, rotate }))
+            },
+            Operand::Register(operand_register, shift_type, shift_operand) => {
+                (mnemonic, DataArguments::Register(RegisterDataArguments { register, operand_register, shift_type, shift_operand }))
+            },
+        };
+
+        if let Mnemonic::Move = mnemonic { Ok(InstructionData::Move(args, flags)) } else { Ok(InstructionData::MoveNot(args, flags)) }
+    }
+
+    // LSL, LSR, ASR, ROR and RRX are MOVs with a shifted register; LSL r0, #1 shifts r0 itself
+    fn parse_shift_instruction(&self, shift_name: &str, flags: UpdateStatusFlags, operands: &[String], index: usize) -> Result<InstructionData, String> {
+        let (register, operand_register, amount) = match (shift_name, operands) {
+            ("rrx", [register, operand_register]) => (register, operand_register, None),
+            (_, [register, amount]) => (register, register, Some(amount.as_str())),
+            (_, [register, operand_register, amount]) => (register, operand_register, Some(amount.as_str())),
+            _ => return Err(String::from("Expected a destination register, an operand register and a shift amount")),
+        };
+        let (shift_type, shift_operand) = self.create_shift(shift_name, amount, index)?;
+
+        Ok(InstructionData::Move(DataArguments::Register(RegisterDataArguments {
+            register: parse_register(register)?,
+            operand_register: parse_register(operand_register)?,
+            shift_type,
+            shift_operand,
+        }), flags))
+    }
+
+    fn parse_compare_instruction(&self, mnemonic: Mnemonic, operands: &[String], index: usize) -> Result<InstructionData, String> {
+        let (register, operand) = match operands.split_first() {
+            Some((register, operand)) if !operand.is_empty() => (parse_register(register)?, operand),
+            _ => return Err(String::from("Expected a register and an operand")),
+        };
+
+        let (mnemonic, args) = match self.parse_operand(operand, index)? {
+            Operand::Immediate(value) => {
+                let (mnemonic, value) = get_encodable_alternative(mnemonic, value);
+                let (immediate, carry, rotate) = create_shifted_immediate(value)?;
+
+                (mnemonic, DataArguments::Immediate(ImmediateDataArguments { register, immediate, carry, rotate }))
+            },
+            Operand::Register(operand_register, shift_type, shift_operand) => {
+                (mnemonic, DataArguments::Register(RegisterDataArguments { register, operand_register, shift_type, shift_operand }))
+            },
+        };
+
+        let data = match mnemonic {
+            Mnemonic::Compare => InstructionData::Compare(args),
+            Mnemonic::CompareNegative => InstructionData::CompareNegative(args),
+            Mnemonic::Test => InstructionData::Test(args),
+            Mnemonic::TestEquivalence => InstructionData::TestEquivalence(args),
+            _ => unreachable!("{:?} is not a compare instruction", mnemonic),
+        };
+
+        Ok(data)
+    }
+
+    fn parse_load_store_instruction(&self, mnemonic: Mnemonic, size: TransferSize, operands: &[String], index: usize) -> Result<InstructionData, String> {
+        let (value_register, address) = match operands.split_first() {
+            Some((register, address)) => (parse_register(register)?, address),
+            None => return Err(String::from("Expected a register and an address")),
+        };
+
+        // LDRD and STRD may name the second register of the pair, which is always the one after the first
+        let address = match (size, address) {
+            (TransferSize::DoubleWord, [second_register, address @ ..]) if is_register(second_register) => {
+                if u8::from(parse_register(second_register)?) != u8::from(value_register) + 1 {
+                    return Err(String::from("The second register of a doubleword transfer must follow the first"));
+                }
+
+                address
+            },
+            _ => address,
+        };
+        let common_arguments = self.parse_address(value_register, address, index)?;
+
+        if let Mnemonic::Load(_) = mnemonic {
+            let data_size = match size {
+                TransferSize::Word => LoadDataSize::Word,
+                TransferSize::Byte => LoadDataSize::Byte,
+                TransferSize::HalfWord => LoadDataSize::UnsignedHalfWord,
+                TransferSize::SignedByte => LoadDataSize::SignedByte,
+                TransferSize::SignedHalfWord => LoadDataSize::SignedHalfWord,
+                TransferSize::DoubleWord => LoadDataSize::DoubleWord,
+            };
+
+            Ok(InstructionData::Load(LoadArguments { data_size, common_arguments }))
+        } else {
+            let data_size = match size {
+                TransferSize::Word => StoreDataSize::Word,
+                TransferSize::Byte => StoreDataSize::Byte,
+                TransferSize::HalfWord => StoreDataSize::HalfWord,
+                TransferSize::DoubleWord => StoreDataSize::DoubleWord,
+                TransferSize::SignedByte | TransferSize::SignedHalfWord => return Err(String::from("Signed stores do not exist")),
+            };
+
+            Ok(InstructionData::Store(StoreArguments { data_size, common_arguments }))
+        }
+    }
+
+    // [Rn], [Rn, offset], [Rn, offset]! and [Rn], offset; a label or =<expression> becomes a PC-relative load
+    fn parse_address(&self, value_register: Register, operands: &[String], index: usize) -> Result<LoadStoreArguments, String> {
+        let (address, post_index_offset) = match operands.split_first() {
+            Some((address, _)) if address.starts_with('=') => {
+                let literal = self.literals.iter().find(|l| l.index == index)
+                    .ok_or_else(|| String::from("Only LDR can load a =<value> literal"))?;
+
+                return self.create_program_counter_relative_arguments(value_register, literal.address, index);
+            },
+            Some((address, post_index_offset)) if address.starts_with('[') => (address, post_index_offset),
+            Some((address, [])) => {
+                let target = self.evaluate(address, index)?;
+
+                return self.create_program_counter_relative_arguments(value_register, target, index);
+            },
+            _ => return Err(String::from("Expected an address")),
+        };
+
+        let (address, write_back) = match address.strip_suffix('!') {
+            Some(address) => (address.trim_end(), LoadStoreWriteBackFlag::WriteBack),
+            None => (address.as_str(), LoadStoreWriteBackFlag::DoNotWriteBack),
+        };
+        let inner = address.strip_prefix('[').and_then(|a| a.strip_suffix(']'))
+            .ok_or_else(|| format!("Invalid address {}", address))?;
+        let inner = split_operands(inner);
+        let (address_register, offset) = match inner.split_first() {
+            Some((register, offset)) => (parse_register(register)?, offset),
+            None => return Err(String::from("Expected a base register")),
+        };
+
+        if post_index_offset.is_empty() {
+            let (offset_direction, offset) = if offset.is_empty() { (LoadStoreOffsetDirection::Positive, LoadStoreOffset::Immediate(u12::new(0))) } else { self.parse_offset(offset, index)? };
+
+            return Ok(LoadStoreArguments {
+                indexing_type: LoadStoreIndexingType::PreIndexed,
+                write_back,
+                offset_direction,
+                value_register,
+                address_register,
+                offset,
+            });
+        }
+
+        if !offset.is_empty() || matches!(write_back, LoadStoreWriteBackFlag::WriteBack) {
+            return Err(String::from("A post-indexed address takes its offset after the brackets"));
+        }
+
+        // post-indexed transfers always write back, like the decoder reports them
+        let (offset_direction, offset) = self.parse_offset(post_index_offset, index)?;
+
+        Ok(LoadStoreArguments {
+            indexing_type: LoadStoreIndexingType::PostIndexed,
+            write_back: LoadStoreWriteBackFlag::WriteBack,
+            offset_direction,
+            value_register,
+            address_register,
+            offset,
+        })
+    }
+
+    // #<immediate>, or a register with an optional sign and shift
+    fn parse_offset(&self, operands: &[String], index: usize) -> Result<(LoadStoreOffsetDirection, LoadStoreOffset), String> {
+        let (offset, shift) = match operands {
+            [offset] => (offset, None),
+            [offset, shift] => (offset, Some(shift)),
+            _ => return Err(String::from("Too many offset operands")),
+        };
+
+        let (negative, register) = match offset.strip_prefix('-') {
+            Some(register) => (true, register),
+            None => (false, offset.strip_prefix('+').unwrap_or(offset)),
+        };
+
+        if let Ok(register) = parse_register(register) {
+            let (shift_type, shift_operand) = match shift {
+                Some(shift) => self.parse_shift(shift, index)?,
+                None => (ShiftType::LogicalShiftLeft, ShiftOperand::Immediate(u5::new(0))),
+            };
+            let shift_operand = match shift_operand {
+                ShiftOperand::Immediate(amount) => amount,
+                ShiftOperand::Register(_) => return Err(String::from("An offset register cannot be shifted by a register")),
+            };
+            let offset_direction = if negative { LoadStoreOffsetDirection::Negative } else { LoadStoreOffsetDirection::Positive };
+
+            return Ok((offset_direction, LoadStoreOffset::Register(LoadStoreRegisterOffset { register, shift_type, shift_operand })));
+        }
+
+        if shift.is_some() {
+            return Err(String::from("An immediate offset cannot be shifted"));
+        }
+
+        let offset = self.parse_immediate(offset, index)? as i32;
+        let offset_direction = if offset < 0 { LoadStoreOffsetDirection::Negative } else { LoadStoreOffsetDirection::Positive };
+
+        Ok((offset_direction, LoadStoreOffset::Immediate(create_offset_immediate(offset as i64)?)))
+    }
+
+    fn create_program_counter_relative_arguments(&self, value_register: Register, target: u32, index: usize) -> Result<LoadStoreArguments, String> {
+        // PC reads as the address of the instruction plus 8
+        let offset = target as i64 - (self.addresses[index] as i64 + 8);
+        let offset_direction = if offset < 0 { LoadStoreOffsetDirection::Negative } else { LoadStoreOffsetDirection::Positive };
+
+        Ok(LoadStoreArguments {
+            indexing_type: LoadStoreIndexingType::PreIndexed,
+            write_back: LoadStoreWriteBackFlag::DoNotWriteBack,
+            offset_direction,
+            value_register,
+            address_register: u4::new(CpuContext::get_program_counter_register()),
+            offset: LoadStoreOffset::Immediate(create_offset_immediate(offset).map_err(|_| format!("Address {:#x} is out of range", target))?),
+        })
+    }
+
+    // ADR is an ADD or SUB of an immediate to PC
+    fn parse_address_instruction(&self, operands: &[String], index: usize) -> Result<InstructionData, String> {
+        let (destination_register, target) = match operands {
+            [register, target] => (parse_register(register)?, self.evaluate(target, index)?),
+            _ => return Err(String::from("Expected a register and an address")),
+        };
+        let offset = target as i64 - (self.addresses[index] as i64 + 8);
+        let (immediate, carry, rotate) = create_shifted_immediate(offset.unsigned_abs() as u32)
+            .map_err(|_| format!("Address {:#x} is out of range for ADR", target))?;
+        let args = ReadWriteDataArguments::Immediate(ReadWriteImmediateDataArguments {
+            source_register: u4::new(CpuContext::get_program_counter_register()),
+            destination_register,
+            immediate,
+            carry,
+            rotate,
+        });
+
+        if offset < 0 {
+            Ok(InstructionData::Subtract(args, UpdateStatusFlags::DoNotUpdateStatusFlags))
+        } else {
+            Ok(InstructionData::Add(args, UpdateStatusFlags::DoNotUpdateStatusFlags))
+        }
+    }
+
+    // #<immediate>, a register, or a register with a shift
+    fn parse_operand(&self, operands: &[String], index: usize) -> Result<Operand, String> {
+        match operands {
+            [operand] if !is_register(operand) => Ok(Operand::Immediate(self.parse_immediate(operand, index)?)),
+            [register] => Ok(Operand::Register(parse_register(register)?, ShiftType::LogicalShiftLeft, ShiftOperand::Immediate(u5::new(0)))),
+            [register, shift] => {
+                let (shift_type, shift_operand) = self.parse_shift(shift, index)?;
+                Ok(Operand::Register(parse_register(register)?, shift_type, shift_operand))
+            },
+            _ => Err(String::from("Too many operands")),
+        }
+    }
+
+    fn parse_shift(&self, text: &str, index: usize) -> Result<(ShiftType, ShiftOperand), String> {
+        let (name, amount) = match text.find(char::is_whitespace) {
+            Some(position) => (&text[..position], Some(text[position..].trim())),
+            None => (text, None),
+        };
+
+        self.create_shift(&name.to_lowercase(), amount, index)
+    }
+
+    // LSR and ASR encode a shift by 32 as a shift by 0; a shift of any type by 0 is LSL #0
+    fn create_shift(&self, name: &str, amount: Option<&str>, index: usize) -> Result<(ShiftType, ShiftOperand), String> {
+        let shift_type = match (name, amount) {
+            ("lsl", _) | ("asl", _) => ShiftType::LogicalShiftLeft,
+            ("lsr", _) => ShiftType::LogicalShiftRight,
+            ("asr", _) => ShiftType::ArithmeticShiftRight,
+            ("ror", _) => ShiftType::RotateRight,
+            ("rrx", None) => return Ok((ShiftType::RotateRightExtended, ShiftOperand::Immediate(u5::new(0)))),
+            ("rrx", Some(_)) => return Err(String::from("RRX does not take a shift amount")),
+            _ => return Err(format!("Unknown shift {}", name)),
+        };
+        let amount = amount.ok_or_else(|| format!("Missing shift amount for {}", name.to_uppercase()))?;
+
+        if let Ok(register) = parse_register(amount) {
+            return Ok((shift_type, ShiftOperand::Register(register)));
+        }
+
+        match (shift_type, self.parse_immediate(amount, index)?) {
+            (_, 0) => Ok((ShiftType::LogicalShiftLeft, ShiftOperand::Immediate(u5::new(0)))),
+            (shift_type, amount @ 1..=31) => Ok((shift_type, ShiftOperand::Immediate(u5::new(amount as u8)))),
+            (shift_type @ ShiftType::LogicalShiftRight, 32) | (shift_type @ ShiftType::ArithmeticShiftRight, 32) => Ok((shift_type, ShiftOperand::Immediate(u5::new(0)))),
+            (_, amount) => Err(format!("Shift amount {} is out of range", amount)),
+        }
+    }
+
+    fn parse_immediate(&self, text: &str, index: usize) -> Result<u32, String> {
+        self.evaluate(text.strip_prefix('#').unwrap_or(text), index)
+    }
+
+    // MOVW and MOVT take a 16-bit value, or :lower16: or :upper16: of a 32-bit one
+    fn parse_large_immediate(&self, text: &str, index: usize) -> Result<u16, String> {
+        let text = text.strip_prefix('#').unwrap_or(text).trim_start();
+
+        if let Some(expression) = text.strip_prefix(":lower16:") {
+            return Ok(self.evaluate(expression, index)? as u16);
+        }
+
+        if let Some(expression) = text.strip_prefix(":upper16:") {
+            return Ok((self.evaluate(expression, index)? >> 16) as u16);
+        }
+
+        let immediate = self.evaluate(text, index)?;
+        if immediate > 0xffff {
+            return Err(format!("Immediate {:#x} does not fit in 16 bits", immediate));
+        }
+
+        Ok(immediate as u16)
+    }
+
+    // a value for a directive that emits fewer than 32 bits, which may be given signed or unsigned
+    fn evaluate_sized(&self, expression: &str, bits: u32, index: usize) -> Result<u32, String> {
+        let value = self.evaluate(expression, index)?;
+        let limit = 1u32 << bits;
+
+        if value >= limit && value < 0u32.wrapping_sub(limit / 2) {
+            return Err(format!("Value {:#x} does not fit in {} bits", value, bits));
+        }
+
+        Ok(value)
+    }
+
+    fn evaluate(&self, expression: &str, index: usize) -> Result<u32, String> {
+        let mut parser = ExpressionParser {
+            assembler: self,
+            characters: expression.chars().collect(),
+            position: 0,
+            index,
+        };
+
+        parser.evaluate()
+    }
+
+    fn get_local_label(&self, name: &str, forward: bool, index: usize) -> Result<u32, String> {
+        let label = if forward {
+            self.local_labels.iter().find(|l| l.name == name && l.index > index)
+        } else {
+            self.local_labels.iter().rev().find(|l| l.name == name && l.index <= index)
+        };
+
+        label.map(|l| l.address).ok_or_else(|| format!("Undefined local label {}{}", name, if forward { 'f' } else { 'b' }))
+    }
+}
+
+// integer expressions with C operators and precedence; symbols are labels and .equ constants, 1b and 1f are local labels
+// and . is the address of the current statement
+struct ExpressionParser<'a> {
+    assembler: &'a Assembler,
+    characters: Vec<char>,
+    position: usize,
+    index: usize,
+}
+
+impl<'a> ExpressionParser<'a> {
+    fn evaluate(&mut self) -> Result<u32, String> {
+        let value = self.parse_binary(0)?;
+
+        match self.peek() {
+            Some(c) => Err(format!("Unexpected '{}' in expression", c)),
+            None => Ok(value),
+        }
+    }
+
+    fn parse_binary(&mut self, level: usize) -> Result<u32, String> {
+        if level == BINARY_OPERATORS.len() {
+            return self.parse_unary();
+        }
+
+        let mut value = self.parse_binary(level + 1)?;
+
+        while let Some(operator) = self.match_operator(BINARY_OPERATORS[level]) {
+            let right = self.parse_binary(level + 1)?;
+
+            value = match operator {
+                "|" => value | right,
+                "^" => value ^ right,
+                "&" => value & right,
+                "<<" => value.checked_shl(right).unwrap_or(0),
+                ">>" => value.checked_shr(right).unwrap_or(0),
+                "+" => value.wrapping_add(right),
+                "-" => value.wrapping_sub(right),
+                "*" => value.wrapping_mul(right),
+                "/" => (value as i32).checked_div(right as i32).ok_or("Division by zero")? as u32,
+                "%" => (value as i32).checked_rem(right as i32).ok_or("Division by zero")? as u32,
+                _ => unreachable!("Unknown operator {}", operator),
+            };
+        }
+
+        Ok(value)
+    }
+
+    fn parse_unary(&mut self) -> Result<u32, String> {
+        match self.peek() {
+            Some('-') => {
+                self.position += 1;
+                Ok(self.parse_unary()?.wrapping_neg())
+            },
+            Some('~') => {
+                self.position += 1;
+                Ok(!self.parse_unary()?)
+            },
+            Some('+') => {
+                self.position += 1;
+                self.parse_unary()
+            },
+            _ => self.parse_primary(),
+        }
+    }
+
+    fn parse_primary(&mut self) -> Result<u32, String> {
+        match self.peek() {
+            Some('(') => {
+                self.position += 1;
+                let value = self.parse_binary(0)?;
+
+                if self.peek() != Some(')') {
+                    return Err(String::from("Missing ')' in expression"));
+                }
+
+                self.position += 1;
+                Ok(value)
+            },
+            Some('\'') => {
+                match self.characters.get(self.position + 1..self.position + 3) {
+                    Some(&[c, '\'']) => {
+                        self.position += 3;
+                        Ok(c as u32)
+                    },
+                    _ => Err(String::from("Invalid character constant")),
+                }
+            },
+            Some(c) if c.is_ascii_digit() => {
+                let token = self.take_while(|c| c.is_ascii_alphanumeric());
+                let (name, direction) = token.split_at(token.len() - 1);
+
+                if name.bytes().all(|b| b.is_ascii_digit()) && (direction == "b" || direction == "f") {
+                    return self.assembler.get_local_label(name, direction == "f", self.index);
+                }
+
+                parse_number(&token).ok_or_else(|| format!("Invalid number {}", token))
+            },
+            Some(c) if is_symbol_character(c) => {
+                let name = self.take_while(is_symbol_character);
+
+                if name == "." {
+                    return Ok(self.assembler.addresses[self.index]);
+                }
+
+                self.assembler.symbols.get(&name).copied().ok_or_else(|| format!("Undefined symbol {}", name))
+            },
+            Some(c) => Err(format!("Unexpected '{}' in expression", c)),
+            None => Err(String::from("Missing value in expression")),
+        }
+    }
+
+    fn match_operator(&mut self, operators: &[&'static str]) -> Option<&'static str> {
+        self.peek()?;
+
+        let remaining: String = self.characters[self.position..].iter().collect();
+        let operator = operators.iter().find(|o| remaining.starts_with(*o))?;
+        self.position += operator.len();
+
+        Some(operator)
+    }
+
+    fn take_while<P: Fn(char) -> bool>(&mut self, predicate: P) -> String {
+        let start = self.position;
+
+        while self.position < self.characters.len() && predicate(self.characters[self.position]) {
+            self.position += 1;
+        }
+
+        self.characters[start..self.position].iter().collect()
+    }
+
+    // the next character that isn't whitespace
+    fn peek(&mut self) -> Option<char> {
+        while self.position < self.characters.len() && self.characters[self.position].is_whitespace() {
+            self.position += 1;
+        }
+
+        self.characters.get(self.position).copied()
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum TransferSize {
+    Word,
+    Byte,
+    HalfWord,
+    SignedByte,
+    SignedHalfWord,
+    DoubleWord,
+}
+
+// the instruction a mnemonic names, without its condition and S suffix
+#[derive(Clone, Copy, Debug)]
+enum Mnemonic {
+    Add,
+    AddWithCarry,
+    And,
+    BitClear,
+    ExclusiveOr,
+    Or,
+    ReverseSubtract,
+    ReverseSubtractWithCarry,
+    Subtract,
+    SubtractWithCarry,
+    Move,
+    MoveNot,
+    Shift(&'static str),
+    Compare,
+    CompareNegative,
+    Test,
+    TestEquivalence,
+    MoveHalfWord,
+    MoveHalfWordTop,
+    Multiply,
+    MultiplyAccumulate,
+    MultiplySubtract,
+    UnsignedMultiplyLong,
+    UnsignedMultiplyAccumulateLong,
+    UnsignedMultiplyAccumulateAccumulateLong,
+    SignedMultiplyLong,
+    SignedMultiplyAccumulateLong,
+    SignedDivide,
+    UnsignedDivide,
+    Branch,
+    BranchLink,
+    BranchExchange,
+    Load(TransferSize),
+    Store(TransferSize),
+    LoadMultiple { before: bool, increment: bool },
+    StoreMultiple { before: bool, increment: bool },
+    Push,
+    Pop,
+    Address,
+    SupervisorCall,
+    Breakpoint,
+    MoveStatusToRegister,
+    SetEndianness,
+    NoOperation,
+}
+
+// whether the address is adjusted before each transfer, and whether it goes up
+type BlockTransferMode = (bool, bool);
+
+enum Operand {
+    Immediate(u32),
+    Register(Register, ShiftType, ShiftOperand),
+}
+
+// a mnemonic is a base name, optionally followed by S and a condition; both orders of the two are accepted, for older code
+fn parse_mnemonic(text: &str) -> Result<(Mnemonic, Condition, UpdateStatusFlags), String> {
+    MNEMONICS.iter()
+        .filter(|(name, _, _)| text.starts_with(name))
+        .find_map(|(name, mnemonic, allows_status_flags)| {
+            let suffix = &text[name.len()..];
+
+            match mnemonic {
+                Mnemonic::LoadMultiple { .. } => parse_block_transfer_suffix(suffix, true),
+                Mnemonic::StoreMultiple { .. } => parse_block_transfer_suffix(suffix, false),
+                _ => parse_suffix(suffix, *allows_status_flags).map(|(condition, flags)| (*mnemonic, condition, flags)),
+            }
+        })
+        .ok_or_else(|| format!("Unknown instruction {}", text))
+}
+
+fn parse_suffix(suffix: &str, allows_status_flags: bool) -> Option<(Condition, UpdateStatusFlags)> {
+    if let Some(condition) = parse_condition(suffix) {
+        return Some((condition, UpdateStatusFlags::DoNotUpdateStatusFlags));
+    }
+
+    if !allows_status_flags {
+        return None;
+    }
+
+    suffix.strip_prefix('s').and_then(parse_condition)
+        .or_else(|| suffix.strip_suffix('s').and_then(parse_condition))
+        .map(|condition| (condition, UpdateStatusFlags::UpdateStatusFlags))
+}
+
+// LDM and STM take an addressing mode, or the stack type it implements: a full or empty stack that grows down or up
+fn parse_block_transfer_suffix(suffix: &str, load_operation: bool) -> Option<(Mnemonic, Condition, UpdateStatusFlags)> {
+    let (before, increment, condition) = BLOCK_TRANSFER_MODES.iter()
+        .find_map(|(name, load_mode, store_mode)| {
+            let (before, increment) = if load_operation { *load_mode } else { *store_mode };
+
+            suffix.strip_prefix(name).or_else(|| suffix.strip_suffix(name))
+                .and_then(parse_condition)
+                .map(|condition| (before, increment, condition))
+        })
+        .or_else(|| parse_condition(suffix).map(|condition| (false, true, condition)))?;
+
+    let mnemonic = if load_operation { Mnemonic::LoadMultiple { before, increment } } else { Mnemonic::StoreMultiple { before, increment } };
+
+    Some((mnemonic, condition, UpdateStatusFlags::DoNotUpdateStatusFlags))
+}
+
+fn parse_condition(text: &str) -> Option<Condition> {
+    let condition = match text {
+        "eq" => Condition::Equal,
+        "ne" => Condition::NotEqual,
+        "cs" | "hs" => Condition::CarrySet,
+        "cc" | "lo" => Condition::CarryClear,
+        "mi" => Condition::Negative,
+        "pl" => Condition::Positive,
+        "vs" => Condition::Overflow,
+        "vc" => Condition::NoOverflow,
+        "hi" => Condition::UnsignedHigher,
+        "ls" => Condition::UnsignedLowerOrSame,
+        "ge" => Condition::GreaterThanOrEqual,
+        "lt" => Condition::LessThan,
+        "gt" => Condition::GreaterThan,
+        "le" => Condition::LessThanOrEqual,
+        "al" | "" => Condition::Always,
+        _ => return None,
+    };
+
+    Some(condition)
+}
+
+fn get_addressing_mode(before: bool, increment: bool) -> BlockTransferAddressingMode {
+    match (increment, before) {
+        (true, false) => BlockTransferAddressingMode::IncrementAfter,
+        (true, true) => BlockTransferAddressingMode::IncrementBefore,
+        (false, false) => BlockTransferAddressingMode::DecrementAfter,
+        (false, true) => BlockTransferAddressingMode::DecrementBefore,
+    }
+}
+
+// MUL Rd, Rn, Rm; MLA and MLS add Ra. Leaving out Rm of a MUL multiplies Rd by Rn
+fn parse_multiply_instruction(mnemonic: Mnemonic, flags: UpdateStatusFlags, operands: &[String]) -> Result<InstructionData, String> {
+    let registers = operands.iter().map(|o| parse_register(o)).collect::<Result<Vec<_>, _>>()?;
+
+    let data = match (mnemonic, registers.as_slice()) {
+        (Mnemonic::Multiply, &[destination_register, first_operand_register]) => {
+            InstructionData::Multiply(MultiplyArguments { destination_register, first_operand_register, second_operand_register: destination_register }, flags)
+        },
+        (Mnemonic::Multiply, &[destination_register, first_operand_register, second_operand_register]) => {
+            InstructionData::Multiply(MultiplyArguments { destination_register, first_operand_register, second_operand_register }, flags)
+        },
+        (Mnemonic::MultiplyAccumulate, &[destination_register, first_operand_register, second_operand_register, accumulate_register]) => {
+            InstructionData::MultiplyAccumulate(MultiplyAccumulateArguments { destination_register, first_operand_register, second_operand_register, accumulate_register }, flags)
+        },
+        (Mnemonic::MultiplySubtract, &[destination_register, first_operand_register, second_operand_register, accumulate_register]) => {
+            InstructionData::MultiplySubtract(MultiplyAccumulateArguments { destination_register, first_operand_register, second_operand_register, accumulate_register })
+        },
+        _ => return Err(String::from("Wrong number of registers for a multiply")),
+    };
+
+    Ok(data)
+}
+
+// UMULL RdLo, RdHi, Rn, Rm and friends
+fn parse_long_multiply_instruction(mnemonic: Mnemonic, flags: UpdateStatusFlags, operands: &[String]) -> Result<InstructionData, String> {
+    let args = match operands {
+        [low, high, first, second] => LongMultiplyArguments {
+            destination_register_low: parse_register(low)?,
+            destination_register_high: parse_register(high)?,
+            first_operand_register: parse_register(first)?,
+            second_operand_register: parse_register(second)?,
+        },
+        _ => return Err(String::from("Expected four registers")),
+    };
+
+    let data = match mnemonic {
+        Mnemonic::UnsignedMultiplyLong => InstructionData::UnsignedMultiplyLong(args, flags),
+        Mnemonic::UnsignedMultiplyAccumulateLong => InstructionData::UnsignedMultiplyAccumulateLong(args, flags),
+        Mnemonic::UnsignedMultiplyAccumulateAccumulateLong => InstructionData::UnsignedMultiplyAccumulateAccumulateLong(args),
+        Mnemonic::SignedMultiplyLong => InstructionData::SignedMultiplyLong(args, flags),
+        Mnemonic::SignedMultiplyAccumulateLong => InstructionData::SignedMultiplyAccumulateLong(args, flags),
+        _ => unreachable!("{:?} is not a long multiply", mnemonic),
+    };
+
+    Ok(data)
+}
+
+// PUSH and POP are STMDB sp! and LDMIA sp!, or for a single register, STR and LDR with write-back, like GNU as encodes them
+fn parse_stack_instruction(mnemonic: Mnemonic, operands: &[String]) -> Result<InstructionData, String> {
+    let register_list = parse_register_list(get_single_operand(operands)?)?;
+    let stack_pointer = u4::new(STACK_POINTER_REGISTER);
+    let push = matches!(mnemonic, Mnemonic::Push);
+
+    if register_list.count_ones() > 1 {
+        let args = BlockTransferArguments {
+            addressing_mode: if push { BlockTransferAddressingMode::DecrementBefore } else { BlockTransferAddressingMode::IncrementAfter },
+            write_back: LoadStoreWriteBackFlag::WriteBack,
+            address_register: stack_pointer,
+            register_list,
+        };
+
+        return Ok(if push { InstructionData::StoreMultiple(args) } else { InstructionData::LoadMultiple(args) });
+    }
+
+    let common_arguments = LoadStoreArguments {
+        indexing_type: if push { LoadStoreIndexingType::PreIndexed } else { LoadStoreIndexingType::PostIndexed },
+        write_back: LoadStoreWriteBackFlag::WriteBack,
+        offset_direction: if push { LoadStoreOffsetDirection::Negative } else { LoadStoreOffsetDirection::Positive },
+        value_register: u4::new(register_list.trailing_zeros() as u8),
+        address_register: stack_pointer,
+        offset: LoadStoreOffset::Immediate(u12::new(4)),
+    };
+
+    if push {
+        Ok(InstructionData::Store(StoreArguments { data_size: StoreDataSize::Word, common_arguments }))
+    } else {
+        Ok(InstructionData::Load(LoadArguments { data_size: LoadDataSize::Word, common_arguments }))
+    }
+}
+
+// {r0, r4-r7, lr}
+fn parse_register_list(text: &str) -> Result<u16, String> {
+    let inner = text.trim().strip_prefix('{').and_then(|t| t.strip_suffix('}'))
+        .ok_or_else(|| format!("Expected a register list instead of {}", text))?;
+    if inner.trim().is_empty() {
+        return Err(String::from("Empty register list"));
+    }
+
+    let mut register_list = 0u16;
+
+    for item in inner.split(',') {
+        let (first, last) = match item.split_once('-') {
+            Some((first, last)) => (u8::from(parse_register(first)?), u8::from(parse_register(last)?)),
+            None => {
+                let register = u8::from(parse_register(item)?);
+                (register, register)
+            },
+        };
+
+        if first > last {
+            return Err(format!("Invalid register range {}", item.trim()));
+        }
+
+        for register in first..=last {
+            register_list |= 1 << register;
+        }
+    }
+
+    Ok(register_list)
+}
+
+fn parse_register(text: &str) -> Result<Register, String> {
+    let name = text.trim().to_lowercase();
+
+    let register = match name.as_str() {
+        "sb" => 9,
+        "sl" => 10,
+        "fp" => 11,
+        "ip" => 12,
+        "sp" => 13,
+        "lr" => 14,
+        "pc" => 15,
+        _ => name.strip_prefix('r')
+            .and_then(|n| n.parse::<u8>().ok())
+            .filter(|&n| n < 16)
+            .ok_or_else(|| format!("Invalid register {}", text.trim()))?,
+    };
+
+    Ok(u4::new(register))
+}
+
+fn is_register(text: &str) -> bool {
+    parse_register(text).is_ok()
+}
+
+fn is_shift(text: &str) -> bool {
+    let name = text.split_whitespace().next().unwrap_or_default().to_lowercase();
+
+    matches!(name.as_str(), "lsl" | "asl" | "lsr" | "asr" | "ror" | "rrx")
+}
+
+// when an immediate can't be encoded, GNU as tries the complementary instruction: ADD r0, #-1 is SUB r0, #1
+fn get_encodable_alternative(mnemonic: Mnemonic, value: u32) -> (Mnemonic, u32) {
+    if get_immediate_rotation(value).is_some() {
+        return (mnemonic, value);
+    }
+
+    let alternative = match mnemonic {
+        Mnemonic::Add => (Mnemonic::Subtract, value.wrapping_neg()),
+        Mnemonic::Subtract => (Mnemonic::Add, value.wrapping_neg()),
+        Mnemonic::AddWithCarry => (Mnemonic::SubtractWithCarry, !value),
+        Mnemonic::SubtractWithCarry => (Mnemonic::AddWithCarry, !value),
+        Mnemonic::And => (Mnemonic::BitClear, !value),
+        Mnemonic::BitClear => (Mnemonic::And, !value),
+        Mnemonic::Move => (Mnemonic::MoveNot, !value),
+        Mnemonic::MoveNot => (Mnemonic::Move, !value),
+        Mnemonic::Compare => (Mnemonic::CompareNegative, value.wrapping_neg()),
+        Mnemonic::CompareNegative => (Mnemonic::Compare, value.wrapping_neg()),
+        _ => return (mnemonic, value),
+    };
+
+    if get_immediate_rotation(alternative.1).is_some() { alternative } else { (mnemonic, value) }
+}
+
+// the immediate, carry and rotation, as the decoder would report them
+fn create_shifted_immediate(value: u32) -> Result<(u32, bool, u8), String> {
+    let rotate = get_immediate_rotation(value)
+        .ok_or_else(|| format!("Immediate {:#x} cannot be encoded as a rotated 8-bit value", value))?;
+
+    Ok((value, value & 0x80000000 != 0, rotate))
+}
+
+fn create_offset_immediate(offset: i64) -> Result<u12, String> {
+    let magnitude = offset.unsigned_abs();
+    if magnitude > 0xfff {
+        return Err(format!("Offset {} is out of range", offset));
+    }
+
+    Ok(u12::new(magnitude as u16))
+}
+
+fn get_operand(operands: &[String], position: usize) -> Result<&str, String> {
+    operands.get(position).map(|o| o.as_str()).ok_or_else(|| String::from("Missing operand"))
+}
+
+fn get_single_operand(operands: &[String]) -> Result<&str, String> {
+    match operands {
+        [operand] => Ok(operand),
+        _ => Err(String::from("Expected one operand")),
+    }
+}
+
+fn get_padding(address: u32, alignment: u32) -> u32 {
+    address.wrapping_neg() & (alignment - 1)
+}
+
+fn parse_number(text: &str) -> Option<u32> {
+    let text = text.to_lowercase();
+
+    if let Some(digits) = text.strip_prefix("0x") {
+        u32::from_str_radix(digits, 16).ok()
+    } else if let Some(digits) = text.strip_prefix("0b") {
+        u32::from_str_radix(digits, 2).ok()
+    } else {
+        text.parse().ok()
+    }
+}
+
+fn is_symbol_character(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '$'
+}
+
+fn is_label_name(name: &str) -> bool {
+    match name.chars().next() {
+        Some(c) if c.is_ascii_digit() => name.bytes().all(|b| b.is_ascii_digit()),
+        Some(_) => name.chars().all(is_symbol_character),
+        None => false,
+    }
+}
+
+// splits a line into labels, a mnemonic and its operands. Comments start with // or @, or are between /* and */
+fn parse_statements(source: &str) -> Vec<Statement> {
+    let mut statements = Vec::new();
+    let mut in_comment = false;
+
+    for (number, line) in source.lines().enumerate() {
+        let line = strip_comments(line, &mut in_comment);
+        let mut text = line.trim();
+        let mut labels = Vec::new();
+
+        while let Some(colon) = text.find(':') {
+            let name = text[..colon].trim();
+            if !is_label_name(name) {
+                break;
+            }
+
+            labels.push(String::from(name));
+            text = text[colon + 1..].trim();
+        }
+
+        if text.is_empty() && labels.is_empty() {
+            continue;
+        }
+
+        let (mnemonic, operands) = match text.find(char::is_whitespace) {
+            Some(position) => (&text[..position], text[position..].trim()),
+            None => (text, ""),
+        };
+        let mnemonic = mnemonic.to_lowercase();
+
+        if mnemonic == ".end" {
+            break;
+        }
+
+        statements.push(Statement {
+            line_number: number + 1,
+            labels,
+            mnemonic,
+            operands: String::from(operands),
+        });
+    }
+
+    statements
+}
+
+fn strip_comments(line: &str, in_comment: &mut bool) -> String {
+    let mut result = String::new();
+    let mut characters = line.chars().peekable();
+    let mut in_string = false;
+
+    while let Some(c) = characters.next() {
+        if *in_comment {
+            if c == '*' && characters.peek() == Some(&'/') {
+                characters.next();
+                *in_comment = false;
+            }
+        } else if in_string {
+            result.push(c);
+
+            if c == '\\' {
+                result.extend(characters.next());
+            } else if c == '"' {
+                in_string = false;
+            }
+        } else {
+            match (c, characters.peek()) {
+                ('@', _) | ('/', Some('/')) => break,
+                ('/', Some('*')) => {
+                    characters.next();
+                    *in_comment = true;
+                    result.push(' ');
+                },
+                ('"', _) => {
+                    in_string = true;
+                    result.push(c);
+                },
+                _ => result.push(c),
+            }
+        }
+    }
+
+    result
+}
+
+// splits on the commas that aren't inside brackets, braces or strings
+fn split_operands(text: &str) -> Vec<String> {
+    let mut operands = Vec::new();
+    let mut current = String::new();
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for c in text.chars() {
+        match c {
+            _ if in_string => {
+                in_string = escaped || c != '"';
+                escaped = !escaped && c == '\\';
+            },
+            '"' => in_string = true,
+            '[' | '{' | '(' => depth += 1,
+            ']' | '}' | ')' => depth -= 1,
+            ',' if depth == 0 => {
+                operands.push(String::from(current.trim()));
+                current.clear();
+                continue;
+            },
+            _ => {},
+        }
+
+        current.push(c);
+    }
+
+    if !current.trim().is_empty() || !operands.is_empty() {
+        operands.push(String::from(current.trim()));
+    }
+
+    operands
+}
+
+// the quoted strings of .ascii and friends, with C escapes
+fn parse_strings(operands: &[String]) -> Result<Vec<Vec<u8>>, String> {
+    operands.iter().map(|operand| {
+        let inner = operand.strip_prefix('"').and_then(|o| o.strip_suffix('"'))
+            .ok_or_else(|| format!("Expected a string instead of {}", operand))?;
+        let mut bytes = Vec::new();
+        let mut characters = inner.chars();
+
+        while let Some(c) = characters.next() {
+            let c = match c {
+                '\\' => match characters.next() {
+                    Some('n') => '\n',
+                    Some('t') => '\t',
+                    Some('r') => '\r',
+                    Some('0') => '\0',
+                    Some(c @ '\\') | Some(c @ '"') | Some(c @ '\'') => c,
+                    _ => return Err(format!("Invalid escape in {}", operand)),
+                },
+                c => c,
+            };
+
+            let mut buffer = [0u8; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+        }
+
+        Ok(bytes)
+    }).collect()
+}
+
+// the programs GNU ld links for ARM start here by default
+pub const DEFAULT_ADDRESS: u32 = 0x00008000;
+
+const ENTRY_POINT_SYMBOL: &str = "_start";
+const DEFAULT_ALIGNMENT_EXPONENT: u32 = 2;
+const MAXIMUM_ALIGNMENT_EXPONENT: u32 = 16;
+
+// directives that don't affect a flat image of ARM code
+const IGNORED_DIRECTIVES: &[&str] = &[
+    ".section", ".text", ".data", ".bss", ".rodata", ".global", ".globl", ".local", ".weak", ".arm", ".syntax", ".type",
+    ".size", ".fpu", ".cpu", ".arch", ".eabi_attribute", ".file", ".ident", ".func", ".endfunc",
+];
+
+// in order of precedence, from lowest to highest
+const BINARY_OPERATORS: [&[&str]; 6] = [ &["|"], &["^"], &["&"], &["<<", ">>"], &["+", "-"], &["*", "/", "%"] ];
+
+// the base names, and whether they take an S suffix; longer names come before the names they start with
+const MNEMONICS: &[(&str, Mnemonic, bool)] = &[
+    ("adc", Mnemonic::AddWithCarry, true),
+    ("add", Mnemonic::Add, true),
+    ("adr", Mnemonic::Address, false),
+    ("and", Mnemonic::And, true),
+    ("asr", Mnemonic::Shift("asr"), true),
+    ("bic", Mnemonic::BitClear, true),
+    ("bkpt", Mnemonic::Breakpoint, false),
+    ("bl", Mnemonic::BranchLink, false),
+    ("bx", Mnemonic::BranchExchange, false),
+    ("b", Mnemonic::Branch, false),
+    ("cmn", Mnemonic::CompareNegative, false),
+    ("cmp", Mnemonic::Compare, false),
+    ("eor", Mnemonic::ExclusiveOr, true),
+    ("ldm", Mnemonic::LoadMultiple { before: false, increment: true }, false),
+    ("ldrsb", Mnemonic::Load(TransferSize::SignedByte), false),
+    ("ldrsh", Mnemonic::Load(TransferSize::SignedHalfWord), false),
+    ("ldrb", Mnemonic::Load(TransferSize::Byte), false),
+    ("ldrd", Mnemonic::Load(TransferSize::DoubleWord), false),
+    ("ldrh", Mnemonic::Load(TransferSize::HalfWord), false),
+    ("ldr", Mnemonic::Load(TransferSize::Word), false),
+    ("lsl", Mnemonic::Shift("lsl"), true),
+    ("lsr", Mnemonic::Shift("lsr"), true),
+    ("mla", Mnemonic::MultiplyAccumulate, true),
+    ("mls", Mnemonic::MultiplySubtract, false),
+    ("movt", Mnemonic::MoveHalfWordTop, false),
+    ("movw", Mnemonic::MoveHalfWord, false),
+    ("mov", Mnemonic::Move, true),
+    ("mrs", Mnemonic::MoveStatusToRegister, false),
+    ("mul", Mnemonic::Multiply, true),
+    ("mvn", Mnemonic::MoveNot, true),
+    ("nop", Mnemonic::NoOperation, false),
+    ("orr", Mnemonic::Or, true),
+    ("pop", Mnemonic::Pop, false),
+    ("push", Mnemonic::Push, false),
+    ("ror", Mnemonic::Shift("ror"), true),
+    ("rrx", Mnemonic::Shift("rrx"), true),
+    ("rsb", Mnemonic::ReverseSubtract, true),
+    ("rsc", Mnemonic::ReverseSubtractWithCarry, true),
+    ("sbc", Mnemonic::SubtractWithCarry, true),
+    ("sdiv", Mnemonic::SignedDivide, false),
+    ("setend", Mnemonic::SetEndianness, false),
+    ("smlal", Mnemonic::SignedMultiplyAccumulateLong, true),
+    ("smull", Mnemonic::SignedMultiplyLong, true),
+    ("stm", Mnemonic::StoreMultiple { before: false, increment: true }, false),
+    ("strb", Mnemonic::Store(TransferSize::Byte), false),
+    ("strd", Mnemonic::Store(TransferSize::DoubleWord), false),
+    ("strh", Mnemonic::Store(TransferSize::HalfWord), false),
+    ("str", Mnemonic::Store(TransferSize::Word), false),
+    ("sub", Mnemonic::Subtract, true),
+    ("svc", Mnemonic::SupervisorCall, false),
+    ("swi", Mnemonic::SupervisorCall, false),
+    ("teq", Mnemonic::TestEquivalence, false),
+    ("tst", Mnemonic::Test, false),
+    ("udiv", Mnemonic::UnsignedDivide, false),
+    ("umaal", Mnemonic::UnsignedMultiplyAccumulateAccumulateLong, false),
+    ("umlal", Mnemonic::UnsignedMultiplyAccumulateLong, true),
+    ("umull", Mnemonic::UnsignedMultiplyLong, true),
+];
+
+// the addressing mode of LDM and of STM for each suffix; the stack types mean different modes for the two
+const BLOCK_TRANSFER_MODES: [(&str, BlockTransferMode, BlockTransferMode); 8] = [
+    ("ia", (false, true), (false, true)),
+    ("ib", (true, true), (true, true)),
+    ("da", (false, false), (false, false)),
+    ("db", (true, false), (true, false)),
+    ("fd", (false, true), (true, false)),
+    ("fa", (false, false), (true, true)),
+    ("ed", (true, true), (false, false)),
+    ("ea", (true, false), (false, true)),
+];
+
+const STACK_POINTER_REGISTER: u8 = 13;
+
+const EQUAL_CONDITION: u8 = 0x0;
+const NOT_EQUAL_CONDITION: u8 = 0x1;
+const CARRY_SET_CONDITION: u8 = 0x2;
+const CARRY_CLEAR_CONDITION: u8 = 0x3;
+const NEGATIVE_CONDITION: u8 = 0x4;
+const POSITIVE_CONDITION: u8 = 0x5;
+const OVERFLOW_CONDITION: u8 = 0x6;
+const NO_OVERFLOW_CONDITION: u8 = 0x7;
+const UNSIGNED_HIGHER_CONDITION: u8 = 0x8;
+const UNSIGNED_LOWER_OR_SAME_CONDITION: u8 = 0x9;
+const GREATER_THAN_OR_EQUAL_CONDITION: u8 = 0xa;
+const LESS_THAN_CONDITION: u8 = 0xb;
+const GREATER_THAN_CONDITION: u8 = 0xc;
+const LESS_THAN_OR_EQUAL_CONDITION: u8 = 0xd;
+const ALWAYS_CONDITION: u8 = 0xe;
+const SET_ENDIANNESS_VALUE: u32 = 0xf1010000;
+const SET_ENDIANNESS_BIG_ENDIAN_BIT: u32 = 0x00000200;
+const BRANCH_INSTRUCTION_CLASS: u32 = 0x0a000000;
+const BRANCH_LINK_BIT: u32 = 0x01000000;
+const BLOCK_TRANSFER_INSTRUCTION_CLASS: u32 = 0x08000000;
+const LOAD_STORE_IMMEDIATE_INSTRUCTION_CLASS: u32 = 0x04000000;
+const SUPERVISOR_CALL_VALUE: u32 = 0x0f000000;
+const EXTRA_LOAD_STORE_VALUE: u32 = 0x00000090;
+const EXTRA_LOAD_STORE_IMMEDIATE_BIT: u32 = 0x00400000;
+const REGISTER_OFFSET_BIT: u32 = 0x02000000;
+const PRE_INDEXED_BIT: u32 = 0x01000000;
+const POSITIVE_OFFSET_BIT: u32 = 0x00800000;
+const BYTE_BIT: u32 = 0x00400000;
+const WRITE_BACK_BIT: u32 = 0x00200000;
+const LOAD_BIT: u32 = 0x00100000;
+const MULTIPLY_VALUE: u32 = 0x00000090;
+const UPDATE_STATUS_BIT: u32 = 0x00100000;
+const IMMEDIATE_MODE_BIT: u32 = 0x02000000;
+const BREAKPOINT_VALUE: u32 = 0x01200070;
+const BRANCH_EXCHANGE_VALUE: u32 = 0x012fff10;
+const MOVE_STATUS_TO_REGISTER_VALUE: u32 = 0x010f0000;
+const MOVE_HALFWORD_VALUE: u32 = 0x03000000;
+const MOVE_HALFWORD_TOP_VALUE: u32 = 0x03400000;
+
+const ADD_OPCODE: u8 = 0x4;
+const ADD_WITH_CARRY_OPCODE: u8 = 0x5;
+const AND_OPCODE: u8 = 0x0;
+const BIT_CLEAR_OPCODE: u8 = 0xe;
+const COMPARE_OPCODE: u8 = 0xa;
+const COMPARE_NEGATIVE_OPCODE: u8 = 0xb;
+const EXCLUSIVE_OR_OPCODE: u8 = 0x1;
+const MOVE_OPCODE: u8 = 0xd;
+const MOVE_NOT_OPCODE: u8 = 0xf;
+const OR_OPCODE: u8 = 0xc;
+const REVERSE_SUBTRACT_OPCODE: u8 = 0x3;
+const REVERSE_SUBTRACT_WITH_CARRY_OPCODE: u8 = 0x7;
+const SUBTRACT_OPCODE: u8 = 0x2;
+const SUBTRACT_WITH_CARRY_OPCODE: u8 = 0x6;
+const TEST_OPCODE: u8 = 0x8;
+const TEST_EQUIVALENCE_OPCODE: u8 = 0x9;
+
+const MULTIPLY_OPCODE: u8 = 0x0;
+const MULTIPLY_ACCUMULATE_OPCODE: u8 = 0x1;
+const UNSIGNED_MULTIPLY_ACCUMULATE_ACCUMULATE_LONG_OPCODE: u8 = 0x2;
+const MULTIPLY_SUBTRACT_OPCODE: u8 = 0x3;
+const UNSIGNED_MULTIPLY_LONG_OPCODE: u8 = 0x4;
+const UNSIGNED_MULTIPLY_ACCUMULATE_LONG_OPCODE: u8 = 0x5;
+const SIGNED_MULTIPLY_LONG_OPCODE: u8 = 0x6;
+const SIGNED_MULTIPLY_ACCUMULATE_LONG_OPCODE: u8 = 0x7;
+
+const SIGNED_DIVIDE_VALUE: u32 = 0x0710f010;
+const UNSIGNED_DIVIDE_VALUE: u32 = 0x0730f010;
+
+const SHIFT_TYPE_LOGICAL_SHIFT_LEFT: u8 =       0b0000000;
+const SHIFT_TYPE_LOGICAL_SHIFT_RIGHT: u8 =      0b0100000;
+const SHIFT_TYPE_ARITHMETIC_SHIFT_RIGHT: u8 =   0b1000000;
+const SHIFT_TYPE_ROTATE_RIGHT: u8 =             0b1100000;
+const SHIFT_REGISTER_BIT: u32 = 0x00000010;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{context::{CpuConfiguration, SystemCallAbi}, decoding::decode, disassembler::disassemble, machine::Machine, sandbox::Sandbox,
+        stream::{FileTable, OutputBuffer, Stream, STANDARD_OUTPUT}, syscall::CourseSyscallHandler};
+
+    fn get_words(data: &[u8]) -> Vec<u32> {
+        data.chunks_exact(4).map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])).collect()
+    }
+
+    fn assemble_word(source: &str) -> u32 {
+        get_words(&assemble(source, DEFAULT_ADDRESS).unwrap().data)[0]
+    }
+
+    // text, assembled, decoded, encoded again and disassembled again, for every kind of instruction
+    #[test]
+    fn instructions_survive_a_round_trip() {
+        let instructions = [
+            ("adds r2, r3, r4, lsl #2", 0xe0932104),
+            ("adc r0, r1, #1", 0xe2a10001),
+            ("and r0, r1, r2, ror r3", 0xe0010372),
+            ("bic r0, r1, #255", 0xe3c100ff),
+            ("bx lr", 0xe12fff1e),
+            ("bkpt #171", 0xe1200a7b),
+            ("cmp r0, #3", 0xe3500003),
+            ("cmn r0, r1", 0xe1700001),
+            ("eor r0, r0, r1, asr #31", 0xe0200fc1),
+            ("ldrne r1, [r0, #4]!", 0x15b01004),
+            ("ldrb r1, [r0], #-1", 0xe4501001),
+            ("ldrd r2, r3, [r0, #8]", 0xe1c020d8),
+            ("ldrh r1, [r0, r2]", 0xe19010b2),
+            ("ldrsb r1, [r0, #-3]", 0xe15010d3),
+            ("ldrsh r1, [r0]", 0xe1d010f0),
+            ("pop {r0}", 0xe49d0004),
+            ("ldm r0!, {r1, r2, r3}", 0xe8b0000e),
+            ("pop {r4, r5, pc}", 0xe8bd8030),
+            ("ldmib r0, {r1, r2}", 0xe9900006),
+            ("mov r0, r1", 0xe1a00001),
+            ("lsls r0, r1, #2", 0xe1b00101),
+            ("mov r0, #0xff000000", 0xe3a004ff),
+            ("movw r0, #4660", 0xe3010234),
+            ("movt r0, #43981", 0xe34a0bcd),
+            ("mvn r0, #0", 0xe3e00000),
+            ("mrs r0, apsr", 0xe10f0000),
+            ("mul r0, r1, r2", 0xe0000291),
+            ("mlas r0, r1, r2, r3", 0xe0303291),
+            ("mls r0, r1, r2, r3", 0xe0603291),
+            ("orr r0, r1, r2, rrx", 0xe1810062),
+            ("rsb r0, r0, #0", 0xe2600000),
+            ("rscs r0, r1, r2", 0xe0f10002),
+            ("setend be", 0xf1010200),
+            ("setend le", 0xf1010000),
+            ("sdiv r0, r1, r2", 0xe710f211),
+            ("smlal r0, r1, r2, r3", 0xe0e10392),
+            ("smulls r0, r1, r2, r3", 0xe0d10392),
+            ("svc #0", 0xef000000),
+            ("str r1, [r0, -r2, lsl #2]", 0xe7001102),
+            ("strb r1, [r0, #1]", 0xe5c01001),
+            ("strd r2, r3, [sp, #-8]!", 0xe16d20f8),
+            ("strh r1, [r0], #2", 0xe0c010b2),
+            ("push {r0, r1, lr}", 0xe92d4003),
+            ("push {r4}", 0xe52d4004),
+            ("stmda r0, {r1, r2}", 0xe8000006),
+            ("sub sp, sp, #8", 0xe24dd008),
+            ("sbc r0, r1, r2", 0xe0c10002),
+            ("tst r0, #1", 0xe3100001),
+            ("teq r0, r1", 0xe1300001),
+            ("udiv r0, r1, r2", 0xe730f211),
+            ("umaal r0, r1, r2, r3", 0xe0410392),
+            ("umlal r0, r1, r2, r3", 0xe0a10392),
+            ("umull r0, r1, r2, r3", 0xe0810392),
+        ];
+
+        for (text, encoded_instruction) in instructions {
+            let instruction = decode(encoded_instruction).unwrap();
+
+            assert_eq!(assemble_word(text), encoded_instruction, "{}", text);
+            assert_eq!(encode(&instruction).unwrap(), encoded_instruction, "{}", text);
+            assert_eq!(disassemble(&instruction, DEFAULT_ADDRESS, &[]), text);
+        }
+    }
+
+    #[test]
+    fn labels_resolve_forwards_and_backwards() {
+        let program = assemble("_start:\n    b end\nloop:\n    bne loop\n    adr r0, value\nend:\n    b .\nvalue:\n    .word 0x12345678\n    .space 2\n", DEFAULT_ADDRESS).unwrap();
+        assert_eq!(get_words(&program.data), [0xea000001, 0x1afffffe, 0xe28f0000, 0xeafffffe, 0x12345678]);
+        assert_eq!(program.data.len(), 22);
+        assert_eq!(program.entry_point, DEFAULT_ADDRESS);
+        assert!(program.symbols.iter().any(|s| s.name == "value" && s.address == DEFAULT_ADDRESS + 16));
+    }
+
+    #[test]
+    fn mov_falls_back_to_movw_for_16_bit_immediates() {
+        assert_eq!(assemble_word("    mov r0, #0x101\n"), 0xe3000101);
+    }
+
+    #[test]
+    fn rejects_what_it_cannot_encode() {
+        assert!(matches!(assemble("    mov r0, #0x10001\n", DEFAULT_ADDRESS), Err(EmulatorError::InvalidAssembly(_))));
+        assert!(matches!(assemble("    ldrd r1, r2, [r0]\n", DEFAULT_ADDRESS), Err(EmulatorError::InvalidAssembly(_))));
+        assert!(matches!(assemble("    b nowhere\n", DEFAULT_ADDRESS), Err(EmulatorError::InvalidAssembly(_))));
+    }
+
+    // runs an assembled program with the course ABI, returning the machine it stopped in and what it wrote
+    fn run_program(source: &str) -> (Machine, String) {
+        let program = assemble(source, DEFAULT_ADDRESS).unwrap();
+        let output = OutputBuffer::create();
+        let mut files = FileTable::create(Sandbox::create("."));
+        let mut machine = Machine::create(CpuConfiguration { system_call_abi: SystemCallAbi::Course, ..Default::default() });
+
+        files.redirect(STANDARD_OUTPUT, Stream::Buffer(output.clone()));
+        machine.set_syscall_handler(Box::new(CourseSyscallHandler::create(files)));
+        machine.get_context_mut().load_memory(program.address, &program.data).unwrap();
+        machine.get_context_mut().set_program_counter(program.entry_point);
+        machine.run().unwrap();
+
+        (machine, output.get_text())
+    }
+
+    // the programs in asm/ no longer need the GNU toolchain. They all succeed: those that exit do so with status 0, and
+    // Count ends by branching to itself, which the binary also reports as 0
+    #[test]
+    fn course_programs_assemble_and_run() {
+        let sources = [
+            include_str!("../asm/ArmSimulator.s"),
+            include_str!("../asm/Count.s"),
+            include_str!("../asm/Fib.s"),
+            include_str!("../asm/LoadStore.s"),
+            include_str!("../asm/Udiv.s"),
+        ];
+
+        for source in sources {
+            let (machine, _) = run_program(source);
+
+            assert!(machine.is_halted());
+            assert_eq!(machine.get_exit_status().unwrap_or(0), 0);
+        }
+
+        let (machine, output) = run_program(include_str!("../asm/Fib.s"));
+        assert_eq!(output, "The 37th Fibonacci number is 14930352\n\0");
+        assert_eq!(machine.get_exit_status(), Some(0));
+
+        let (machine, _) = run_program(include_str!("../asm/Count.s"));
+        assert_eq!(machine.get_context().get_register(0), 100);
+    }
+}
@@ -1,10 +1,76 @@
-use std::{mem::size_of, ops::RangeInclusive, slice};
+use std::{cell::RefCell, mem::size_of, ops::RangeInclusive};
+
+use crate::{error::EmulatorError, memory::{Memory, MemoryAccess, MAXIMUM_MEMORY_SIZE}};
 
 pub struct CpuContext {
     registers: [u32; 16],
-    memory: Box<[u8]>,
+    memory: Memory,
     status: StatusFlags,
-    halted: bool
+    endianness: Endianness,
+    configuration: CpuConfiguration,
+    pending_supervisor_call: Option<u32>,
+    pending_breakpoint: Option<u32>,
+    halted: bool,
+    exit_status: Option<u32>,
+    data_accesses: Option<RefCell<Vec<DataAccess>>>,  // only recorded while something is watching memory
+}
+
+// a read or write of guest data, by an instruction or on behalf of the guest
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DataAccess {
+    pub address: u32,
+    pub length: u32,
+    pub access: MemoryAccess,
+}
+
+#[derive(Copy, Clone)]
+pub struct CpuConfiguration {
+    pub divide_by_zero: DivideByZeroBehaviour,
+    pub memory_size: u64,   // the size in bytes of the RAM at address zero, at most 4 GiB. Other regions can be mapped beyond it
+    pub alignment: AlignmentBehaviour,
+    pub system_call_abi: SystemCallAbi,
+}
+
+impl Default for CpuConfiguration {
+    fn default() -> Self {
+        CpuConfiguration {
+            divide_by_zero: DivideByZeroBehaviour::default(),
+            memory_size: MAXIMUM_MEMORY_SIZE,
+            alignment: AlignmentBehaviour::default(),
+            system_call_abi: SystemCallAbi::default(),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum DivideByZeroBehaviour {
+    #[default]
+    ReturnZero,         // the architectural default: the quotient is zero
+    Trap,               // as if SCTLR.DZ were set: the instruction is undefined, which ends the program with SIGILL
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum AlignmentBehaviour {
+    #[default]
+    Unaligned,          // ARMv7 with SCTLR.A clear: LDR, STR and the halfword transfers may be unaligned
+    Strict,             // as if SCTLR.A were set: every unaligned access takes an alignment fault
+    Rotate,             // pre-ARMv6: unaligned LDR rotates the aligned word, and STR, LDM and STM ignore the bottom address bits
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum SystemCallAbi {
+    #[default]
+    Linux,              // the Linux ARM EABI, with byte-oriented I/O
+    Course,             // only exit (1) and write (4), which writes a length-prefixed UTF-16 string from r1 to standard output
+    Semihosting,        // ARM semihosting through SVC 0x123456 or BKPT 0xAB, with files relative to the working directory
+}
+
+// the byte order of data accesses, as selected by CPSR.E; instructions are always fetched little-endian (BE8)
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum Endianness {
+    #[default]
+    Little,
+    Big,
 }
 
 #[derive(Copy, Clone)]
@@ -16,23 +82,58 @@ pub struct StatusFlags {
 }
 
 const LINK_RETURN_REGISTER: u8 = 14;
+const NEGATIVE_BIT: u32 = 31;
+const ZERO_BIT: u32 = 30;
+const CARRY_BIT: u32 = 29;
+const OVERFLOW_BIT: u32 = 28;
+const ENDIANNESS_BIT: u32 = 9;
 const PROGRAM_COUNTER_REGISTER: u8 = 15;
 
 impl CpuContext {
-    pub fn create() -> CpuContext {
+    pub fn create(configuration: CpuConfiguration) -> CpuContext {
         let registers = [0u32; 16];
-        let memory = [0u8; 0x10000];
+        let memory = Memory::create(configuration.memory_size);
 
         CpuContext {
-            registers: registers,
-            memory: Box::from(memory),
+            registers,
+            memory,
             status: StatusFlags { negative: false, zero: false, carry: false, overflow: false },
-            halted: false
+            endianness: Endianness::default(),
+            configuration,
+            pending_supervisor_call: None,
+            pending_breakpoint: None,
+            halted: false,
+            exit_status: None,
+            data_accesses: None,
         }
     }
 
-    pub fn write_memory(&mut self, data: &Vec<u8>) {
-        self.memory[..data.len()].copy_from_slice(data)
+    pub fn get_configuration(&self) -> &CpuConfiguration {
+        &self.configuration
+    }
+
+    pub fn read_memory(&self, address: u32, length: usize) -> Result<Vec<u8>, EmulatorError> {
+        let mut data = vec![0u8; length];
+        self.read_data(address, &mut data)?;
+
+        Ok(data)
+    }
+
+    pub fn write_memory(&mut self, address: u32, data: &[u8]) -> Result<(), EmulatorError> {
+        self.write_data(address, data)
+    }
+
+    // writes regardless of permissions, for loading programs into ROM
+    pub fn load_memory(&mut self, address: u32, data: &[u8]) -> Result<(), EmulatorError> {
+        self.memory.load(address, data)
+    }
+
+    pub fn get_memory(&self) -> &Memory {
+        &self.memory
+    }
+
+    pub fn get_memory_mut(&mut self) -> &mut Memory {
+        &mut self.memory
     }
 
     pub const fn get_link_return_register() -> u8 {
@@ -56,7 +157,7 @@ impl CpuContext {
         let value = self.registers[register as usize];
 
         match register {
-            PROGRAM_COUNTER_REGISTER => value + 4,  // instructions reading from PC will get PC + 8, but since PC has already been advanced by 4, we need to add only another 4
+            PROGRAM_COUNTER_REGISTER => value.wrapping_add(4),  // instructions reading from PC will get PC + 8, but since PC has already been advanced by 4, we need to add only another 4
             _ => value,
         }
     }
@@ -71,108 +172,170 @@ impl CpuContext {
     }
 
     pub fn set_status(&mut self, negative: Option<bool>, zero: Option<bool>, carry: Option<bool>, overflow: Option<bool>) {
-        match negative {
-            Some(v) => self.status.negative = v,
-            None => {}
+        if let Some(v) = negative {
+            self.status.negative = v
         }
-        match zero {
-            Some(v) => self.status.zero = v,
-            None => {}
+        if let Some(v) = zero {
+            self.status.zero = v
         }
-        match carry {
-            Some(v) => self.status.carry = v,
-            None => {}
+        if let Some(v) = carry {
+            self.status.carry = v
         }
-        match overflow {
-            Some(v) => self.status.overflow = v,
-            None => {}
+        if let Some(v) = overflow {
+            self.status.overflow = v
         }
     }
 
-    pub fn read_word(&self, address: u32) -> u32 {
-        let start_address = address as usize;
-        let end_address = start_address + size_of::<u32>();
-        let slice = &self.memory[start_address..end_address];
-        let pointer = slice.as_ptr();
-        let pointer_u32 = pointer as *const u32;
+    // CPSR as MRS reads it: the condition flags and the E bit
+    pub fn get_program_status_register(&self) -> u32 {
+        let status = self.status;
 
-        unsafe {
-            *pointer_u32
-        }
+        (status.negative as u32) << NEGATIVE_BIT
+            | (status.zero as u32) << ZERO_BIT
+            | (status.carry as u32) << CARRY_BIT
+            | (status.overflow as u32) << OVERFLOW_BIT
+            | ((self.endianness == Endianness::Big) as u32) << ENDIANNESS_BIT
     }
 
-    pub fn read_byte(&self, address: u32) -> u8 {
-        let start_address = address as usize;
-        let end_address = start_address + size_of::<u32>();
-        let slice = &self.memory[start_address..end_address];
-        let pointer = slice.as_ptr();
+    // the other bits have no meaning in this emulator and are ignored
+    pub fn set_program_status_register(&mut self, value: u32) {
+        let is_set = |bit: u32| value & (1 << bit) != 0;
 
-        unsafe {
-            *pointer
-        }
+        self.status = StatusFlags { negative: is_set(NEGATIVE_BIT), zero: is_set(ZERO_BIT), carry: is_set(CARRY_BIT), overflow: is_set(OVERFLOW_BIT) };
+        self.endianness = if is_set(ENDIANNESS_BIT) { Endianness::Big } else { Endianness::Little };
+    }
+
+    pub fn get_endianness(&self) -> Endianness {
+        self.endianness
+    }
+
+    pub fn set_endianness(&mut self, endianness: Endianness) {
+        self.endianness = endianness
     }
 
-    pub fn read_half_word(&self, address: u32) -> u16 {
-        let start_address = address as usize;
-        let end_address = start_address + size_of::<u32>();
-        let slice = &self.memory[start_address..end_address];
-        let pointer = slice.as_ptr();
-        let pointer_u16 = pointer as *const u16;
+    pub fn read_word(&self, address: u32) -> Result<u32, EmulatorError> {
+        let mut bytes = [0u8; size_of::<u32>()];
+        self.read_data(address, &mut bytes)?;
 
-        unsafe {
-            *pointer_u16
+        match self.endianness {
+            Endianness::Little => Ok(u32::from_le_bytes(bytes)),
+            Endianness::Big => Ok(u32::from_be_bytes(bytes)),
         }
     }
 
-    pub fn read_string(&self, address: u32) -> String {
-        let length = self.read_word(address);
-        let address = (address as usize) + size_of::<u32>();
+    pub fn fetch_instruction(&self, address: u32) -> Result<u32, EmulatorError> {
+        let mut bytes = [0u8; size_of::<u32>()];
+        self.memory.fetch(address, &mut bytes)?;
 
-        assert!(address % std::mem::align_of::<u16>() == 0);
+        Ok(u32::from_le_bytes(bytes))
+    }
 
-        let slice = &self.memory[address..address + (length * 2) as usize];
+    pub fn read_byte(&self, address: u32) -> Result<u8, EmulatorError> {
+        let mut bytes = [0u8; size_of::<u8>()];
+        self.read_data(address, &mut bytes)?;
 
-        unsafe {
-            let slice_u16: &[u16] = slice::from_raw_parts(slice.as_ptr() as *const u16, length as usize);
-            String::from_utf16(slice_u16).unwrap()
-        }
+        Ok(bytes[0])
+    }
+
+    pub fn read_half_word(&self, address: u32) -> Result<u16, EmulatorError> {
+        let mut bytes = [0u8; size_of::<u16>()];
+        self.read_data(address, &mut bytes)?;
+
+        Ok(self.get_half_word_from_bytes(bytes))
     }
 
-    pub fn write_word(&mut self, address: u32, value: u32) {
-        let start_address = address as usize;
-        let end_address = start_address + size_of::<u32>();
-        let slice = &mut self.memory[start_address..end_address];
-        let pointer = slice.as_mut_ptr();
-        let pointer_u32 = pointer as *mut u32;
+    pub fn read_string(&self, address: u32) -> Result<String, EmulatorError> {
+        let length = self.read_word(address)?;
+        let string_address = address.wrapping_add(size_of::<u32>() as u32);
 
-        unsafe {
-            *pointer_u32 = value
+        if !(string_address as usize).is_multiple_of(std::mem::align_of::<u16>()) {
+            return Err(EmulatorError::AlignmentFault(string_address));
         }
+
+        let bytes = self.read_memory(string_address, (length as usize) * size_of::<u16>())?;
+        let characters: Vec<u16> = bytes.chunks_exact(size_of::<u16>())
+            .map(|c| self.get_half_word_from_bytes([c[0], c[1]]))
+            .collect();
+
+        String::from_utf16(&characters).map_err(|_| EmulatorError::InvalidString(address))
+    }
+
+    pub fn write_word(&mut self, address: u32, value: u32) -> Result<(), EmulatorError> {
+        let bytes = match self.endianness {
+            Endianness::Little => value.to_le_bytes(),
+            Endianness::Big => value.to_be_bytes(),
+        };
+
+        self.write_data(address, &bytes)
+    }
+
+    pub fn write_byte(&mut self, address: u32, value: u8) -> Result<(), EmulatorError> {
+        self.write_data(address, &[value])
     }
 
-    pub fn write_byte(&mut self, address: u32, value: u8) {
-        let start_address = address as usize;
-        let end_address = start_address + size_of::<u32>();
-        let slice = &mut self.memory[start_address..end_address];
-        let pointer = slice.as_mut_ptr();
+    pub fn write_half_word(&mut self, address: u32, value: u16) -> Result<(), EmulatorError> {
+        let bytes = match self.endianness {
+            Endianness::Little => value.to_le_bytes(),
+            Endianness::Big => value.to_be_bytes(),
+        };
+
+        self.write_data(address, &bytes)
+    }
 
-        unsafe {
-            *pointer = value
+    // starts or stops recording the data accesses made through this context; starting clears the record
+    pub fn set_recording_data_accesses(&mut self, record: bool) {
+        self.data_accesses = if record { Some(RefCell::new(Vec::new())) } else { None }
+    }
+
+    // returns the data accesses recorded since the last call
+    pub fn take_data_accesses(&mut self) -> Vec<DataAccess> {
+        match &self.data_accesses {
+            Some(accesses) => accesses.take(),
+            None => Vec::new(),
         }
     }
 
-    pub fn write_half_word(&mut self, address: u32, value: u16) {
-        let start_address = address as usize;
-        let end_address = start_address + size_of::<u32>();
-        let slice = &mut self.memory[start_address..end_address];
-        let pointer = slice.as_mut_ptr();
-        let pointer_u16 = pointer as *mut u16;
+    fn read_data(&self, address: u32, buffer: &mut [u8]) -> Result<(), EmulatorError> {
+        self.record_data_access(address, buffer.len(), MemoryAccess::Read);
+        self.memory.read(address, buffer)
+    }
 
-        unsafe {
-            *pointer_u16 = value
+    fn write_data(&mut self, address: u32, data: &[u8]) -> Result<(), EmulatorError> {
+        self.record_data_access(address, data.len(), MemoryAccess::Write);
+        self.memory.write(address, data)
+    }
+
+    fn record_data_access(&self, address: u32, length: usize, access: MemoryAccess) {
+        if let Some(accesses) = &self.data_accesses {
+            accesses.borrow_mut().push(DataAccess { address, length: length as u32, access });
         }
     }
 
+    fn get_half_word_from_bytes(&self, bytes: [u8; 2]) -> u16 {
+        match self.endianness {
+            Endianness::Little => u16::from_le_bytes(bytes),
+            Endianness::Big => u16::from_be_bytes(bytes),
+        }
+    }
+
+    // SVC hands control to whoever services system calls; the CPU only records the request, with the immediate from the instruction
+    pub fn request_supervisor_call(&mut self, immediate: u32) {
+        self.pending_supervisor_call = Some(immediate)
+    }
+
+    pub fn take_pending_supervisor_call(&mut self) -> Option<u32> {
+        self.pending_supervisor_call.take()
+    }
+
+    // BKPT is recorded the same way, so that a debugger or semihosting can pick it up
+    pub fn request_breakpoint(&mut self, immediate: u32) {
+        self.pending_breakpoint = Some(immediate)
+    }
+
+    pub fn take_pending_breakpoint(&mut self) -> Option<u32> {
+        self.pending_breakpoint.take()
+    }
+
     pub fn is_halted(&self) -> bool {
         self.halted
     }
@@ -181,6 +344,16 @@ impl CpuContext {
         self.halted = true
     }
 
+    // halts on behalf of the guest, recording the status it exited with
+    pub fn exit(&mut self, status: u32) {
+        self.exit_status = Some(status);
+        self.halted = true
+    }
+
+    pub fn get_exit_status(&self) -> Option<u32> {
+        self.exit_status
+    }
+
     pub fn debug_get_registers(&self) -> String {
         let mut result = String::new();
 
@@ -207,9 +380,11 @@ impl CpuContext {
     pub fn debug_get_memory_range(&self, range: &RangeInclusive<u32>) -> String {
         let mut result = String::new();
 
-        for a in range.clone().into_iter() {
-            let byte = self.read_byte(a);
-            result.push_str(&format!("{:0>2X} ", byte));
+        for a in range.clone() {
+            match self.read_byte(a) {
+                Ok(byte) => result.push_str(&format!("{:0>2X} ", byte)),
+                Err(_) => result.push_str("?? "),
+            }
         }
 
         result
@@ -0,0 +1,454 @@
+use std::io::{self, BufRead, Write};
+
+use crate::{
+    context::CpuContext,
+    decoding::decode,
+    disassembler,
+    file::Symbol,
+    machine::{Breakpoint, Machine, StopReason, Trigger, TriggerAction, WatchKind, Watchpoint},
+};
+
+const PROMPT: &str = "(rusty_arm) ";
+const HELP: &str = "\
+step [n]                    execute one or n instructions
+continue                    run until a breakpoint, a watchpoint or the end of the program
+finish                      run until the current function returns to the address in LR
+break [address|symbol]      set a breakpoint, or list the breakpoints and watchpoints
+watch <address|symbol> [n]  stop when any of the n bytes (4 by default) at the address are written
+rwatch, awatch              the same, for reads or for any access
+                            breakpoints and watchpoints can be followed by conditions: <register>=<value>, which
+                            only counts hits while the register holds the value, and hits=<n>, which only
+                            triggers from the nth hit on; and by an action: stop (the default), log or dump
+delete [address|symbol]     delete the breakpoints and watchpoints at the address, or all of them
+regs                        show the registers and flags
+x/<n>w <address|symbol>     show n words of memory; x/<n>b shows bytes
+set <register> <value>      change a register: r0-r15, sp, lr, pc or cpsr
+disas [address|symbol] [n]  show n instructions (5 by default), starting at PC
+quit                        stop the program and leave the debugger
+An empty line repeats the previous command.";
+
+// an interactive debugger reading commands from input, until the user quits or the input ends. Addresses can be
+// given as hexadecimal (0x...) or decimal numbers, or as the name of a symbol
+pub fn run_debugger<R: BufRead, W: Write>(machine: &mut Machine, symbols: &[Symbol], input: R, mut output: W) -> io::Result<()> {
+    let mut lines = input.lines();
+    let mut previous = String::new();
+
+    show_location(machine.get_context(), symbols, &mut output)?;
+
+    loop {
+        write!(output, "{}", PROMPT)?;
+        output.flush()?;
+
+        let line = match lines.next() {
+            Some(line) => line?,
+            None => return Ok(()),
+        };
+
+        let line = if line.trim().is_empty() { previous.clone() } else { String::from(line.trim()) };
+        previous = line.clone();
+
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (command, arguments) = match words.split_first() {
+            Some((command, arguments)) => (*command, arguments),
+            None => continue,
+        };
+
+        match command {
+            "quit" | "q" => return Ok(()),
+            "help" | "h" => writeln!(output, "{}", HELP)?,
+            "step" | "s" | "stepi" | "si" => {
+                match arguments.first().map(|a| parse_number(a)) {
+                    None => resume(machine, symbols, &mut output, Resume::Step(1))?,
+                    Some(Some(count)) => resume(machine, symbols, &mut output, Resume::Step(count as u64))?,
+                    Some(None) => writeln!(output, "Invalid instruction count {}.", arguments[0])?,
+                }
+            },
+            "continue" | "c" => resume(machine, symbols, &mut output, Resume::Continue)?,
+            "finish" => {
+                let return_address = machine.get_context().get_register(CpuContext::get_link_return_register());
+                resume(machine, symbols, &mut output, Resume::Until(return_address))?
+            },
+            "break" | "b" => match arguments.first() {
+                None => list_breakpoints(machine, symbols, &mut output)?,
+                Some(_) => match parse_breakpoint(arguments, symbols) {
+                    Ok(breakpoint) => {
+                        machine.add_breakpoint(breakpoint);
+                        writeln!(output, "Breakpoint at {}.", describe_address(breakpoint.address, symbols))?
+                    },
+                    Err(e) => writeln!(output, "{}", e)?,
+                },
+            },
+            "watch" | "rwatch" | "awatch" => {
+                let kind = match command {
+                    "rwatch" => WatchKind::Read,
+                    "awatch" => WatchKind::Access,
+                    _ => WatchKind::Write,
+                };
+
+                match parse_watchpoint(arguments, kind, symbols) {
+                    Ok(watchpoint) => {
+                        machine.add_watchpoint(watchpoint);
+                        writeln!(output, "Watchpoint on {} bytes at {}.", get_length(&watchpoint), describe_address(watchpoint.start, symbols))?
+                    },
+                    Err(e) => writeln!(output, "{}", e)?,
+                }
+            },
+            "delete" | "d" => match arguments.first() {
+                None => {
+                    for breakpoint in machine.get_breakpoints().to_vec() {
+                        machine.remove_breakpoint(breakpoint.address);
+                    }
+                    for watchpoint in machine.get_watchpoints().to_vec() {
+                        machine.remove_watchpoint(watchpoint.start, watchpoint.end, watchpoint.kind);
+                    }
+                    writeln!(output, "Deleted all breakpoints and watchpoints.")?
+                },
+                Some(argument) => match parse_address(argument, symbols) {
+                    Some(address) => {
+                        let mut deleted = machine.remove_breakpoint(address);
+                        for watchpoint in machine.get_watchpoints().to_vec().into_iter().filter(|w| w.start == address) {
+                            deleted |= machine.remove_watchpoint(watchpoint.start, watchpoint.end, watchpoint.kind);
+                        }
+
+                        if deleted {
+                            writeln!(output, "Deleted the breakpoints and watchpoints at {}.", describe_address(address, symbols))?
+                        } else {
+                            writeln!(output, "There is no breakpoint or watchpoint at {}.", describe_address(address, symbols))?
+                        }
+                    },
+                    None => writeln!(output, "Unknown address {}.", argument)?,
+                },
+            },
+            "regs" | "r" => {
+                let context = machine.get_context();
+                writeln!(output, "{}\n{}", context.debug_get_registers(), context.debug_get_status())?
+            },
+            "set" => match (arguments.first(), arguments.get(1).and_then(|v| parse_address(v, symbols))) {
+                (Some(register), Some(value)) => {
+                    if !set_register(machine.get_context_mut(), register, value) {
+                        writeln!(output, "Unknown register {}.", register)?
+                    }
+                },
+                _ => writeln!(output, "Usage: set <register> <value>")?,
+            },
+            "disas" => {
+                let address = match arguments.first() {
+                    Some(argument) => parse_address(argument, symbols),
+                    None => Some(machine.get_context().get_program_counter()),
+                };
+                let count = arguments.get(1).and_then(|c| parse_number(c)).unwrap_or(DEFAULT_DISASSEMBLY_COUNT);
+
+                match address {
+                    Some(address) => disassemble(machine.get_context(), symbols, &mut output, address, count)?,
+                    None => writeln!(output, "Unknown address {}.", arguments[0])?,
+                }
+            },
+            _ if command.starts_with("x/") || command == "x" => examine(machine.get_context(), symbols, &mut output, command, arguments)?,
+            _ => writeln!(output, "Unknown command {}; try help.", command)?,
+        }
+    }
+}
+
+enum Resume {
+    Step(u64),
+    Continue,
+    Until(u32),     // run until PC reaches the address
+}
+
+fn resume<W: Write>(machine: &mut Machine, symbols: &[Symbol], output: &mut W, resume: Resume) -> io::Result<()> {
+    if machine.is_halted() {
+        return writeln!(output, "The program has exited.");
+    }
+
+    let result = match resume {
+        Resume::Step(count) => machine.run_for(count),
+        Resume::Continue => machine.run(),
+        Resume::Until(address) => machine.run_until(|c| c.get_program_counter() == address),
+    };
+
+    match result {
+        Ok(StopReason::Halted) => {
+            return match machine.get_exit_status() {
+                Some(status) => writeln!(output, "The program exited with status {}.", status),
+                None => writeln!(output, "The program halted."),
+            };
+        },
+        Ok(StopReason::Breakpoint(address)) => writeln!(output, "Breakpoint at {}.", describe_address(address, symbols))?,
+        Ok(StopReason::Watchpoint { address, access }) => writeln!(output, "Watchpoint: {} at {}.", access, describe_address(address, symbols))?,
+        Ok(StopReason::ConditionMet) | Ok(StopReason::CycleLimitReached) => {},
+        Err(e) => writeln!(output, "The program crashed: {}", e)?,
+    }
+
+    show_location(machine.get_context(), symbols, output)
+}
+
+fn show_location<W: Write>(context: &CpuContext, symbols: &[Symbol], output: &mut W) -> io::Result<()> {
+    disassemble(context, symbols, output, context.get_program_counter(), 1)
+}
+
+fn list_breakpoints<W: Write>(machine: &Machine, symbols: &[Symbol], output: &mut W) -> io::Result<()> {
+    if machine.get_breakpoints().is_empty() && machine.get_watchpoints().is_empty() {
+        return writeln!(output, "There are no breakpoints or watchpoints.");
+    }
+
+    for breakpoint in machine.get_breakpoints() {
+        writeln!(output, "Breakpoint at {}{}", describe_address(breakpoint.address, symbols), describe_trigger(&breakpoint.trigger))?;
+    }
+
+    for watchpoint in machine.get_watchpoints() {
+        writeln!(output, "Watchpoint ({:?}) on {} bytes at {}{}", watchpoint.kind, get_length(watchpoint),
+            describe_address(watchpoint.start, symbols), describe_trigger(&watchpoint.trigger))?;
+    }
+
+    Ok(())
+}
+
+fn describe_trigger(trigger: &Trigger) -> String {
+    let mut description = format!(", hit {} times", trigger.get_hits());
+
+    if let Some((register, value)) = trigger.register_value {
+        description += &format!(", when r{} = {:0>8X}", register, value);
+    }
+
+    if let Some(hit_count) = trigger.hit_count {
+        description += &format!(", from hit {} on", hit_count);
+    }
+
+    match trigger.action {
+        TriggerAction::Stop => description,
+        TriggerAction::Log => description + ", logs",
+        TriggerAction::Dump => description + ", dumps",
+    }
+}
+
+fn get_length(watchpoint: &Watchpoint) -> u64 {
+    watchpoint.end.wrapping_sub(watchpoint.start) as u64 + 1
+}
+
+// x/<n><unit> <address>, where the unit is w for words, read in the guest's current byte order, or b for bytes
+fn examine<W: Write>(context: &CpuContext, symbols: &[Symbol], output: &mut W, command: &str, arguments: &[&str]) -> io::Result<()> {
+    let format = command.strip_prefix("x/").unwrap_or("");
+    let (count, unit) = match format.char_indices().find(|(_, c)| !c.is_ascii_digit()) {
+        Some((index, unit)) => (&format[..index], unit),
+        None => (format, 'w'),
+    };
+    let count = if count.is_empty() { Some(1) } else { parse_number(count) };
+    let unit_size = match unit {
+        'w' => Some(WORD_SIZE),
+        'b' => Some(1),
+        _ => None,
+    };
+
+    let (address, count, unit_size) = match (arguments.first().and_then(|a| parse_address(a, symbols)), count, unit_size) {
+        (Some(address), Some(count), Some(unit_size)) => (address, count, unit_size),
+        _ => return writeln!(output, "Usage: x/<n>w <address|symbol> or x/<n>b <address|symbol>"),
+    };
+
+    let length = count.saturating_mul(unit_size);
+    let mut offset = 0;
+
+    while offset < length {
+        let start = address.wrapping_add(offset);
+        let line_length = (length - offset).min(BYTES_PER_LINE);
+
+        let text = match unit_size {
+            WORD_SIZE => (0..line_length / WORD_SIZE)
+                .map(|index| match context.read_word(start.wrapping_add(index * WORD_SIZE)) {
+                    Ok(word) => format!("{:0>8X}", word),
+                    Err(_) => String::from("????????"),
+                })
+                .collect::<Vec<_>>()
+                .join(" "),
+            _ => String::from(context.debug_get_memory_range(&(start..=start.wrapping_add(line_length - 1))).trim_end()),
+        };
+
+        writeln!(output, "{:0>8X}: {}", start, text)?;
+        offset += line_length;
+    }
+
+    Ok(())
+}
+
+fn disassemble<W: Write>(context: &CpuContext, symbols: &[Symbol], output: &mut W, address: u32, count: u32) -> io::Result<()> {
+    for index in 0..count {
+        let address = address.wrapping_add(index * WORD_SIZE);
+        let marker = if address == context.get_program_counter() { "=>" } else { "  " };
+
+        let text = match context.fetch_instruction(address) {
+            Ok(instruction) => match decode(instruction) {
+                Ok(decoded) => format!("{:0>8X}  {}", instruction, disassembler::disassemble(&decoded, address, symbols)),
+                Err(e) => format!("{:0>8X}  ({})", instruction, e),
+            },
+            Err(e) => format!("({})", e),
+        };
+
+        writeln!(output, "{} {}: {}", marker, describe_address(address, symbols), text)?;
+    }
+
+    Ok(())
+}
+
+fn set_register(context: &mut CpuContext, name: &str, value: u32) -> bool {
+    if name.eq_ignore_ascii_case("cpsr") {
+        context.set_program_status_register(value);
+        return true;
+    }
+
+    match parse_register(name) {
+        Some(register) => {
+            context.set_register(register, value);
+            true
+        },
+        None => false,
+    }
+}
+
+// r0 to r15, sp, lr or pc
+fn parse_register(name: &str) -> Option<u8> {
+    match name.to_ascii_lowercase().as_str() {
+        "sp" => Some(13),
+        "lr" => Some(14),
+        "pc" => Some(15),
+        name => name.strip_prefix('r').and_then(|n| n.parse::<u8>().ok()).filter(|&r| r <= 15),
+    }
+}
+
+// parses an address followed by options: <register>=<value> and hits=<n> conditions, and a stop, log or dump action.
+// The command line separates these with commas, the debugger with spaces
+pub fn parse_breakpoint(words: &[&str], symbols: &[Symbol]) -> Result<Breakpoint, String> {
+    let (address, options) = parse_location(words, symbols)?;
+    let mut breakpoint = Breakpoint::create(address);
+
+    for option in options {
+        if !parse_trigger_option(option, &mut breakpoint.trigger, symbols)? {
+            return Err(format!("Unknown breakpoint option {}.", option));
+        }
+    }
+
+    Ok(breakpoint)
+}
+
+// like parse_breakpoint, but a number right after the address is the length of the range to watch (4 by default), and
+// read, write and access override the kind
+pub fn parse_watchpoint(words: &[&str], kind: WatchKind, symbols: &[Symbol]) -> Result<Watchpoint, String> {
+    let (start, mut options) = parse_location(words, symbols)?;
+    let mut length = WORD_SIZE;
+
+    if let Some(value) = options.first().and_then(|o| parse_number(o)) {
+        if value == 0 {
+            return Err(String::from("A watchpoint needs a length of at least 1."));
+        }
+
+        length = value;
+        options = &options[1..];
+    }
+
+    let mut watchpoint = Watchpoint::create(start, start.wrapping_add(length - 1), kind);
+
+    for &option in options {
+        match option {
+            "read" => watchpoint.kind = WatchKind::Read,
+            "write" => watchpoint.kind = WatchKind::Write,
+            "access" => watchpoint.kind = WatchKind::Access,
+            _ if parse_trigger_option(option, &mut watchpoint.trigger, symbols)? => {},
+            _ => return Err(format!("Unknown watchpoint option {}.", option)),
+        }
+    }
+
+    Ok(watchpoint)
+}
+
+fn parse_location<'a, 'b>(words: &'a [&'b str], symbols: &[Symbol]) -> Result<(u32, &'a [&'b str]), String> {
+    match words.split_first() {
+        Some((address, options)) => match parse_address(address, symbols) {
+            Some(address) => Ok((address, options)),
+            None => Err(format!("Unknown address {}.", address)),
+        },
+        None => Err(String::from("An address is required.")),
+    }
+}
+
+// returns whether the option is one of the trigger's
+fn parse_trigger_option(option: &str, trigger: &mut Trigger, symbols: &[Symbol]) -> Result<bool, String> {
+    match option {
+        "stop" => trigger.action = TriggerAction::Stop,
+        "log" => trigger.action = TriggerAction::Log,
+        "dump" => trigger.action = TriggerAction::Dump,
+        _ => {
+            let (name, value) = match option.split_once('=') {
+                Some(pair) => pair,
+                None => return Ok(false),
+            };
+
+            if name == "hits" {
+                match parse_number(value) {
+                    Some(count) => trigger.hit_count = Some(count.into()),
+                    None => return Err(format!("Invalid hit count {}.", value)),
+                }
+            } else {
+                let register = match parse_register(name) {
+                    Some(register) => register,
+                    None => return Ok(false),
+                };
+
+                match parse_address(value, symbols) {
+                    Some(value) => trigger.register_value = Some((register, value)),
+                    None => return Err(format!("Invalid value {}.", value)),
+                }
+            }
+        },
+    }
+
+    Ok(true)
+}
+
+// the address, followed by the symbol it's in, if any
+fn describe_address(address: u32, symbols: &[Symbol]) -> String {
+    match disassembler::get_symbol_label(address, symbols) {
+        Some(label) => format!("{:0>8X} {}", address, label),
+        None => format!("{:0>8X}", address),
+    }
+}
+
+fn parse_address(value: &str, symbols: &[Symbol]) -> Option<u32> {
+    parse_number(value).or_else(|| symbols.iter().find(|s| s.name == value).map(|s| s.address))
+}
+
+fn parse_number(value: &str) -> Option<u32> {
+    match value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => value.parse::<u32>().ok(),
+    }
+}
+
+const WORD_SIZE: u32 = 4;
+const BYTES_PER_LINE: u32 = 16;
+const DEFAULT_DISASSEMBLY_COUNT: u32 = 5;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::{CpuConfiguration, Endianness};
+
+    fn run_commands(machine: &mut Machine, commands: &str) -> String {
+        let mut output = Vec::new();
+        run_debugger(machine, &[], commands.as_bytes(), &mut output).unwrap();
+
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn examine_shows_words_in_the_current_byte_order() {
+        let mut machine = Machine::create(CpuConfiguration::default());
+        machine.get_context_mut().write_memory(0x1000, &[0x78, 0x56, 0x34, 0x12, 0xef, 0xbe, 0xad, 0xde]).unwrap();
+
+        let output = run_commands(&mut machine, "x/2w 0x1000\nx/2b 0x1000\n");
+        assert!(output.contains("00001000: 12345678 DEADBEEF\n"));
+        assert!(output.contains("00001000: 78 56\n"));
+
+        machine.get_context_mut().set_endianness(Endianness::Big);
+
+        let output = run_commands(&mut machine, "x/2w 0x1000\n");
+        assert!(output.contains("00001000: 78563412 EFBEADDE\n"));
+    }
+}
@@ -1,12 +1,20 @@
-use core::panic;
 use instructions::*;
 
 use ux::{self, u12, u24, u4, u5};
 
-use crate::instructions;
+use crate::{context::{CpuContext, Endianness}, error::EmulatorError, instructions};
 
-pub fn decode(encoded_instruction: u32) -> Result<Instruction, String> {
-    let condition = decode_condition(encoded_instruction);
+pub fn decode(encoded_instruction: u32) -> Result<Instruction, EmulatorError> {
+    decode_instruction(encoded_instruction)
+        .map_err(|message| EmulatorError::UnknownInstruction { instruction: encoded_instruction, message })
+}
+
+fn decode_instruction(encoded_instruction: u32) -> Result<Instruction, String> {
+    if encoded_instruction & CONDITION_MASK == UNCONDITIONAL_INSTRUCTIONS {
+        return decode_unconditional_instruction(encoded_instruction);
+    }
+
+    let condition = decode_condition(encoded_instruction)?;
     let instruction_class = encoded_instruction & INSTRUCTION_CLASS_MASK;
 
     match instruction_class {
@@ -18,9 +26,13 @@ pub fn decode(encoded_instruction: u32) -> Result<Instruction, String> {
             Ok((condition, data))
         },
         DATA_PROCESSING_REGISTER_INSTRUCTION_CLASS => {
+            let multiply = encoded_instruction & MULTIPLY_MASK == MULTIPLY_VALUE;
             let extra_loads_stores = encoded_instruction & EXTRA_LOAD_STORES_FLAG == EXTRA_LOAD_STORES_FLAG;
 
-            if !extra_loads_stores {
+            if multiply {
+                let data = decode_multiply(encoded_instruction)?;
+                Ok((condition, data))
+            } else if !extra_loads_stores {
                 let data = decode_data_processing_instruction(encoded_instruction)?;
                 Ok((condition, data))
             } else {
@@ -28,26 +40,47 @@ pub fn decode(encoded_instruction: u32) -> Result<Instruction, String> {
                 Ok((condition, data))
             }
         },
+        LOAD_STORE_REGISTER_INSTRUCTION_CLASS if encoded_instruction & MEDIA_INSTRUCTIONS_FLAG != 0 => {
+            let data = decode_media_instruction(encoded_instruction)?;
+
+            Ok((condition, data))
+        },
         LOAD_STORE_IMMEDIATE_INSTRUCTION_CLASS | LOAD_STORE_REGISTER_INSTRUCTION_CLASS => {
             let data = decode_regular_load_store(encoded_instruction);
 
             Ok((condition, data))
         },
+        BLOCK_TRANSFER_INSTRUCTION_CLASS => {
+            let data = decode_block_transfer(encoded_instruction)?;
+
+            Ok((condition, data))
+        },
         SUPERVISOR_CALL_INSTRUCTION_CLASS if (encoded_instruction & 0x0f000000) == 0x0f000000 => {
             let immediate = u24::new(encoded_instruction & 0x00ffffff);
             Ok((condition, InstructionData::SupervisorCall(immediate)))
         },
         _ => {
-            Err(format!("Unknown instruction {:0>8X}", encoded_instruction))
+            Err(String::from("Unknown instruction"))
         }
     }
 }
 
-fn decode_condition(encoded_instruction: u32) -> Condition {
+// instructions with the condition field set to 0b1111 are executed unconditionally
+fn decode_unconditional_instruction(encoded_instruction: u32) -> Result<Instruction, String> {
+    if encoded_instruction & SET_ENDIANNESS_MASK == SET_ENDIANNESS_VALUE {
+        let endianness = if encoded_instruction & SET_ENDIANNESS_BIG_ENDIAN_BIT != 0 { Endianness::Big } else { Endianness::Little };
+
+        return Ok((Condition::Always, InstructionData::SetEndianness(endianness)));
+    }
+
+    Err(String::from("Unknown unconditional instruction"))
+}
+
+fn decode_condition(encoded_instruction: u32) -> Result<Condition, String> {
     let masked_condition = encoded_instruction & CONDITION_MASK;
     let condition_byte = (masked_condition >> 28) as u8;
 
-    match condition_byte {
+    let condition = match condition_byte {
         EQUAL_CONDITION => Condition::Equal,
         NOT_EQUAL_CONDITION => Condition::NotEqual,
         CARRY_SET_CONDITION => Condition::CarrySet,
@@ -63,30 +96,43 @@ fn decode_condition(encoded_instruction: u32) -> Condition {
         GREATER_THAN_CONDITION => Condition::GreaterThan,
         LESS_THAN_OR_EQUAL_CONDITION => Condition::LessThanOrEqual,
         ALWAYS_CONDITION => Condition::Always,
-        _ => panic!("Unknown condition {:0>2X} (instruction: {:0>8X})", condition_byte, encoded_instruction),
-    }
+        _ => return Err(format!("Unknown condition {:0>2X}", condition_byte)),
+    };
+
+    Ok(condition)
 }
 
 fn decode_data_processing_instruction(encoded_instruction: u32) -> Result<InstructionData, String> {
-    let update_status_flag = if (encoded_instruction & UPDATE_STATUS_BIT) != 0 {
+    let sets_status_flags = (encoded_instruction & UPDATE_STATUS_BIT) != 0;
+    let update_status_flag = if sets_status_flags {
         UpdateStatusFlags::UpdateStatusFlags
     } else {
         UpdateStatusFlags::DoNotUpdateStatusFlags
     };
     let opcode = ((encoded_instruction & OPCODE_MASK) >> 21) as u8;
 
+    // TST, TEQ, CMP and CMN always set the flags; with the S bit clear, the same opcodes encode miscellaneous instructions
     match opcode {
         ADD_OPCODE => Ok(InstructionData::Add(decode_read_write_arguments(encoded_instruction), update_status_flag)),
         ADD_WITH_CARRY_OPCODE => Ok(InstructionData::AddWithCarry(decode_read_write_arguments(encoded_instruction), update_status_flag)),
         AND_OPCODE => Ok(InstructionData::And(decode_read_write_arguments(encoded_instruction), update_status_flag)),
-        BRANCH_EXCHANGE_OPCODE => Ok(InstructionData::BranchExchange(decode_branch_exchange_arguments(encoded_instruction))),
-        COMPARE_OPCODE => Ok(InstructionData::Compare(decode_read_arguments(encoded_instruction))),
+        BIT_CLEAR_OPCODE => Ok(InstructionData::BitClear(decode_read_write_arguments(encoded_instruction), update_status_flag)),
+        BREAKPOINT_OPCODE if !sets_status_flags && (encoded_instruction & BREAKPOINT_MASK) == BREAKPOINT_VALUE => Ok(InstructionData::Breakpoint(decode_breakpoint_immediate(encoded_instruction))),
+        BRANCH_EXCHANGE_OPCODE if !sets_status_flags => Ok(InstructionData::BranchExchange(decode_branch_exchange_arguments(encoded_instruction))),
+        COMPARE_OPCODE if sets_status_flags => Ok(InstructionData::Compare(decode_read_arguments(encoded_instruction))),
+        COMPARE_NEGATIVE_OPCODE if sets_status_flags => Ok(InstructionData::CompareNegative(decode_read_arguments(encoded_instruction))),
+        EXCLUSIVE_OR_OPCODE => Ok(InstructionData::ExclusiveOr(decode_read_write_arguments(encoded_instruction), update_status_flag)),
         MOVE_OPCODE => Ok(InstructionData::Move(decode_write_arguments(encoded_instruction), update_status_flag)),
         MOVE_NOT_OPCODE => Ok(InstructionData::MoveNot(decode_write_arguments(encoded_instruction), update_status_flag)),
-        MOVE_STATUS_TO_REGISTER_OPCODE => Ok(InstructionData::MoveStatusToRegister(decode_destination_register(encoded_instruction))),
+        MOVE_STATUS_TO_REGISTER_OPCODE if !sets_status_flags => Ok(InstructionData::MoveStatusToRegister(decode_destination_register(encoded_instruction))),
         OR_OPCODE => Ok(InstructionData::Or(decode_read_write_arguments(encoded_instruction), update_status_flag)),
+        REVERSE_SUBTRACT_OPCODE => Ok(InstructionData::ReverseSubtract(decode_read_write_arguments(encoded_instruction), update_status_flag)),
+        REVERSE_SUBTRACT_WITH_CARRY_OPCODE => Ok(InstructionData::ReverseSubtractWithCarry(decode_read_write_arguments(encoded_instruction), update_status_flag)),
         SUBTRACT_OPCODE => Ok(InstructionData::Subtract(decode_read_write_arguments(encoded_instruction), update_status_flag)),
-        _ => Err(format!("Unknown data processing opcode {:0>2X} (instruction: {:0>8X})", opcode, encoded_instruction))
+        SUBTRACT_WITH_CARRY_OPCODE => Ok(InstructionData::SubtractWithCarry(decode_read_write_arguments(encoded_instruction), update_status_flag)),
+        TEST_OPCODE if sets_status_flags => Ok(InstructionData::Test(decode_read_arguments(encoded_instruction))),
+        TEST_EQUIVALENCE_OPCODE if sets_status_flags => Ok(InstructionData::TestEquivalence(decode_read_arguments(encoded_instruction))),
+        _ => Err(format!("Unknown data processing opcode {:0>2X}", opcode))
     }
 }
 
@@ -96,7 +142,66 @@ fn decode_extra_data_processing_immediate_instruction(encoded_instruction: u32)
     match opcode {
         MOVE_HALFWORD_OPCODE => Ok(InstructionData::MoveHalfWord(decode_large_immediate_arguments(encoded_instruction))),
         MOVE_HALFWORD_TOP_OPCODE => Ok(InstructionData::MoveHalfWordTop(decode_large_immediate_arguments(encoded_instruction))),
-        _ => Err(format!("Unknown extra data processing opcode {:0>2X} (instruction: {:0>8X})", opcode, encoded_instruction))
+        _ => Err(format!("Unknown extra data processing opcode {:0>2X}", opcode))
+    }
+}
+
+fn decode_multiply(encoded_instruction: u32) -> Result<InstructionData, String> {
+    let sets_status_flags = (encoded_instruction & UPDATE_STATUS_BIT) != 0;
+    let update_status_flag = if sets_status_flags {
+        UpdateStatusFlags::UpdateStatusFlags
+    } else {
+        UpdateStatusFlags::DoNotUpdateStatusFlags
+    };
+    let opcode = ((encoded_instruction & MULTIPLY_OPCODE_MASK) >> 21) as u8;
+
+    let high_register: Register = u4::new(((encoded_instruction & 0x000f0000) >> 16) as u8);
+    let low_register: Register = u4::new(((encoded_instruction & 0x0000f000) >> 12) as u8);
+    let second_operand_register: Register = u4::new(((encoded_instruction & 0x00000f00) >> 8) as u8);
+    let first_operand_register: Register = u4::new((encoded_instruction & 0x0000000f) as u8);
+
+    let multiply_arguments = || MultiplyArguments {
+        destination_register: high_register,
+        first_operand_register,
+        second_operand_register,
+    };
+    let multiply_accumulate_arguments = || MultiplyAccumulateArguments {
+        destination_register: high_register,
+        first_operand_register,
+        second_operand_register,
+        accumulate_register: low_register,
+    };
+    let long_multiply_arguments = || LongMultiplyArguments {
+        destination_register_low: low_register,
+        destination_register_high: high_register,
+        first_operand_register,
+        second_operand_register,
+    };
+
+    match opcode {
+        MULTIPLY_OPCODE => Ok(InstructionData::Multiply(multiply_arguments(), update_status_flag)),
+        MULTIPLY_ACCUMULATE_OPCODE => Ok(InstructionData::MultiplyAccumulate(multiply_accumulate_arguments(), update_status_flag)),
+        UNSIGNED_MULTIPLY_ACCUMULATE_ACCUMULATE_LONG_OPCODE if !sets_status_flags => Ok(InstructionData::UnsignedMultiplyAccumulateAccumulateLong(long_multiply_arguments())),
+        MULTIPLY_SUBTRACT_OPCODE if !sets_status_flags => Ok(InstructionData::MultiplySubtract(multiply_accumulate_arguments())),
+        UNSIGNED_MULTIPLY_LONG_OPCODE => Ok(InstructionData::UnsignedMultiplyLong(long_multiply_arguments(), update_status_flag)),
+        UNSIGNED_MULTIPLY_ACCUMULATE_LONG_OPCODE => Ok(InstructionData::UnsignedMultiplyAccumulateLong(long_multiply_arguments(), update_status_flag)),
+        SIGNED_MULTIPLY_LONG_OPCODE => Ok(InstructionData::SignedMultiplyLong(long_multiply_arguments(), update_status_flag)),
+        SIGNED_MULTIPLY_ACCUMULATE_LONG_OPCODE => Ok(InstructionData::SignedMultiplyAccumulateLong(long_multiply_arguments(), update_status_flag)),
+        _ => Err(format!("Unknown multiply opcode {:0>2X}", opcode))
+    }
+}
+
+fn decode_media_instruction(encoded_instruction: u32) -> Result<InstructionData, String> {
+    let divide_arguments = || DivideArguments {
+        destination_register: u4::new(((encoded_instruction & 0x000f0000) >> 16) as u8),
+        dividend_register: u4::new((encoded_instruction & 0x0000000f) as u8),
+        divisor_register: u4::new(((encoded_instruction & 0x00000f00) >> 8) as u8),
+    };
+
+    match encoded_instruction & MEDIA_OPCODE_MASK {
+        SIGNED_DIVIDE_OPCODE => Ok(InstructionData::SignedDivide(divide_arguments())),
+        UNSIGNED_DIVIDE_OPCODE => Ok(InstructionData::UnsignedDivide(divide_arguments())),
+        _ => Err(String::from("Unknown media instruction"))
     }
 }
 
@@ -136,15 +241,14 @@ fn decode_regular_load_store(encoded_instruction: u32) -> InstructionData {
             0b01 => ShiftType::LogicalShiftRight,
             0b10 => ShiftType::ArithmeticShiftRight,
             0b11 => ShiftType::RotateRight,
-            _ => panic!("Impossible shift type {}", shift_type),
+            _ => unreachable!("Impossible shift type {}", shift_type),
         };
         let shift_operand = u5::new(((encoded_instruction & 0x00000f80) >> 7) as u8);
         let register = u4::new((encoded_instruction & 0x0000000f) as u8);
 
-        let shift_operand = match shift_type {
-            ShiftType::LogicalShiftLeft => shift_operand,
-            _ if shift_operand == u5::new(0) => u5::new(32),
-            _ => shift_operand,
+        let shift_type = match shift_type {
+            ShiftType::RotateRight if shift_operand == u5::new(0) => ShiftType::RotateRightExtended,
+            _ => shift_type,
         };
 
         LoadStoreOffset::Register(
@@ -178,6 +282,45 @@ fn decode_regular_load_store(encoded_instruction: u32) -> InstructionData {
     }
 }
 
+fn decode_block_transfer(encoded_instruction: u32) -> Result<InstructionData, String> {
+    let before = encoded_instruction & 0x01000000 != 0;
+    let increment = encoded_instruction & 0x00800000 != 0;
+    let user_registers = encoded_instruction & 0x00400000 != 0;
+    let write_back = if encoded_instruction & 0x00200000 != 0 { LoadStoreWriteBackFlag::WriteBack } else { LoadStoreWriteBackFlag::DoNotWriteBack };
+    let load_operation = encoded_instruction & 0x00100000 != 0;
+
+    let address_register: Register = u4::new(((encoded_instruction & 0x000f0000) >> 16) as u8);
+    let register_list = (encoded_instruction & 0x0000ffff) as u16;
+
+    if user_registers {
+        return Err(String::from("Block transfers of user mode registers are not supported"));
+    }
+
+    if register_list == 0 {
+        return Err(String::from("Block transfer with empty register list"));
+    }
+
+    let addressing_mode = match (increment, before) {
+        (true, false) => BlockTransferAddressingMode::IncrementAfter,
+        (true, true) => BlockTransferAddressingMode::IncrementBefore,
+        (false, false) => BlockTransferAddressingMode::DecrementAfter,
+        (false, true) => BlockTransferAddressingMode::DecrementBefore,
+    };
+
+    let args = BlockTransferArguments {
+        addressing_mode,
+        write_back,
+        address_register,
+        register_list,
+    };
+
+    if load_operation {
+        Ok(InstructionData::LoadMultiple(args))
+    } else {
+        Ok(InstructionData::StoreMultiple(args))
+    }
+}
+
 fn decode_extra_load_store(encoded_instruction: u32) -> Result<InstructionData, String> {
     let indexing_type = if encoded_instruction & 0x01000000 != 0 { LoadStoreIndexingType::PreIndexed } else { LoadStoreIndexingType::PostIndexed };
     let offset_direction = if encoded_instruction & 0x00800000 != 0 { LoadStoreOffsetDirection::Positive } else { LoadStoreOffsetDirection::Negative };
@@ -218,6 +361,11 @@ fn decode_extra_load_store(encoded_instruction: u32) -> Result<InstructionData,
         offset
     };
 
+    // LDRD and STRD live in the store half of the encoding space
+    if !load_operation && data_size & 0b10 != 0 {
+        validate_doubleword_registers(&common_arguments)?;
+    }
+
     return match (load_operation, data_size) {
         (false, 0b01) => get_store_instruction(StoreDataSize::HalfWord, common_arguments),
         (false, 0b10) => get_load_instruction(LoadDataSize::DoubleWord, common_arguments),
@@ -243,6 +391,34 @@ fn decode_extra_load_store(encoded_instruction: u32) -> Result<InstructionData,
     }
 }
 
+// shared with the encoder, which has to refuse the same register pairs
+pub fn validate_doubleword_registers(args: &LoadStoreArguments) -> Result<(), String> {
+    let first_register: u8 = args.value_register.into();
+    let second_register = first_register + 1;
+    let address_register: u8 = args.address_register.into();
+
+    // the register pair is Rt, Rt + 1; Rt must be even and the pair may not include PC
+    if first_register & 1 != 0 || second_register == CpuContext::get_program_counter_register() {
+        return Err(String::from("Doubleword transfer requires an even register below R14"));
+    }
+
+    if let LoadStoreWriteBackFlag::WriteBack = args.write_back {
+        if address_register == first_register || address_register == second_register || address_register == CpuContext::get_program_counter_register() {
+            return Err(String::from("Doubleword transfer cannot write back to a transferred register or PC"));
+        }
+    }
+
+    if let LoadStoreOffset::Register(ref offset) = args.offset {
+        let offset_register: u8 = offset.register.into();
+
+        if offset_register == CpuContext::get_program_counter_register() {
+            return Err(String::from("Doubleword transfer cannot use PC as offset register"));
+        }
+    }
+
+    Ok(())
+}
+
 fn decode_write_arguments(encoded_instruction: u32) -> DataArguments {
     let immediate_mode = encoded_instruction & IMMEDIATE_MODE_BIT != 0;
     let register: Register = u4::new(((encoded_instruction & 0x0000f000) >> 12) as u8);
@@ -275,7 +451,7 @@ fn decode_write_arguments(encoded_instruction: u32) -> DataArguments {
 fn decode_shifted_immediate(encoded_instruction: u32) -> (u32, bool, u8) {
     // rotate is encoded as rotate / 2, so this is >> 8, << 1
     let rotate = ((encoded_instruction & 0x00000f00) >> 7) as u8;
-    let immediate = (encoded_instruction & 0x000000ff) as u32;
+    let immediate = encoded_instruction & 0x000000ff;
 
     let immediate = immediate.rotate_right(rotate as u32);
     let carry = immediate & 0x80000000 != 0;
@@ -367,8 +543,9 @@ fn decode_register_shift_arguments(encoded_instruction: u32) -> (Register, Shift
         SHIFT_TYPE_LOGICAL_SHIFT_LEFT => ShiftType::LogicalShiftLeft,
         SHIFT_TYPE_LOGICAL_SHIFT_RIGHT => ShiftType::LogicalShiftRight,
         SHIFT_TYPE_ARITHMETIC_SHIFT_RIGHT => ShiftType::ArithmeticShiftRight,
+        SHIFT_TYPE_ROTATE_RIGHT if immediate_shift && encoded_instruction & 0x00000f80 == 0 => ShiftType::RotateRightExtended,
         SHIFT_TYPE_ROTATE_RIGHT => ShiftType::RotateRight,
-        _ => panic!("Unknown shift type {:0>2X} (instruction: {:0>8X})", shift_type, encoded_instruction),
+        _ => unreachable!("Unknown shift type {:0>2X}", shift_type),
     };
 
     (operand_register, shift_type, shift_operand)
@@ -389,6 +566,11 @@ fn decode_branch_exchange_arguments(encoded_instruction: u32) -> Register {
     u4::new((encoded_instruction & 0x0000000f) as u8)
 }
 
+// BKPT splits its 16-bit immediate into bits 19-8 and 3-0
+fn decode_breakpoint_immediate(encoded_instruction: u32) -> u16 {
+    (((encoded_instruction & 0x000fff00) >> 4) | (encoded_instruction & 0x0000000f)) as u16
+}
+
 fn decode_destination_register(encoded_instruction: u32) -> Register {
     u4::new(((encoded_instruction & 0x0000f000) >> 12) as u8)
 }
@@ -409,36 +591,94 @@ const GREATER_THAN_CONDITION: u8 = 0xc;
 const LESS_THAN_OR_EQUAL_CONDITION: u8 = 0xd;
 const ALWAYS_CONDITION: u8 = 0xe;
 const CONDITION_MASK: u32 = 0xf0000000;
+const UNCONDITIONAL_INSTRUCTIONS: u32 = 0xf0000000;
+const SET_ENDIANNESS_MASK: u32 = 0xfffffdff;
+const SET_ENDIANNESS_VALUE: u32 = 0xf1010000;
+const SET_ENDIANNESS_BIG_ENDIAN_BIT: u32 = 0x00000200;
 const INSTRUCTION_CLASS_MASK: u32 = 0x0e000000;
 const BRANCH_INSTRUCTION_CLASS: u32 = 0x0a000000;
+const BLOCK_TRANSFER_INSTRUCTION_CLASS: u32 = 0x08000000;
 const DATA_PROCESSING_REGISTER_INSTRUCTION_CLASS: u32 = 0x00000000;
 const DATA_PROCESSING_IMMEDIATE_INSTRUCTION_CLASS: u32 = 0x02000000;
 const LOAD_STORE_IMMEDIATE_INSTRUCTION_CLASS: u32 = 0x04000000;
-const LOAD_STORE_REGISTER_INSTRUCTION_CLASS: u32 = 0x05000000;
+const LOAD_STORE_REGISTER_INSTRUCTION_CLASS: u32 = 0x06000000;
 const SUPERVISOR_CALL_INSTRUCTION_CLASS: u32 = 0x0e000000;
 const EXTRA_LOAD_STORES_FLAG: u32 = 0x00000090;
+const MULTIPLY_MASK: u32 = 0x0f0000f0;
+const MULTIPLY_VALUE: u32 = 0x00000090;
+const MULTIPLY_OPCODE_MASK: u32 = 0x00e00000;
+const MEDIA_INSTRUCTIONS_FLAG: u32 = 0x00000010;
+const MEDIA_OPCODE_MASK: u32 = 0x01f0f0f0;
 const DATA_PROCESSING_IMMEDIATE_EXTRA_INSTRUCTIONS_MASK: u32 = 0x01900000;
 const DATA_PROCESSING_IMMEDIATE_EXTRA_INSTRUCTIONS_VALUE: u32 = 0x01000000;
 const UPDATE_STATUS_BIT: u32 = 0x00100000;
 const IMMEDIATE_MODE_BIT: u32 = 0x02000000;
 const OPCODE_MASK: u32 = 0x01e00000;
+const BREAKPOINT_MASK: u32 = 0x000000f0;
+const BREAKPOINT_VALUE: u32 = 0x00000070;
 
 const ADD_OPCODE: u8 = 0x4;
 const ADD_WITH_CARRY_OPCODE: u8 = 0x5;
 const AND_OPCODE: u8 = 0x0;
+const BIT_CLEAR_OPCODE: u8 = 0xe;
 const BRANCH_EXCHANGE_OPCODE: u8 = 0x9;
+const BREAKPOINT_OPCODE: u8 = 0x9;
 const COMPARE_OPCODE: u8 = 0xa;
+const COMPARE_NEGATIVE_OPCODE: u8 = 0xb;
+const EXCLUSIVE_OR_OPCODE: u8 = 0x1;
 const MOVE_OPCODE: u8 = 0xd;
 const MOVE_HALFWORD_OPCODE: u8 = 0x8;
 const MOVE_HALFWORD_TOP_OPCODE: u8 = 0xa;
 const MOVE_NOT_OPCODE: u8 = 0xf;
 const MOVE_STATUS_TO_REGISTER_OPCODE: u8 = 0x8;
 const OR_OPCODE: u8 = 0xc;
+const REVERSE_SUBTRACT_OPCODE: u8 = 0x3;
+const REVERSE_SUBTRACT_WITH_CARRY_OPCODE: u8 = 0x7;
 const SUBTRACT_OPCODE: u8 = 0x2;
+const SUBTRACT_WITH_CARRY_OPCODE: u8 = 0x6;
+const TEST_OPCODE: u8 = 0x8;
+const TEST_EQUIVALENCE_OPCODE: u8 = 0x9;
+
+const MULTIPLY_OPCODE: u8 = 0x0;
+const MULTIPLY_ACCUMULATE_OPCODE: u8 = 0x1;
+const UNSIGNED_MULTIPLY_ACCUMULATE_ACCUMULATE_LONG_OPCODE: u8 = 0x2;
+const MULTIPLY_SUBTRACT_OPCODE: u8 = 0x3;
+const UNSIGNED_MULTIPLY_LONG_OPCODE: u8 = 0x4;
+const UNSIGNED_MULTIPLY_ACCUMULATE_LONG_OPCODE: u8 = 0x5;
+const SIGNED_MULTIPLY_LONG_OPCODE: u8 = 0x6;
+const SIGNED_MULTIPLY_ACCUMULATE_LONG_OPCODE: u8 = 0x7;
+
+const SIGNED_DIVIDE_OPCODE: u32 = 0x0110f010;
+const UNSIGNED_DIVIDE_OPCODE: u32 = 0x0130f010;
 
 const SHIFT_TYPE_LOGICAL_SHIFT_LEFT: u8 =       0b0000000;
 const SHIFT_TYPE_LOGICAL_SHIFT_RIGHT: u8 =      0b0100000;
 const SHIFT_TYPE_ARITHMETIC_SHIFT_RIGHT: u8 =   0b1000000;
 const SHIFT_TYPE_ROTATE_RIGHT: u8 =             0b1100000;
 const SHIFT_TYPE_MASK: u32 = 0x00000060;
-const SHIFT_IMMEDIATE_BIT: u32 = 0x00000010;
\ No newline at end of file
+const SHIFT_IMMEDIATE_BIT: u32 = 0x00000010;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_register_offset_load_store() {
+        // ldr r0, [r1, r2] and str r0, [r1, -r2]; bit 25 selects the register offset, so these used to be unknown instructions
+        assert!(matches!(
+            decode(0xe7910002),
+            Ok((Condition::Always, InstructionData::Load(LoadArguments { common_arguments: LoadStoreArguments { offset: LoadStoreOffset::Register(_), .. }, .. })))
+        ));
+        assert!(matches!(
+            decode(0xe7010002),
+            Ok((Condition::Always, InstructionData::Store(StoreArguments { common_arguments: LoadStoreArguments { offset: LoadStoreOffset::Register(_), offset_direction: LoadStoreOffsetDirection::Negative, .. }, .. })))
+        ));
+    }
+
+    #[test]
+    fn rejects_odd_doubleword_register_pairs() {
+        // ldrd r2, r3, [r0] and ldrd r3, r4, [r0]
+        assert!(decode(0xe1c020d0).is_ok());
+        assert!(matches!(decode(0xe1c030d0), Err(EmulatorError::UnknownInstruction { .. })));
+    }
+}
@@ -0,0 +1,505 @@
+use crate::{
+    context::Endianness,
+    decoding::decode,
+    file::{self, Symbol},
+    instructions::*,
+};
+
+// renders the instruction at the address in UAL syntax, the way GNU objdump does: lowercase mnemonics with the condition
+// after the S suffix, and sp, lr and pc by name. Branch targets and PC-relative addresses are shown as absolute
+// addresses, followed by the symbol they're in, if any
+pub fn disassemble(instruction: &Instruction, address: u32, symbols: &[Symbol]) -> String {
+    let (condition, data) = instruction;
+    let condition = get_condition_suffix(condition);
+
+    match data {
+        InstructionData::Add(args, update_status) => {
+            match get_pc_relative_immediate(args, update_status) {
+                Some(immediate) => format!("adr{} {}, {}", condition, register(destination_register(args)), describe_target(address.wrapping_add(PC_OFFSET).wrapping_add(immediate), symbols)),
+                None => format_read_write("add", update_status, condition, args),
+            }
+        },
+        InstructionData::AddWithCarry(args, update_status) => format_read_write("adc", update_status, condition, args),
+        InstructionData::And(args, update_status) => format_read_write("and", update_status, condition, args),
+        InstructionData::BitClear(args, update_status) => format_read_write("bic", update_status, condition, args),
+        InstructionData::Branch(offset, link) => {
+            let mnemonic = match link {
+                BranchLinkFlag::LinkReturnAddress => "bl",
+                BranchLinkFlag::DoNotLinkReturnAddress => "b",
+            };
+
+            format!("{}{} {}", mnemonic, condition, describe_target(address.wrapping_add(*offset as u32), symbols))
+        },
+        InstructionData::BranchExchange(register_number) => format!("bx{} {}", condition, register(*register_number)),
+        InstructionData::Breakpoint(immediate) => format!("bkpt {}", format_immediate((*immediate).into())),
+        InstructionData::Compare(args) => format_read("cmp", condition, args),
+        InstructionData::CompareNegative(args) => format_read("cmn", condition, args),
+        InstructionData::ExclusiveOr(args, update_status) => format_read_write("eor", update_status, condition, args),
+        InstructionData::Load(args) => {
+            let mnemonic = match args.data_size {
+                LoadDataSize::Word => "ldr",
+                LoadDataSize::Byte => "ldrb",
+                LoadDataSize::DoubleWord => "ldrd",
+                LoadDataSize::UnsignedHalfWord => "ldrh",
+                LoadDataSize::SignedByte => "ldrsb",
+                LoadDataSize::SignedHalfWord => "ldrsh",
+            };
+
+            match args.data_size {
+                LoadDataSize::Word if is_single_register_pop(&args.common_arguments) => format!("pop{} {{{}}}", condition, register(args.common_arguments.value_register)),
+                LoadDataSize::DoubleWord => format_load_store(mnemonic, true, condition, &args.common_arguments, address, symbols),
+                _ => format_load_store(mnemonic, false, condition, &args.common_arguments, address, symbols),
+            }
+        },
+        InstructionData::LoadMultiple(args) => format_block_transfer("ldm", "pop", BlockTransferAddressingMode::IncrementAfter, condition, args),
+        InstructionData::Move(args, update_status) => format_move(update_status, condition, args),
+        InstructionData::MoveHalfWord(args) => format!("movw{} {}, {}", condition, register(args.register), format_immediate(args.immediate.into())),
+        InstructionData::MoveHalfWordTop(args) => format!("movt{} {}, {}", condition, register(args.register), format_immediate(args.immediate.into())),
+        InstructionData::MoveNot(args, update_status) => format!("mvn{}{} {}, {}", get_status_suffix(update_status), condition, register(get_data_register(args)), format_operand(args)),
+        InstructionData::MoveStatusToRegister(register_number) => format!("mrs{} {}, apsr", condition, register(*register_number)),
+        InstructionData::Multiply(args, update_status) => format!("mul{}{} {}, {}, {}", get_status_suffix(update_status), condition,
+            register(args.destination_register), register(args.first_operand_register), register(args.second_operand_register)),
+        InstructionData::MultiplyAccumulate(args, update_status) => format_multiply_accumulate("mla", get_status_suffix(update_status), condition, args),
+        InstructionData::MultiplySubtract(args) => format_multiply_accumulate("mls", "", condition, args),
+        InstructionData::Or(args, update_status) => format_read_write("orr", update_status, condition, args),
+        InstructionData::ReverseSubtract(args, update_status) => format_read_write("rsb", update_status, condition, args),
+        InstructionData::ReverseSubtractWithCarry(args, update_status) => format_read_write("rsc", update_status, condition, args),
+        InstructionData::SetEndianness(endianness) => match endianness {
+            Endianness::Big => String::from("setend be"),
+            Endianness::Little => String::from("setend le"),
+        },
+        InstructionData::SignedDivide(args) => format_divide("sdiv", condition, args),
+        InstructionData::SignedMultiplyAccumulateLong(args, update_status) => format_long_multiply("smlal", get_status_suffix(update_status), condition, args),
+        InstructionData::SignedMultiplyLong(args, update_status) => format_long_multiply("smull", get_status_suffix(update_status), condition, args),
+        InstructionData::SupervisorCall(immediate) => format!("svc{} {}", condition, format_immediate((*immediate).into())),
+        InstructionData::Store(args) => {
+            let mnemonic = match args.data_size {
+                StoreDataSize::Word => "str",
+                StoreDataSize::Byte => "strb",
+                StoreDataSize::DoubleWord => "strd",
+                StoreDataSize::HalfWord => "strh",
+            };
+
+            match args.data_size {
+                StoreDataSize::Word if is_single_register_push(&args.common_arguments) => format!("push{} {{{}}}", condition, register(args.common_arguments.value_register)),
+                StoreDataSize::DoubleWord => format_load_store(mnemonic, true, condition, &args.common_arguments, address, symbols),
+                _ => format_load_store(mnemonic, false, condition, &args.common_arguments, address, symbols),
+            }
+        },
+        InstructionData::StoreMultiple(args) => format_block_transfer("stm", "push", BlockTransferAddressingMode::DecrementBefore, condition, args),
+        InstructionData::Subtract(args, update_status) => {
+            match get_pc_relative_immediate(args, update_status) {
+                Some(immediate) => format!("adr{} {}, {}", condition, register(destination_register(args)), describe_target(address.wrapping_add(PC_OFFSET).wrapping_sub(immediate), symbols)),
+                None => format_read_write("sub", update_status, condition, args),
+            }
+        },
+        InstructionData::SubtractWithCarry(args, update_status) => format_read_write("sbc", update_status, condition, args),
+        InstructionData::Test(args) => format_read("tst", condition, args),
+        InstructionData::TestEquivalence(args) => format_read("teq", condition, args),
+        InstructionData::UnsignedDivide(args) => format_divide("udiv", condition, args),
+        InstructionData::UnsignedMultiplyAccumulateAccumulateLong(args) => format_long_multiply("umaal", "", condition, args),
+        InstructionData::UnsignedMultiplyAccumulateLong(args, update_status) => format_long_multiply("umlal", get_status_suffix(update_status), condition, args),
+        InstructionData::UnsignedMultiplyLong(args, update_status) => format_long_multiply("umull", get_status_suffix(update_status), condition, args),
+    }
+}
+
+// disassembles a region of code, one instruction per line, objdump style: symbols get a label line of their own, and
+// words that don't decode are shown as data
+pub fn disassemble_region(data: &[u8], address: u32, symbols: &[Symbol]) -> Vec<String> {
+    let mut lines = Vec::new();
+
+    for (index, bytes) in data.chunks_exact(INSTRUCTION_SIZE as usize).enumerate() {
+        let instruction_address = address.wrapping_add(index as u32 * INSTRUCTION_SIZE);
+        let encoded_instruction = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+
+        for symbol in symbols.iter().filter(|s| s.address == instruction_address) {
+            lines.push(format!("\n{:0>8x} <{}>:", instruction_address, symbol.name));
+        }
+
+        let text = match decode(encoded_instruction) {
+            Ok(instruction) => disassemble(&instruction, instruction_address, symbols),
+            Err(_) => format!(".word 0x{:0>8x}", encoded_instruction),
+        };
+
+        lines.push(format!("{:>8x}:\t{:0>8x}\t{}", instruction_address, encoded_instruction, text));
+    }
+
+    lines
+}
+
+// the symbol containing the address in angle brackets, with the offset into it if it doesn't start there
+pub fn get_symbol_label(address: u32, symbols: &[Symbol]) -> Option<String> {
+    let symbol = file::find_symbol(symbols, address)?;
+
+    if symbol.address == address {
+        Some(format!("<{}>", symbol.name))
+    } else {
+        Some(format!("<{}+{}>", symbol.name, address - symbol.address))
+    }
+}
+
+fn describe_target(address: u32, symbols: &[Symbol]) -> String {
+    match get_symbol_label(address, symbols) {
+        Some(label) => format!("0x{:0>8x} {}", address, label),
+        None => format!("0x{:0>8x}", address),
+    }
+}
+
+fn format_read_write(mnemonic: &str, update_status: &UpdateStatusFlags, condition: &str, args: &ReadWriteDataArguments) -> String {
+    let status = get_status_suffix(update_status);
+
+    match args {
+        ReadWriteDataArguments::Immediate(args) => format!("{}{}{} {}, {}, {}", mnemonic, status, condition,
+            register(args.destination_register), register(args.source_register), format_immediate(args.immediate)),
+        ReadWriteDataArguments::Register(args) => format!("{}{}{} {}, {}, {}{}", mnemonic, status, condition,
+            register(args.destination_register), register(args.source_register), register(args.operand_register),
+            format_shift(&args.shift_type, &args.shift_operand)),
+    }
+}
+
+fn format_read(mnemonic: &str, condition: &str, args: &DataArguments) -> String {
+    format!("{}{} {}, {}", mnemonic, condition, register(get_data_register(args)), format_operand(args))
+}
+
+// MOV with a shifted register is written as the shift itself, e.g. lsl r0, r1, #2
+fn format_move(update_status: &UpdateStatusFlags, condition: &str, args: &DataArguments) -> String {
+    let status = get_status_suffix(update_status);
+
+    let args = match args {
+        DataArguments::Immediate(args) => return format!("mov{}{} {}, {}", status, condition, register(args.register), format_immediate(args.immediate)),
+        DataArguments::Register(args) => args,
+    };
+
+    let destination = register(args.register);
+    let operand = register(args.operand_register);
+
+    match (&args.shift_type, &args.shift_operand) {
+        (ShiftType::RotateRightExtended, _) => format!("rrx{}{} {}, {}", status, condition, destination, operand),
+        (ShiftType::LogicalShiftLeft, ShiftOperand::Immediate(amount)) if u8::from(*amount) == 0 => format!("mov{}{} {}, {}", status, condition, destination, operand),
+        (shift_type, ShiftOperand::Immediate(amount)) => format!("{}{}{} {}, {}, #{}", get_shift_name(shift_type), status, condition, destination, operand, get_shift_amount(shift_type, (*amount).into())),
+        (shift_type, ShiftOperand::Register(shift_register)) => format!("{}{}{} {}, {}, {}", get_shift_name(shift_type), status, condition, destination, operand, register(*shift_register)),
+    }
+}
+
+fn format_operand(args: &DataArguments) -> String {
+    match args {
+        DataArguments::Immediate(args) => format_immediate(args.immediate),
+        DataArguments::Register(args) => format!("{}{}", register(args.operand_register), format_shift(&args.shift_type, &args.shift_operand)),
+    }
+}
+
+fn get_data_register(args: &DataArguments) -> Register {
+    match args {
+        DataArguments::Immediate(args) => args.register,
+        DataArguments::Register(args) => args.register,
+    }
+}
+
+fn destination_register(args: &ReadWriteDataArguments) -> Register {
+    match args {
+        ReadWriteDataArguments::Immediate(args) => args.destination_register,
+        ReadWriteDataArguments::Register(args) => args.destination_register,
+    }
+}
+
+// ADD and SUB of an immediate to PC, without setting the flags, are written as ADR
+fn get_pc_relative_immediate(args: &ReadWriteDataArguments, update_status: &UpdateStatusFlags) -> Option<u32> {
+    match (args, update_status) {
+        (ReadWriteDataArguments::Immediate(args), UpdateStatusFlags::DoNotUpdateStatusFlags) if u8::from(args.source_register) == PROGRAM_COUNTER_REGISTER => Some(args.immediate),
+        _ => None,
+    }
+}
+
+// ", <shift> #<amount>" or ", <shift> <register>", or nothing for LSL #0
+fn format_shift(shift_type: &ShiftType, shift_operand: &ShiftOperand) -> String {
+    match (shift_type, shift_operand) {
+        (ShiftType::RotateRightExtended, _) => String::from(", rrx"),
+        (ShiftType::LogicalShiftLeft, ShiftOperand::Immediate(amount)) if u8::from(*amount) == 0 => String::new(),
+        (shift_type, ShiftOperand::Immediate(amount)) => format!(", {} #{}", get_shift_name(shift_type), get_shift_amount(shift_type, (*amount).into())),
+        (shift_type, ShiftOperand::Register(shift_register)) => format!(", {} {}", get_shift_name(shift_type), register(*shift_register)),
+    }
+}
+
+fn get_shift_name(shift_type: &ShiftType) -> &'static str {
+    match shift_type {
+        ShiftType::LogicalShiftLeft => "lsl",
+        ShiftType::LogicalShiftRight => "lsr",
+        ShiftType::ArithmeticShiftRight => "asr",
+        ShiftType::RotateRight => "ror",
+        ShiftType::RotateRightExtended => "rrx",
+    }
+}
+
+// LSR #32 and ASR #32 are encoded as a shift by zero
+fn get_shift_amount(shift_type: &ShiftType, amount: u8) -> u8 {
+    match shift_type {
+        ShiftType::LogicalShiftRight | ShiftType::ArithmeticShiftRight if amount == 0 => 32,
+        _ => amount,
+    }
+}
+
+// a single-register POP is LDR Rt, [SP], #4
+fn is_single_register_pop(args: &LoadStoreArguments) -> bool {
+    matches!(args.indexing_type, LoadStoreIndexingType::PostIndexed)
+        && matches!(args.offset_direction, LoadStoreOffsetDirection::Positive)
+        && is_stack_word_offset(args)
+}
+
+// a single-register PUSH is STR Rt, [SP, #-4]!
+fn is_single_register_push(args: &LoadStoreArguments) -> bool {
+    matches!(args.indexing_type, LoadStoreIndexingType::PreIndexed)
+        && matches!(args.write_back, LoadStoreWriteBackFlag::WriteBack)
+        && matches!(args.offset_direction, LoadStoreOffsetDirection::Negative)
+        && is_stack_word_offset(args)
+}
+
+fn is_stack_word_offset(args: &LoadStoreArguments) -> bool {
+    u8::from(args.address_register) == STACK_POINTER_REGISTER && matches!(args.offset, LoadStoreOffset::Immediate(immediate) if u16::from(immediate) == WORD_SIZE)
+}
+
+fn format_load_store(mnemonic: &str, is_double_word: bool, condition: &str, args: &LoadStoreArguments, address: u32, symbols: &[Symbol]) -> String {
+    let value_register: u8 = args.value_register.into();
+    let address_register: u8 = args.address_register.into();
+
+    let registers = if is_double_word {
+        format!("{}, {}", register(args.value_register), get_register_name(value_register + 1))
+    } else {
+        register(args.value_register)
+    };
+
+    let sign = match args.offset_direction {
+        LoadStoreOffsetDirection::Positive => "",
+        LoadStoreOffsetDirection::Negative => "-",
+    };
+
+    let offset = match &args.offset {
+        LoadStoreOffset::Immediate(immediate) => format!("#{}{}", sign, u16::from(*immediate)),
+        LoadStoreOffset::Register(offset) => {
+            let shift_operand = ShiftOperand::Immediate(offset.shift_operand);
+            format!("{}{}{}", sign, register(offset.register), format_shift(&offset.shift_type, &shift_operand))
+        },
+    };
+    let is_zero_offset = matches!(args.offset, LoadStoreOffset::Immediate(immediate) if u16::from(immediate) == 0)
+        && matches!(args.offset_direction, LoadStoreOffsetDirection::Positive);
+
+    let base = register(args.address_register);
+    let operand = match (&args.indexing_type, &args.write_back) {
+        (LoadStoreIndexingType::PostIndexed, _) => format!("[{}], {}", base, offset),
+        (LoadStoreIndexingType::PreIndexed, LoadStoreWriteBackFlag::WriteBack) => format!("[{}, {}]!", base, offset),
+        (LoadStoreIndexingType::PreIndexed, LoadStoreWriteBackFlag::DoNotWriteBack) if is_zero_offset => format!("[{}]", base),
+        (LoadStoreIndexingType::PreIndexed, LoadStoreWriteBackFlag::DoNotWriteBack) => format!("[{}, {}]", base, offset),
+    };
+
+    // literal loads get the address they load from as a comment
+    let literal_address = match (&args.offset, &args.indexing_type, &args.write_back) {
+        (LoadStoreOffset::Immediate(immediate), LoadStoreIndexingType::PreIndexed, LoadStoreWriteBackFlag::DoNotWriteBack) if address_register == PROGRAM_COUNTER_REGISTER => {
+            let base_address = address.wrapping_add(PC_OFFSET);
+            let immediate = u32::from(u16::from(*immediate));
+
+            match args.offset_direction {
+                LoadStoreOffsetDirection::Positive => Some(base_address.wrapping_add(immediate)),
+                LoadStoreOffsetDirection::Negative => Some(base_address.wrapping_sub(immediate)),
+            }
+        },
+        _ => None,
+    };
+
+    match literal_address {
+        Some(literal_address) => format!("{}{} {}, {} ; {}", mnemonic, condition, registers, operand, describe_target(literal_address, symbols)),
+        None => format!("{}{} {}, {}", mnemonic, condition, registers, operand),
+    }
+}
+
+// LDMIA SP! and STMDB SP! are written as POP and PUSH, and the default addressing mode (IA) has no suffix
+fn format_block_transfer(mnemonic: &str, stack_mnemonic: &str, stack_addressing_mode: BlockTransferAddressingMode, condition: &str, args: &BlockTransferArguments) -> String {
+    let register_list = format_register_list(args.register_list);
+    let write_back = matches!(args.write_back, LoadStoreWriteBackFlag::WriteBack);
+
+    if write_back && u8::from(args.address_register) == STACK_POINTER_REGISTER && get_addressing_mode_suffix(&args.addressing_mode) == get_addressing_mode_suffix(&stack_addressing_mode) {
+        return format!("{}{} {}", stack_mnemonic, condition, register_list);
+    }
+
+    format!("{}{}{} {}{}, {}", mnemonic, get_addressing_mode_suffix(&args.addressing_mode), condition,
+        register(args.address_register), if write_back { "!" } else { "" }, register_list)
+}
+
+fn get_addressing_mode_suffix(addressing_mode: &BlockTransferAddressingMode) -> &'static str {
+    match addressing_mode {
+        BlockTransferAddressingMode::IncrementAfter => "",
+        BlockTransferAddressingMode::IncrementBefore => "ib",
+        BlockTransferAddressingMode::DecrementAfter => "da",
+        BlockTransferAddressingMode::DecrementBefore => "db",
+    }
+}
+
+fn format_register_list(register_list: u16) -> String {
+    let registers: Vec<String> = (0..16u8)
+        .filter(|r| register_list & (1 << r) != 0)
+        .map(get_register_name)
+        .collect();
+
+    format!("{{{}}}", registers.join(", "))
+}
+
+fn format_multiply_accumulate(mnemonic: &str, status: &str, condition: &str, args: &MultiplyAccumulateArguments) -> String {
+    format!("{}{}{} {}, {}, {}, {}", mnemonic, status, condition, register(args.destination_register),
+        register(args.first_operand_register), register(args.second_operand_register), register(args.accumulate_register))
+}
+
+fn format_long_multiply(mnemonic: &str, status: &str, condition: &str, args: &LongMultiplyArguments) -> String {
+    format!("{}{}{} {}, {}, {}, {}", mnemonic, status, condition, register(args.destination_register_low),
+        register(args.destination_register_high), register(args.first_operand_register), register(args.second_operand_register))
+}
+
+fn format_divide(mnemonic: &str, condition: &str, args: &DivideArguments) -> String {
+    format!("{}{} {}, {}, {}", mnemonic, condition, register(args.destination_register), register(args.dividend_register), register(args.divisor_register))
+}
+
+// small immediates in decimal, like objdump; large ones, which are usually masks or addresses, in hexadecimal
+fn format_immediate(value: u32) -> String {
+    if value < LARGE_IMMEDIATE {
+        format!("#{}", value)
+    } else {
+        format!("#0x{:x}", value)
+    }
+}
+
+fn get_status_suffix(update_status: &UpdateStatusFlags) -> &'static str {
+    match update_status {
+        UpdateStatusFlags::UpdateStatusFlags => "s",
+        UpdateStatusFlags::DoNotUpdateStatusFlags => "",
+    }
+}
+
+fn get_condition_suffix(condition: &Condition) -> &'static str {
+    match condition {
+        Condition::Equal => "eq",
+        Condition::NotEqual => "ne",
+        Condition::CarrySet => "cs",
+        Condition::CarryClear => "cc",
+        Condition::Negative => "mi",
+        Condition::Positive => "pl",
+        Condition::Overflow => "vs",
+        Condition::NoOverflow => "vc",
+        Condition::UnsignedHigher => "hi",
+        Condition::UnsignedLowerOrSame => "ls",
+        Condition::GreaterThanOrEqual => "ge",
+        Condition::LessThan => "lt",
+        Condition::GreaterThan => "gt",
+        Condition::LessThanOrEqual => "le",
+        Condition::Always => "",
+    }
+}
+
+fn register(register: Register) -> String {
+    get_register_name(register.into())
+}
+
+fn get_register_name(register: u8) -> String {
+    match register {
+        STACK_POINTER_REGISTER => String::from("sp"),
+        LINK_RETURN_REGISTER => String::from("lr"),
+        PROGRAM_COUNTER_REGISTER => String::from("pc"),
+        _ => format!("r{}", register),
+    }
+}
+
+const INSTRUCTION_SIZE: u32 = 4;
+const WORD_SIZE: u16 = 4;
+const PC_OFFSET: u32 = 8;      // reading PC gives the address of the instruction + 8
+const LARGE_IMMEDIATE: u32 = 0x10000;
+const STACK_POINTER_REGISTER: u8 = 13;
+const LINK_RETURN_REGISTER: u8 = 14;
+const PROGRAM_COUNTER_REGISTER: u8 = 15;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_every_instruction_in_ual_syntax() {
+        let instructions = [
+            (0xe0932104, "adds r2, r3, r4, lsl #2"),
+            (0xe2a10001, "adc r0, r1, #1"),
+            (0xe0010372, "and r0, r1, r2, ror r3"),
+            (0xe3c100ff, "bic r0, r1, #255"),
+            (0xe12fff1e, "bx lr"),
+            (0xe1200a7b, "bkpt #171"),
+            (0xe3500003, "cmp r0, #3"),
+            (0xe1700001, "cmn r0, r1"),
+            (0xe0200fc1, "eor r0, r0, r1, asr #31"),
+            (0x15b01004, "ldrne r1, [r0, #4]!"),
+            (0xe4501001, "ldrb r1, [r0], #-1"),
+            (0xe1c020d8, "ldrd r2, r3, [r0, #8]"),
+            (0xe19010b2, "ldrh r1, [r0, r2]"),
+            (0xe15010d3, "ldrsb r1, [r0, #-3]"),
+            (0xe1d010f0, "ldrsh r1, [r0]"),
+            (0xe49d0004, "pop {r0}"),
+            (0xe8b0000e, "ldm r0!, {r1, r2, r3}"),
+            (0xe8bd8030, "pop {r4, r5, pc}"),
+            (0xe9900006, "ldmib r0, {r1, r2}"),
+            (0xe1a00001, "mov r0, r1"),
+            (0xe1b00101, "lsls r0, r1, #2"),
+            (0xe3a004ff, "mov r0, #0xff000000"),
+            (0xe3010234, "movw r0, #4660"),
+            (0xe34a0bcd, "movt r0, #43981"),
+            (0xe3e00000, "mvn r0, #0"),
+            (0xe10f0000, "mrs r0, apsr"),
+            (0xe0000291, "mul r0, r1, r2"),
+            (0xe0303291, "mlas r0, r1, r2, r3"),
+            (0xe0603291, "mls r0, r1, r2, r3"),
+            (0xe1810062, "orr r0, r1, r2, rrx"),
+            (0xe2600000, "rsb r0, r0, #0"),
+            (0xe0f10002, "rscs r0, r1, r2"),
+            (0xf1010200, "setend be"),
+            (0xf1010000, "setend le"),
+            (0xe710f211, "sdiv r0, r1, r2"),
+            (0xe0e10392, "smlal r0, r1, r2, r3"),
+            (0xe0d10392, "smulls r0, r1, r2, r3"),
+            (0xef000000, "svc #0"),
+            (0xe7001102, "str r1, [r0, -r2, lsl #2]"),
+            (0xe5c01001, "strb r1, [r0, #1]"),
+            (0xe16d20f8, "strd r2, r3, [sp, #-8]!"),
+            (0xe0c010b2, "strh r1, [r0], #2"),
+            (0xe92d4003, "push {r0, r1, lr}"),
+            (0xe52d4004, "push {r4}"),
+            (0xe8000006, "stmda r0, {r1, r2}"),
+            (0xe24dd008, "sub sp, sp, #8"),
+            (0xe0c10002, "sbc r0, r1, r2"),
+            (0xe3100001, "tst r0, #1"),
+            (0xe1300001, "teq r0, r1"),
+            (0xe730f211, "udiv r0, r1, r2"),
+            (0xe0410392, "umaal r0, r1, r2, r3"),
+            (0xe0a10392, "umlal r0, r1, r2, r3"),
+            (0xe0810392, "umull r0, r1, r2, r3"),
+        ];
+
+        for (encoded_instruction, text) in instructions {
+            assert_eq!(disassemble(&decode(encoded_instruction).unwrap(), 0x8000, &[]), text, "{:0>8x}", encoded_instruction);
+        }
+    }
+
+    #[test]
+    fn shows_targets_as_addresses_with_their_symbols() {
+        let symbols = [Symbol { name: String::from("main"), address: 0x8010, size: 8 }];
+        let text = |encoded_instruction: u32, address: u32| disassemble(&decode(encoded_instruction).unwrap(), address, &symbols);
+
+        assert_eq!(text(0xeb000002, 0x8000), "bl 0x00008010 <main>");
+        assert_eq!(text(0x1a000002, 0x8004), "bne 0x00008014 <main+4>");
+        assert_eq!(text(0xeafffffe, 0x8020), "b 0x00008020");
+        assert_eq!(text(0xe28f0008, 0x8000), "adr r0, 0x00008010 <main>");
+        assert_eq!(text(0xe24f0004, 0x8000), "adr r0, 0x00008004");
+    }
+
+    #[test]
+    fn region_listing_labels_symbols_and_shows_unknown_words_as_data() {
+        let symbols = [Symbol { name: String::from("_start"), address: 0x8000, size: 8 }];
+        let data = [0x01, 0x00, 0xa0, 0xe3, 0xf0, 0x00, 0xf0, 0xe7];
+
+        assert_eq!(disassemble_region(&data, 0x8000, &symbols), [
+            "\n00008000 <_start>:",
+            "    8000:\te3a00001\tmov r0, #1",
+            "    8004:\te7f000f0\t.word 0xe7f000f0",
+        ]);
+    }
+}
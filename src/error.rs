@@ -0,0 +1,76 @@
+use std::{fmt, io};
+
+use crate::memory::MemoryAccess;
+
+#[derive(Debug)]
+pub enum EmulatorError {
+    UnknownInstruction { instruction: u32, message: String },
+    UndefinedInstruction(u32),      // the address of an instruction that traps, such as a divide by zero with DivideByZeroBehaviour::Trap
+    UnsupportedSupervisorCall(u32),
+    Breakpoint(u32),
+    UnsupportedSystemCall(u32),
+    UnsupportedFileDescriptor(u32),
+    MemoryFault(u32),
+    PermissionFault { address: u32, access: MemoryAccess },
+    AlignmentFault(u32),
+    InvalidString(u32),
+    InvalidExecutable(String),
+    InvalidAssembly(String),
+    InvalidMemoryRegion { start: u32, size: u64 },
+    Io(io::Error),
+}
+
+impl fmt::Display for EmulatorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmulatorError::UnknownInstruction { instruction, message } => write!(f, "{} (instruction: {:0>8X})", message, instruction),
+            EmulatorError::UndefinedInstruction(address) => write!(f, "Undefined instruction at {:0>8X}", address),
+            EmulatorError::UnsupportedSupervisorCall(immediate) => write!(f, "Unsupported supervisor call {:0>6X}", immediate),
+            EmulatorError::Breakpoint(immediate) => write!(f, "Breakpoint {:0>4X}", immediate),
+            EmulatorError::UnsupportedSystemCall(number) => write!(f, "Unsupported system call {:0>8X}", number),
+            EmulatorError::UnsupportedFileDescriptor(descriptor) => write!(f, "Unsupported file descriptor {}", descriptor),
+            EmulatorError::MemoryFault(address) => write!(f, "Memory fault at {:0>8X}", address),
+            EmulatorError::PermissionFault { address, access } => write!(f, "Permission fault ({} access) at {:0>8X}", access, address),
+            EmulatorError::AlignmentFault(address) => write!(f, "Alignment fault at {:0>8X}", address),
+            EmulatorError::InvalidString(address) => write!(f, "Invalid string at {:0>8X}", address),
+            EmulatorError::InvalidExecutable(message) => write!(f, "Invalid executable: {}", message),
+            EmulatorError::InvalidAssembly(message) => write!(f, "Invalid assembly: {}", message),
+            EmulatorError::InvalidMemoryRegion { start, size } => write!(f, "Invalid memory region of {:X} bytes at {:0>8X}", size, start),
+            EmulatorError::Io(error) => write!(f, "I/O error: {}", error),
+        }
+    }
+}
+
+impl EmulatorError {
+    // the signal Linux would send a process for the same problem, for errors caused by the guest
+    pub fn get_signal_number(&self) -> Option<u32> {
+        match self {
+            EmulatorError::UnknownInstruction { .. } | EmulatorError::UndefinedInstruction(_) => Some(SIGILL),
+            EmulatorError::Breakpoint(_) => Some(SIGTRAP),
+            EmulatorError::AlignmentFault(_) => Some(SIGBUS),
+            EmulatorError::MemoryFault(_) | EmulatorError::PermissionFault { .. } | EmulatorError::InvalidString(_) => Some(SIGSEGV),
+            EmulatorError::UnsupportedSupervisorCall(_) | EmulatorError::UnsupportedSystemCall(_) | EmulatorError::UnsupportedFileDescriptor(_) => Some(SIGSYS),
+            EmulatorError::InvalidExecutable(_) | EmulatorError::InvalidAssembly(_) | EmulatorError::InvalidMemoryRegion { .. } | EmulatorError::Io(_) => None,
+        }
+    }
+}
+
+impl std::error::Error for EmulatorError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            EmulatorError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for EmulatorError {
+    fn from(error: io::Error) -> Self {
+        EmulatorError::Io(error)
+    }
+}
+const SIGILL: u32 = 4;
+const SIGTRAP: u32 = 5;
+const SIGBUS: u32 = 7;
+const SIGSEGV: u32 = 11;
+const SIGSYS: u32 = 31;
@@ -1,41 +1,65 @@
-use core::panic;
-
 use ux::{u24, u4};
 
-use crate::{context::*, instructions::*, syscall};
+use crate::{context::*, disassembler::disassemble, error::EmulatorError, instructions::*};
 
 const INSTRUCTION_SIZE: u32 = 4;
+const WORD_SIZE: u32 = 4;
+const HALF_WORD_SIZE: u32 = 2;
 
-pub fn execute(context: &mut CpuContext, instr: Instruction) {
+pub fn execute(context: &mut CpuContext, instr: Instruction) -> Result<(), EmulatorError> {
     let program_counter = context.get_program_counter();
-    context.set_program_counter(program_counter + INSTRUCTION_SIZE);
+    context.set_program_counter(program_counter.wrapping_add(INSTRUCTION_SIZE));
 
     if !is_condition_met(context, &instr.0) {
-        return;
+        return Ok(());
     }
 
     if cfg!(feature = "print_instructions") {
-        println!("{:0>8X} {:0>8X} {:?}", program_counter, context.read_word(program_counter), instr);
+        println!("{:0>8X} {:0>8X} {}", program_counter, context.fetch_instruction(program_counter)?, disassemble(&instr, program_counter, &[]));
     }
 
     match instr.1 {
-        InstructionData::Add(ref args, ref update_status) => execute_add(context, &args, &update_status),
-        InstructionData::AddWithCarry(ref args, ref update_status) => execute_add_with_carry(context, &args, &update_status),
-        InstructionData::And(ref args, ref update_status) => execute_and(context, &args, &update_status),
-        InstructionData::Branch(ref address, ref link) => execute_branch(context, &address, &link),
-        InstructionData::BranchExchange(ref register) => execute_branch_exchange(context, &register),
-        InstructionData::Compare(ref args) => execute_compare(context, &args),
-        InstructionData::Load(ref args) => execute_load(context, &args),
-        InstructionData::Move(ref args, ref update_status) => execute_move(context, &args, &update_status),
-        InstructionData::MoveHalfWord(ref args) => execute_move_half_word(context, &args),
-        InstructionData::MoveHalfWordTop(ref args) => execute_move_half_word_top(context, &args),
-        InstructionData::MoveNot(ref args, ref update_status) => execute_move_not(context, &args, &update_status),
-        InstructionData::MoveStatusToRegister(ref register) => execute_move_status_to_register(context, &register),
-        InstructionData::Or(ref args, ref update_status) => execute_or(context, &args, &update_status),
-        InstructionData::SupervisorCall(ref arg) => execute_supervisor_call(context, &arg),
-        InstructionData::Store(ref args) => execute_store(context, &args),
-        InstructionData::Subtract(ref args, ref update_status) => execute_subtract(context, &args, &update_status),
+        InstructionData::Add(ref args, ref update_status) => execute_add(context, args, update_status),
+        InstructionData::AddWithCarry(ref args, ref update_status) => execute_add_with_carry(context, args, update_status),
+        InstructionData::And(ref args, ref update_status) => execute_and(context, args, update_status),
+        InstructionData::BitClear(ref args, ref update_status) => execute_bit_clear(context, args, update_status),
+        InstructionData::Branch(ref address, ref link) => execute_branch(context, address, link),
+        InstructionData::BranchExchange(ref register) => execute_branch_exchange(context, register),
+        InstructionData::Breakpoint(immediate) => context.request_breakpoint(immediate.into()),
+        InstructionData::Compare(ref args) => execute_compare(context, args),
+        InstructionData::CompareNegative(ref args) => execute_compare_negative(context, args),
+        InstructionData::ExclusiveOr(ref args, ref update_status) => execute_exclusive_or(context, args, update_status),
+        InstructionData::Load(ref args) => execute_load(context, args)?,
+        InstructionData::LoadMultiple(ref args) => execute_load_multiple(context, args)?,
+        InstructionData::Move(ref args, ref update_status) => execute_move(context, args, update_status),
+        InstructionData::MoveHalfWord(ref args) => execute_move_half_word(context, args),
+        InstructionData::MoveHalfWordTop(ref args) => execute_move_half_word_top(context, args),
+        InstructionData::MoveNot(ref args, ref update_status) => execute_move_not(context, args, update_status),
+        InstructionData::MoveStatusToRegister(ref register) => execute_move_status_to_register(context, register),
+        InstructionData::Multiply(ref args, ref update_status) => execute_multiply(context, args, update_status),
+        InstructionData::MultiplyAccumulate(ref args, ref update_status) => execute_multiply_accumulate(context, args, update_status),
+        InstructionData::MultiplySubtract(ref args) => execute_multiply_subtract(context, args),
+        InstructionData::Or(ref args, ref update_status) => execute_or(context, args, update_status),
+        InstructionData::ReverseSubtract(ref args, ref update_status) => execute_reverse_subtract(context, args, update_status),
+        InstructionData::ReverseSubtractWithCarry(ref args, ref update_status) => execute_reverse_subtract_with_carry(context, args, update_status),
+        InstructionData::SetEndianness(endianness) => context.set_endianness(endianness),
+        InstructionData::SignedDivide(ref args) => execute_signed_divide(context, args)?,
+        InstructionData::SignedMultiplyAccumulateLong(ref args, ref update_status) => execute_signed_multiply_accumulate_long(context, args, update_status),
+        InstructionData::SignedMultiplyLong(ref args, ref update_status) => execute_signed_multiply_long(context, args, update_status),
+        InstructionData::SupervisorCall(ref arg) => execute_supervisor_call(context, arg),
+        InstructionData::Store(ref args) => execute_store(context, args)?,
+        InstructionData::StoreMultiple(ref args) => execute_store_multiple(context, args)?,
+        InstructionData::Subtract(ref args, ref update_status) => execute_subtract(context, args, update_status),
+        InstructionData::SubtractWithCarry(ref args, ref update_status) => execute_subtract_with_carry(context, args, update_status),
+        InstructionData::Test(ref args) => execute_test(context, args),
+        InstructionData::TestEquivalence(ref args) => execute_test_equivalence(context, args),
+        InstructionData::UnsignedDivide(ref args) => execute_unsigned_divide(context, args)?,
+        InstructionData::UnsignedMultiplyAccumulateAccumulateLong(ref args) => execute_unsigned_multiply_accumulate_accumulate_long(context, args),
+        InstructionData::UnsignedMultiplyAccumulateLong(ref args, ref update_status) => execute_unsigned_multiply_accumulate_long(context, args, update_status),
+        InstructionData::UnsignedMultiplyLong(ref args, ref update_status) => execute_unsigned_multiply_long(context, args, update_status),
     }
+
+    Ok(())
 }
 
 fn is_condition_met(context: &CpuContext, cond: &Condition) -> bool {
@@ -92,111 +116,266 @@ fn execute_move_not(context: &mut CpuContext, args: &DataArguments, update_statu
 }
 
 fn execute_add(context: &mut CpuContext, args: &ReadWriteDataArguments, update_status: &UpdateStatusFlags) {
-    execute_add_core(context, args, update_status, |_, _, v| v)
+    execute_arithmetic_core(context, args, update_status, |original, operand, _| add_with_carry(original, operand, false))
 }
 
 fn execute_add_with_carry(context: &mut CpuContext, args: &ReadWriteDataArguments, update_status: &UpdateStatusFlags) {
-    execute_add_core(
-        context, 
-        args, 
-        update_status, 
-        |c, _, v| v.wrapping_add(if c.get_status().carry { 1 } else { 0 })
-    )
+    execute_arithmetic_core(context, args, update_status, add_with_carry)
+}
+
+fn execute_subtract(context: &mut CpuContext, args: &ReadWriteDataArguments, update_status: &UpdateStatusFlags) {
+    execute_arithmetic_core(context, args, update_status, |original, operand, _| add_with_carry(original, !operand, true))
+}
+
+fn execute_subtract_with_carry(context: &mut CpuContext, args: &ReadWriteDataArguments, update_status: &UpdateStatusFlags) {
+    execute_arithmetic_core(context, args, update_status, |original, operand, carry| add_with_carry(original, !operand, carry))
+}
+
+fn execute_reverse_subtract(context: &mut CpuContext, args: &ReadWriteDataArguments, update_status: &UpdateStatusFlags) {
+    execute_arithmetic_core(context, args, update_status, |original, operand, _| add_with_carry(!original, operand, true))
+}
+
+fn execute_reverse_subtract_with_carry(context: &mut CpuContext, args: &ReadWriteDataArguments, update_status: &UpdateStatusFlags) {
+    execute_arithmetic_core(context, args, update_status, |original, operand, carry| add_with_carry(!original, operand, carry))
 }
 
-fn execute_add_core(
-    context: &mut CpuContext, 
-    args: &ReadWriteDataArguments, 
+fn execute_arithmetic_core(
+    context: &mut CpuContext,
+    args: &ReadWriteDataArguments,
     update_status: &UpdateStatusFlags,
-    modify_value: fn(&CpuContext, &ReadWriteDataArguments, u32) -> u32
+    operation: fn(u32, u32, bool) -> (u32, bool, bool)
 ) {
     let (destination_register, original, operand, _) = get_read_write_data_arguments(context, args);
 
-    let result = original.wrapping_add(operand);
-    let result = modify_value(context, args, result);
+    let (result, carry, overflow) = operation(original, operand, context.get_status().carry);
 
     context.set_register(destination_register.into(), result);
 
     if let UpdateStatusFlags::UpdateStatusFlags = *update_status {
         context.set_status(
-            Some(get_sign(result)), 
-            Some(result == 0), 
-            Some((original as u64) + (operand as u64) > (u32::MAX as u64)), 
-            Some(
-                get_sign(original) != get_sign(operand)
-             && get_sign(original) != get_sign(result)
-            )
+            Some(get_sign(result)),
+            Some(result == 0),
+            Some(carry),
+            Some(overflow)
         );
     }
 }
 
-fn execute_subtract(context: &mut CpuContext, args: &ReadWriteDataArguments, update_status: &UpdateStatusFlags) {
-    let (destination_register, original, operand, _) = get_read_write_data_arguments(context, args);
+fn execute_or(context: &mut CpuContext, args: &ReadWriteDataArguments, update_status: &UpdateStatusFlags) {
+    execute_logical_core(context, args, update_status, |original, operand| original | operand)
+}
 
-    let result = original.wrapping_sub(operand);
+fn execute_and(context: &mut CpuContext, args: &ReadWriteDataArguments, update_status: &UpdateStatusFlags) {
+    execute_logical_core(context, args, update_status, |original, operand| original & operand)
+}
 
+fn execute_exclusive_or(context: &mut CpuContext, args: &ReadWriteDataArguments, update_status: &UpdateStatusFlags) {
+    execute_logical_core(context, args, update_status, |original, operand| original ^ operand)
+}
+
+fn execute_bit_clear(context: &mut CpuContext, args: &ReadWriteDataArguments, update_status: &UpdateStatusFlags) {
+    execute_logical_core(context, args, update_status, |original, operand| original & !operand)
+}
+
+fn execute_logical_core(
+    context: &mut CpuContext,
+    args: &ReadWriteDataArguments,
+    update_status: &UpdateStatusFlags,
+    operation: fn(u32, u32) -> u32
+) {
+    let (destination_register, original, operand, carry) = get_read_write_data_arguments(context, args);
+
+    let result = operation(original, operand);
     context.set_register(destination_register.into(), result);
 
     if let UpdateStatusFlags::UpdateStatusFlags = *update_status {
         context.set_status(
             Some(get_sign(result)),
             Some(result == 0),
-            Some(result <= original),
-            Some(
-                get_sign(original) != get_sign(operand)
-             && get_sign(original) != get_sign(result)
-            )
+            Some(carry),
+            None
         );
     }
 }
 
-fn execute_or(context: &mut CpuContext, args: &ReadWriteDataArguments, update_status: &UpdateStatusFlags) {
-    let (destination_register, original, operand, carry) = get_read_write_data_arguments(context, args);
+fn execute_compare(context: &mut CpuContext, args: &DataArguments) {
+    execute_compare_core(context, args, |original, operand| add_with_carry(original, !operand, true))
+}
+
+fn execute_compare_negative(context: &mut CpuContext, args: &DataArguments) {
+    execute_compare_core(context, args, |original, operand| add_with_carry(original, operand, false))
+}
+
+fn execute_compare_core(context: &mut CpuContext, args: &DataArguments, operation: fn(u32, u32) -> (u32, bool, bool)) {
+    let (register, operand, _) = get_data_arguments(context, args);
+
+    let original = context.get_register(register.into());
+    let (result, carry, overflow) = operation(original, operand);
+
+    context.set_status(
+        Some(get_sign(result)),
+        Some(result == 0),
+        Some(carry),
+        Some(overflow)
+    );
+}
+
+fn execute_test(context: &mut CpuContext, args: &DataArguments) {
+    execute_test_core(context, args, |original, operand| original & operand)
+}
+
+fn execute_test_equivalence(context: &mut CpuContext, args: &DataArguments) {
+    execute_test_core(context, args, |original, operand| original ^ operand)
+}
+
+fn execute_test_core(context: &mut CpuContext, args: &DataArguments, operation: fn(u32, u32) -> u32) {
+    let (register, operand, carry) = get_data_arguments(context, args);
 
-    let result = original | operand;
+    let original = context.get_register(register.into());
+    let result = operation(original, operand);
+
+    context.set_status(
+        Some(get_sign(result)),
+        Some(result == 0),
+        Some(carry),
+        None
+    );
+}
+
+fn execute_multiply(context: &mut CpuContext, args: &MultiplyArguments, update_status: &UpdateStatusFlags) {
+    let first_operand = context.get_register(args.first_operand_register.into());
+    let second_operand = context.get_register(args.second_operand_register.into());
+
+    let result = first_operand.wrapping_mul(second_operand);
+
+    set_multiply_result(context, args.destination_register, result, update_status);
+}
+
+fn execute_multiply_accumulate(context: &mut CpuContext, args: &MultiplyAccumulateArguments, update_status: &UpdateStatusFlags) {
+    let first_operand = context.get_register(args.first_operand_register.into());
+    let second_operand = context.get_register(args.second_operand_register.into());
+    let accumulate = context.get_register(args.accumulate_register.into());
+
+    let result = first_operand.wrapping_mul(second_operand).wrapping_add(accumulate);
+
+    set_multiply_result(context, args.destination_register, result, update_status);
+}
+
+fn execute_multiply_subtract(context: &mut CpuContext, args: &MultiplyAccumulateArguments) {
+    let first_operand = context.get_register(args.first_operand_register.into());
+    let second_operand = context.get_register(args.second_operand_register.into());
+    let accumulate = context.get_register(args.accumulate_register.into());
+
+    let result = accumulate.wrapping_sub(first_operand.wrapping_mul(second_operand));
+
+    set_multiply_result(context, args.destination_register, result, &UpdateStatusFlags::DoNotUpdateStatusFlags);
+}
+
+fn set_multiply_result(context: &mut CpuContext, destination_register: Register, result: u32, update_status: &UpdateStatusFlags) {
     context.set_register(destination_register.into(), result);
 
+    // multiplications leave the carry and overflow flags untouched
     if let UpdateStatusFlags::UpdateStatusFlags = *update_status {
         context.set_status(
             Some(get_sign(result)),
             Some(result == 0),
-            Some(carry),
+            None,
             None
         );
     }
 }
 
-fn execute_and(context: &mut CpuContext, args: &ReadWriteDataArguments, update_status: &UpdateStatusFlags) {
-    let (destination_register, original, operand, carry) = get_read_write_data_arguments(context, args);
+fn execute_unsigned_multiply_long(context: &mut CpuContext, args: &LongMultiplyArguments, update_status: &UpdateStatusFlags) {
+    execute_long_multiply_core(context, args, update_status, |first, second, _| (first as u64) * (second as u64))
+}
 
-    let result = original & operand;
-    context.set_register(destination_register.into(), result);
+fn execute_unsigned_multiply_accumulate_long(context: &mut CpuContext, args: &LongMultiplyArguments, update_status: &UpdateStatusFlags) {
+    execute_long_multiply_core(
+        context,
+        args,
+        update_status,
+        |first, second, accumulate| ((first as u64) * (second as u64)).wrapping_add(accumulate)
+    )
+}
+
+fn execute_unsigned_multiply_accumulate_accumulate_long(context: &mut CpuContext, args: &LongMultiplyArguments) {
+    execute_long_multiply_core(
+        context,
+        args,
+        &UpdateStatusFlags::DoNotUpdateStatusFlags,
+        // both 32-bit halves are added separately; the result cannot overflow 64 bits
+        |first, second, accumulate| (first as u64) * (second as u64) + (accumulate & 0xffffffff) + (accumulate >> 32)
+    )
+}
+
+fn execute_signed_multiply_long(context: &mut CpuContext, args: &LongMultiplyArguments, update_status: &UpdateStatusFlags) {
+    execute_long_multiply_core(
+        context,
+        args,
+        update_status,
+        |first, second, _| ((first as i32 as i64) * (second as i32 as i64)) as u64
+    )
+}
+
+fn execute_signed_multiply_accumulate_long(context: &mut CpuContext, args: &LongMultiplyArguments, update_status: &UpdateStatusFlags) {
+    execute_long_multiply_core(
+        context,
+        args,
+        update_status,
+        |first, second, accumulate| (((first as i32 as i64) * (second as i32 as i64)) as u64).wrapping_add(accumulate)
+    )
+}
+
+fn execute_long_multiply_core(
+    context: &mut CpuContext,
+    args: &LongMultiplyArguments,
+    update_status: &UpdateStatusFlags,
+    operation: fn(u32, u32, u64) -> u64
+) {
+    let first_operand = context.get_register(args.first_operand_register.into());
+    let second_operand = context.get_register(args.second_operand_register.into());
+    let accumulate_low = context.get_register(args.destination_register_low.into()) as u64;
+    let accumulate_high = context.get_register(args.destination_register_high.into()) as u64;
+
+    let result = operation(first_operand, second_operand, (accumulate_high << 32) | accumulate_low);
+
+    context.set_register(args.destination_register_low.into(), result as u32);
+    context.set_register(args.destination_register_high.into(), (result >> 32) as u32);
 
     if let UpdateStatusFlags::UpdateStatusFlags = *update_status {
         context.set_status(
-            Some(get_sign(result)),
+            Some(result & 0x8000000000000000 != 0),
             Some(result == 0),
-            Some(carry),
+            None,
             None
         );
     }
 }
 
-fn execute_compare(context: &mut CpuContext, args: &DataArguments) {
-    let (register, operand, _) = get_data_arguments(context, args);
+fn execute_signed_divide(context: &mut CpuContext, args: &DivideArguments) -> Result<(), EmulatorError> {
+    execute_divide_core(context, args, |dividend, divisor| (dividend as i32).wrapping_div(divisor as i32) as u32)
+}
 
-    let original = context.get_register(register.into());
-    let result = original.wrapping_sub(operand);
+fn execute_unsigned_divide(context: &mut CpuContext, args: &DivideArguments) -> Result<(), EmulatorError> {
+    execute_divide_core(context, args, |dividend, divisor| dividend / divisor)
+}
 
-    context.set_status(
-        Some(get_sign(result)), 
-        Some(result == 0), 
-        Some(result < original), 
-        Some(
-            get_sign(original) != get_sign(operand)
-         && get_sign(original) != get_sign(result)
-        )
-    );
+// there are no exception vectors in user mode, so the trap ends the program the way Linux would, with SIGILL
+fn execute_divide_core(context: &mut CpuContext, args: &DivideArguments, operation: fn(u32, u32) -> u32) -> Result<(), EmulatorError> {
+    let dividend = context.get_register(args.dividend_register.into());
+    let divisor = context.get_register(args.divisor_register.into());
+
+    let result = if divisor != 0 {
+        operation(dividend, divisor)
+    } else {
+        match context.get_configuration().divide_by_zero {
+            DivideByZeroBehaviour::ReturnZero => 0,
+            DivideByZeroBehaviour::Trap => return Err(EmulatorError::UndefinedInstruction(context.get_program_counter().wrapping_sub(INSTRUCTION_SIZE))),
+        }
+    };
+
+    context.set_register(args.destination_register.into(), result);
+    Ok(())
 }
 
 fn execute_branch(context: &mut CpuContext, address: &i32, link: &BranchLinkFlag) {
@@ -207,9 +386,10 @@ fn execute_branch(context: &mut CpuContext, address: &i32, link: &BranchLinkFlag
         context.set_register(CpuContext::get_link_return_register(), original_program_counter);
     }
 
-    let destination = (original_program_counter - INSTRUCTION_SIZE).wrapping_add(*address as u32);
+    let destination = original_program_counter.wrapping_sub(INSTRUCTION_SIZE).wrapping_add(*address as u32);
 
-    if destination == original_program_counter {
+    // a branch to itself can only loop forever, so it ends the program instead
+    if destination == original_program_counter.wrapping_sub(INSTRUCTION_SIZE) {
         context.halt()
     } else {
         context.set_program_counter(destination);
@@ -221,21 +401,21 @@ fn execute_branch_exchange(context: &mut CpuContext, register: &Register) {
     context.set_program_counter(destination_address);
 }
 
-fn execute_load(context: &mut CpuContext, args: &LoadArguments) {
-    execute_load_store(context, args, &args.common_arguments, get_load_data, load_data);
+fn execute_load(context: &mut CpuContext, args: &LoadArguments) -> Result<(), EmulatorError> {
+    execute_load_store(context, args, &args.common_arguments, get_load_data, load_data)
 }
 
-fn execute_store(context: &mut CpuContext, args: &StoreArguments) {
-    execute_load_store(context, args, &args.common_arguments, get_store_data, store_data);
+fn execute_store(context: &mut CpuContext, args: &StoreArguments) -> Result<(), EmulatorError> {
+    execute_load_store(context, args, &args.common_arguments, get_store_data, store_data)
 }
 
 fn execute_load_store<A>(
     context: &mut CpuContext,
     full_args: &A,
     args: &LoadStoreArguments,
-    get_data: fn(&CpuContext, u32, &A) -> u32,
-    action: fn(&mut CpuContext, u32, u32, &A)
-) {
+    get_data: fn(&CpuContext, u32, &A) -> Result<u64, EmulatorError>,
+    action: fn(&mut CpuContext, u32, u64, &A) -> Result<(), EmulatorError>
+) -> Result<(), EmulatorError> {
     let address = context.get_register(args.address_register.into());
     let offset: u32 = get_load_store_offset(context, &args.offset);
     let address = match args.indexing_type {
@@ -243,7 +423,10 @@ fn execute_load_store<A>(
         LoadStoreIndexingType::PostIndexed => address,
     };
 
-    let data = get_data(context, address, full_args);
+    let data = get_data(context, address, full_args)?;
+
+    // the transfer happens before write-back, so that a faulting access leaves the base register untouched
+    action(context, address, data, full_args)?;
 
     if let LoadStoreWriteBackFlag::WriteBack = args.write_back {
         let address = match args.indexing_type {
@@ -253,33 +436,181 @@ fn execute_load_store<A>(
         context.set_register(args.address_register.into(), address);
     }
 
-    action(context, address, data, full_args);
+    Ok(())
 }
 
-fn get_load_data(context: &CpuContext, address: u32, args: &LoadArguments) -> u32 {
-    match args.data_size {
-        LoadDataSize::Word => context.read_word(address),
-        LoadDataSize::Byte => context.read_byte(address) as u32,
-        LoadDataSize::UnsignedHalfWord => context.read_half_word(address) as u32,
-        _ => panic!("Data type {:?} not supported", args.data_size)
+fn execute_load_multiple(context: &mut CpuContext, args: &BlockTransferArguments) -> Result<(), EmulatorError> {
+    let (start_address, write_back_address) = get_block_transfer_addresses(context, args);
+    let start_address = align_block_transfer_address(context, start_address)?;
+
+    let mut address = start_address;
+    for register in get_block_transfer_registers(args) {
+        let value = context.read_word(address)?;
+        // loading PC acts as a branch, which is how POP { ..., PC } returns from a function
+        context.set_register(register, value);
+        address = address.wrapping_add(WORD_SIZE);
+    }
+
+    // when the base register is also loaded, the loaded value wins over the written-back address
+    let base_loaded = args.register_list & (1 << u8::from(args.address_register)) != 0;
+
+    if let LoadStoreWriteBackFlag::WriteBack = args.write_back {
+        if !base_loaded {
+            context.set_register(args.address_register.into(), write_back_address);
+        }
+    }
+
+    Ok(())
+}
+
+fn execute_store_multiple(context: &mut CpuContext, args: &BlockTransferArguments) -> Result<(), EmulatorError> {
+    let (start_address, write_back_address) = get_block_transfer_addresses(context, args);
+    let start_address = align_block_transfer_address(context, start_address)?;
+
+    // all values are stored before write-back, so a stored base register holds its original value
+    let mut address = start_address;
+    for register in get_block_transfer_registers(args) {
+        let value = context.get_register(register);
+        context.write_word(address, value)?;
+        address = address.wrapping_add(WORD_SIZE);
+    }
+
+    if let LoadStoreWriteBackFlag::WriteBack = args.write_back {
+        context.set_register(args.address_register.into(), write_back_address);
+    }
+
+    Ok(())
+}
+
+fn get_block_transfer_registers(args: &BlockTransferArguments) -> impl Iterator<Item = u8> + '_ {
+    (0..=CpuContext::get_program_counter_register()).filter(move |register| args.register_list & (1 << register) != 0)
+}
+
+// returns the lowest address transferred, and the value written back to the base register
+fn get_block_transfer_addresses(context: &CpuContext, args: &BlockTransferArguments) -> (u32, u32) {
+    let base_address = context.get_register(args.address_register.into());
+    let size = args.register_list.count_ones() * WORD_SIZE;
+
+    match args.addressing_mode {
+        BlockTransferAddressingMode::IncrementAfter => (base_address, base_address.wrapping_add(size)),
+        BlockTransferAddressingMode::IncrementBefore => (base_address.wrapping_add(WORD_SIZE), base_address.wrapping_add(size)),
+        BlockTransferAddressingMode::DecrementAfter => (base_address.wrapping_sub(size).wrapping_add(WORD_SIZE), base_address.wrapping_sub(size)),
+        BlockTransferAddressingMode::DecrementBefore => (base_address.wrapping_sub(size), base_address.wrapping_sub(size)),
+    }
+}
+
+// block transfers are always word-aligned; only the legacy behaviour forgives an unaligned base address
+fn align_block_transfer_address(context: &CpuContext, address: u32) -> Result<u32, EmulatorError> {
+    match context.get_configuration().alignment {
+        AlignmentBehaviour::Rotate => Ok(address & !(WORD_SIZE - 1)),
+        _ => {
+            check_alignment(address, WORD_SIZE)?;
+            Ok(address)
+        },
+    }
+}
+
+fn check_alignment(address: u32, size: u32) -> Result<(), EmulatorError> {
+    if !address.is_multiple_of(size) {
+        return Err(EmulatorError::AlignmentFault(address));
+    }
+
+    Ok(())
+}
+
+// checks a single word or halfword access against the configured alignment behaviour
+fn check_access_alignment(context: &CpuContext, address: u32, size: u32) -> Result<(), EmulatorError> {
+    match context.get_configuration().alignment {
+        AlignmentBehaviour::Strict => check_alignment(address, size),
+        _ => Ok(()),
+    }
+}
+
+fn read_data_word(context: &CpuContext, address: u32) -> Result<u32, EmulatorError> {
+    match context.get_configuration().alignment {
+        AlignmentBehaviour::Rotate => {
+            let rotation = (address & (WORD_SIZE - 1)) * 8;
+            Ok(context.read_word(address & !(WORD_SIZE - 1))?.rotate_right(rotation))
+        },
+        _ => {
+            check_access_alignment(context, address, WORD_SIZE)?;
+            context.read_word(address)
+        },
     }
 }
 
-fn load_data(context: &mut CpuContext, _: u32, data: u32, args: &LoadArguments) {
-    context.set_register(args.common_arguments.value_register.into(), data);
+fn write_data_word(context: &mut CpuContext, address: u32, value: u32) -> Result<(), EmulatorError> {
+    match context.get_configuration().alignment {
+        AlignmentBehaviour::Rotate => context.write_word(address & !(WORD_SIZE - 1), value),
+        _ => {
+            check_access_alignment(context, address, WORD_SIZE)?;
+            context.write_word(address, value)
+        },
+    }
 }
 
-fn get_store_data(context: &CpuContext, _: u32, args: &StoreArguments) -> u32 {
-    let data = context.get_register(args.common_arguments.value_register.into());
-    data
+fn read_data_half_word(context: &CpuContext, address: u32) -> Result<u16, EmulatorError> {
+    check_access_alignment(context, address, HALF_WORD_SIZE)?;
+    context.read_half_word(address)
+}
+
+fn write_data_half_word(context: &mut CpuContext, address: u32, value: u16) -> Result<(), EmulatorError> {
+    check_access_alignment(context, address, HALF_WORD_SIZE)?;
+    context.write_half_word(address, value)
+}
+
+// doubleword transfers carry the first register in the low half and the second register in the high half
+fn get_load_data(context: &CpuContext, address: u32, args: &LoadArguments) -> Result<u64, EmulatorError> {
+    let data = match args.data_size {
+        LoadDataSize::Word => read_data_word(context, address)? as u64,
+        LoadDataSize::Byte => context.read_byte(address)? as u64,
+        LoadDataSize::UnsignedHalfWord => read_data_half_word(context, address)? as u64,
+        LoadDataSize::SignedByte => context.read_byte(address)? as i8 as i32 as u32 as u64,
+        LoadDataSize::SignedHalfWord => read_data_half_word(context, address)? as i16 as i32 as u32 as u64,
+        LoadDataSize::DoubleWord => {
+            // doubleword transfers need word alignment, whatever the configured behaviour
+            check_alignment(address, WORD_SIZE)?;
+            let low = context.read_word(address)? as u64;
+            let high = context.read_word(address.wrapping_add(WORD_SIZE))? as u64;
+            (high << 32) | low
+        },
+    };
+
+    Ok(data)
 }
 
-fn store_data(context: &mut CpuContext, address: u32, data: u32, args: &StoreArguments) {
+fn load_data(context: &mut CpuContext, _: u32, data: u64, args: &LoadArguments) -> Result<(), EmulatorError> {
+    let value_register: u8 = args.common_arguments.value_register.into();
+
+    context.set_register(value_register, data as u32);
+
+    if let LoadDataSize::DoubleWord = args.data_size {
+        context.set_register(value_register + 1, (data >> 32) as u32);
+    }
+
+    Ok(())
+}
+
+fn get_store_data(context: &CpuContext, _: u32, args: &StoreArguments) -> Result<u64, EmulatorError> {
+    let value_register: u8 = args.common_arguments.value_register.into();
+    let low = context.get_register(value_register) as u64;
+
     match args.data_size {
-        StoreDataSize::Word => context.write_word(address, data),
+        StoreDataSize::DoubleWord => Ok(((context.get_register(value_register + 1) as u64) << 32) | low),
+        _ => Ok(low),
+    }
+}
+
+fn store_data(context: &mut CpuContext, address: u32, data: u64, args: &StoreArguments) -> Result<(), EmulatorError> {
+    match args.data_size {
+        StoreDataSize::Word => write_data_word(context, address, data as u32),
         StoreDataSize::Byte => context.write_byte(address, (data & 0x000000ff) as u8),
-        StoreDataSize::HalfWord => context.write_half_word(address, (data & 0x0000ffff) as u16),
-        _ => panic!("Data type {:?} not supported", args.data_size),
+        StoreDataSize::HalfWord => write_data_half_word(context, address, (data & 0x0000ffff) as u16),
+        StoreDataSize::DoubleWord => {
+            check_alignment(address, WORD_SIZE)?;
+            context.write_word(address, data as u32)?;
+            context.write_word(address.wrapping_add(WORD_SIZE), (data >> 32) as u32)
+        },
     }
 }
 
@@ -297,27 +628,12 @@ fn execute_move_half_word_top(context: &mut CpuContext, args: &LargeImmediateArg
 }
 
 fn execute_supervisor_call(context: &mut CpuContext, arg: &u24) {
-    const SYSTEM_CALL: u32 = 0;
-
-    if *arg != u24::new(SYSTEM_CALL) {
-        panic!("Unsupported supervisor call {:0>6X}", *arg);
-    }
-
-    syscall::execute_system_call(context);
+    context.request_supervisor_call((*arg).into());
 }
 
 fn execute_move_status_to_register(context: &mut CpuContext, register: &Register) {
-    let status = context.get_status();
+    let value = context.get_program_status_register();
 
-    let negative_value: u32 = if status.negative { 1 } else { 0 };
-    let zero_value: u32 = if status.zero { 1 } else { 0 };
-    let carry_value: u32 = if status.carry { 1 } else { 0 };
-    let overflow_value: u32 = if status.overflow { 1 } else { 0 };
-    let value = (negative_value << 31)
-                  | (zero_value << 30)
-                  | (carry_value << 29)
-                  | (overflow_value << 28);
-    
     context.set_register((*register).into(), value);
 }
 
@@ -325,6 +641,15 @@ fn get_sign(value: u32) -> bool {
     value & 0x80000000 != 0
 }
 
+// the AddWithCarry() pseudo-function from the ARM ARM; subtraction is x + !y + 1, so the carry flag is an inverted borrow
+fn add_with_carry(x: u32, y: u32, carry_in: bool) -> (u32, bool, bool) {
+    let unsigned_sum = (x as u64) + (y as u64) + (carry_in as u64);
+    let signed_sum = (x as i32 as i64) + (y as i32 as i64) + (carry_in as i64);
+    let result = unsigned_sum as u32;
+
+    (result, result as u64 != unsigned_sum, result as i32 as i64 != signed_sum)
+}
+
 fn get_data_arguments(context: &CpuContext, args: &DataArguments) -> (Register, u32, bool) {
     match args {
         DataArguments::Immediate(args) => {
@@ -341,7 +666,7 @@ fn get_read_write_data_arguments(context: &CpuContext, args: &ReadWriteDataArgum
     match args {
         ReadWriteDataArguments::Immediate(args) => {
             let original = context.get_register(args.source_register.into());
-            (args.destination_register, original, args.immediate, args.carry)
+            (args.destination_register, original, args.immediate, if args.rotate == 0 { context.get_status().carry } else { args.carry })
         },
         ReadWriteDataArguments::Register(args) => {
             let original = context.get_register(args.source_register.into());
@@ -355,55 +680,68 @@ fn get_read_write_data_arguments(context: &CpuContext, args: &ReadWriteDataArgum
 fn apply_shift_operand(context: &CpuContext, register: &u4, shift_type: &ShiftType, shift_operand: &ShiftOperand) -> (u32, bool) {
     let raw = context.get_register((*register).into());
 
-    let shift_operand = get_shift_operand(context, shift_operand);
-
-    return match *shift_type {
-        ShiftType::LogicalShiftLeft => logical_shift_left(context, raw, shift_operand),
-        ShiftType::LogicalShiftRight => logical_shift_right(context, raw, shift_operand),
-        _ => panic!("Unsupported shift type {:?}", *shift_type),
+    let amount = match *shift_operand {
+        ShiftOperand::Immediate(immediate) => get_immediate_shift_amount(shift_type, immediate.into()),
+        // only the bottom byte of the register is used as the shift amount
+        ShiftOperand::Register(register) => context.get_register(register.into()) & 0x000000ff,
     };
 
-    fn get_shift_operand(context: &CpuContext, operand: &ShiftOperand) -> u8 {
-        match *operand {
-            ShiftOperand::Immediate(immediate) => immediate.into(),
-            ShiftOperand::Register(register) => context.get_register(register.into()) as u8,
-        }
-    }
-
+    shift_with_carry(raw, shift_type, amount, context.get_status().carry)
 }
 
-fn logical_shift_left(context: &CpuContext, value: u32, bits: u8) -> (u32, bool) {
-    let result = value.wrapping_shl(bits as u32);
-
-    if bits == 0 {
-        (result, context.get_status().carry)
-    } else if bits < 32 {
-        (result, (value & (1 << (32 - bits))) != 0)
-    } else if bits > 32 {
-        (result, false)
-    } else { // bits == 32 
-        (result, (value & 1) != 0)
+// LSR #0 and ASR #0 cannot be expressed as immediates, so their encodings are used for shifting by 32 instead
+fn get_immediate_shift_amount(shift_type: &ShiftType, immediate: u32) -> u32 {
+    match *shift_type {
+        ShiftType::LogicalShiftRight | ShiftType::ArithmeticShiftRight if immediate == 0 => 32,
+        _ => immediate,
     }
 }
 
-fn logical_shift_right(context: &CpuContext, value: u32, bits: u8) -> (u32, bool) {
-    let result = value.wrapping_shr(bits as u32);
-
-    if bits == 0 {
-        (result, context.get_status().carry)
-    } else if bits < 32 {
-        (result, (value & (1 << (bits - 1))) != 0)
-    } else if bits > 32 {
-        (result, false)
-    } else { // bits == 32 
-        (result, get_sign(value))
+// the barrel shifter; returns the shifted value and the carry out. Shifting by zero leaves both the value and the carry untouched
+fn shift_with_carry(value: u32, shift_type: &ShiftType, amount: u32, carry_in: bool) -> (u32, bool) {
+    match *shift_type {
+        ShiftType::RotateRightExtended => {
+            let carry_bit = if carry_in { 0x80000000 } else { 0 };
+            ((value >> 1) | carry_bit, (value & 1) != 0)
+        },
+        _ if amount == 0 => (value, carry_in),
+        ShiftType::LogicalShiftLeft => {
+            if amount < 32 {
+                (value << amount, (value & (1 << (32 - amount))) != 0)
+            } else if amount == 32 {
+                (0, (value & 1) != 0)
+            } else {
+                (0, false)
+            }
+        },
+        ShiftType::LogicalShiftRight => {
+            if amount < 32 {
+                (value >> amount, (value & (1 << (amount - 1))) != 0)
+            } else if amount == 32 {
+                (0, get_sign(value))
+            } else {
+                (0, false)
+            }
+        },
+        ShiftType::ArithmeticShiftRight => {
+            if amount < 32 {
+                (((value as i32) >> amount) as u32, (value & (1 << (amount - 1))) != 0)
+            } else {
+                (((value as i32) >> 31) as u32, get_sign(value))
+            }
+        },
+        ShiftType::RotateRight => {
+            // rotating by a multiple of 32 leaves the value intact, but still sets the carry from bit 31
+            let result = value.rotate_right(amount % 32);
+            (result, get_sign(result))
+        },
     }
 }
 
 fn apply_offset(address: u32, offset: u32, direction: &LoadStoreOffsetDirection) -> u32 {
     match *direction {
-        LoadStoreOffsetDirection::Positive => address + offset,
-        LoadStoreOffsetDirection::Negative => address - offset,
+        LoadStoreOffsetDirection::Positive => address.wrapping_add(offset),
+        LoadStoreOffsetDirection::Negative => address.wrapping_sub(offset),
     }
 }
 
@@ -412,14 +750,476 @@ fn get_load_store_offset(context: &CpuContext, offset: &LoadStoreOffset) -> u32
         LoadStoreOffset::Immediate(v) => v.into(),
         LoadStoreOffset::Register(ref args) => {
             let offset = context.get_register(args.register.into());
+            let amount = get_immediate_shift_amount(&args.shift_type, args.shift_operand.into());
 
-            let shift_operand: u32 = args.shift_operand.into();
-            match args.shift_type {
-                ShiftType::LogicalShiftLeft => offset << shift_operand,
-                ShiftType::LogicalShiftRight => offset >> shift_operand,
-                ShiftType::RotateRight => offset.rotate_right(shift_operand),
-                _ => panic!("Shift type {:?} not yet implemented", args.shift_type),
-            }
+            let (offset, _) = shift_with_carry(offset, &args.shift_type, amount, context.get_status().carry);
+            offset
         }
     }
-}
\ No newline at end of file
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoding::decode;
+
+    fn create_context() -> CpuContext {
+        CpuContext::create(CpuConfiguration::default())
+    }
+
+    fn execute_at(context: &mut CpuContext, address: u32, encoded_instruction: u32) -> Result<(), EmulatorError> {
+        context.set_program_counter(address);
+        execute(context, decode(encoded_instruction)?)
+    }
+
+    // N, Z, C and V, from bit 3 down to bit 0
+    fn get_flags(context: &CpuContext) -> u32 {
+        context.get_program_status_register() >> 28
+    }
+
+    #[test]
+    fn program_counter_wraps_around_the_top_of_memory() {
+        let mut context = create_context();
+
+        // mov r0, pc at FFFFFFF8 reads PC + 8, which wraps to zero
+        execute_at(&mut context, 0xfffffff8, 0xe1a0000f).unwrap();
+        assert_eq!(context.get_register(0), 0x00000000);
+
+        // b 0x00000004 at FFFFFFFC, after PC has already wrapped
+        execute_at(&mut context, 0xfffffffc, 0xea000000).unwrap();
+        assert_eq!(context.get_program_counter(), 0x00000004);
+    }
+
+    #[test]
+    fn addition_sets_carry_and_overflow() {
+        let mut context = create_context();
+
+        // adds r2, r0, r1: signed overflow without a carry
+        context.set_register(0, 0x7fffffff);
+        context.set_register(1, 0x00000001);
+        execute_at(&mut context, 0x1000, 0xe0902001).unwrap();
+        assert_eq!(context.get_register(2), 0x80000000);
+        assert_eq!(get_flags(&context), 0b1001);
+
+        // a carry without a signed overflow
+        context.set_register(0, 0xffffffff);
+        execute_at(&mut context, 0x1000, 0xe0902001).unwrap();
+        assert_eq!(context.get_register(2), 0x00000000);
+        assert_eq!(get_flags(&context), 0b0110);
+
+        // cmn r0, r1 sets the same flags as adds, but writes nothing
+        context.set_register(0, 0x7fffffff);
+        context.set_register(2, 0x12345678);
+        execute_at(&mut context, 0x1000, 0xe1700001).unwrap();
+        assert_eq!(context.get_register(2), 0x12345678);
+        assert_eq!(get_flags(&context), 0b1001);
+    }
+
+    #[test]
+    fn subtraction_carry_is_the_inverse_of_borrow() {
+        let mut context = create_context();
+
+        // subs r2, r0, r1 with a borrow clears carry
+        context.set_register(0, 0x00000000);
+        context.set_register(1, 0x00000001);
+        execute_at(&mut context, 0x1000, 0xe0502001).unwrap();
+        assert_eq!(context.get_register(2), 0xffffffff);
+        assert_eq!(get_flags(&context), 0b1000);
+
+        // sbcs r2, r0, r1 subtracts one more when carry is clear
+        context.set_register(0, 0x00000005);
+        context.set_register(1, 0x00000003);
+        execute_at(&mut context, 0x1000, 0xe0d02001).unwrap();
+        assert_eq!(context.get_register(2), 0x00000001);
+        assert_eq!(get_flags(&context), 0b0010);
+
+        // ...and nothing more once it's set
+        execute_at(&mut context, 0x1000, 0xe0d02001).unwrap();
+        assert_eq!(context.get_register(2), 0x00000002);
+
+        // rscs r2, r0, r1 is r1 - r0 - NOT(C)
+        context.set_status(None, None, Some(false), None);
+        execute_at(&mut context, 0x1000, 0xe0f02001).unwrap();
+        assert_eq!(context.get_register(2), 0xfffffffd);
+        assert_eq!(get_flags(&context), 0b1000);
+
+        // rsbs r2, r0, #0 negates, overflowing only for the most negative number
+        context.set_register(0, 0x80000000);
+        execute_at(&mut context, 0x1000, 0xe2702000).unwrap();
+        assert_eq!(context.get_register(2), 0x80000000);
+        assert_eq!(get_flags(&context), 0b1001);
+    }
+
+    #[test]
+    fn logical_instructions() {
+        let mut context = create_context();
+
+        context.set_register(0, 0b1100);
+        context.set_register(1, 0b1010);
+
+        // eor r2, r0, r1
+        execute_at(&mut context, 0x1000, 0xe0202001).unwrap();
+        assert_eq!(context.get_register(2), 0b0110);
+
+        // bic r2, r0, r1
+        execute_at(&mut context, 0x1000, 0xe1c02001).unwrap();
+        assert_eq!(context.get_register(2), 0b0100);
+
+        // teq r0, r1 only changes the flags
+        context.set_register(1, 0b1100);
+        execute_at(&mut context, 0x1000, 0xe1300001).unwrap();
+        assert_eq!(context.get_register(2), 0b0100);
+        assert_eq!(get_flags(&context), 0b0100);
+
+        // tst r0, #0x80000000: a rotated immediate sets carry to its top bit, and overflow is left alone
+        context.set_status(None, None, Some(false), Some(true));
+        execute_at(&mut context, 0x1000, 0xe3100102).unwrap();
+        assert_eq!(get_flags(&context), 0b0111);
+    }
+
+    #[test]
+    fn multiplies_keep_the_low_word() {
+        let mut context = create_context();
+
+        // muls r2, r0, r1 keeps the bottom 32 bits and leaves carry and overflow alone
+        context.set_register(0, 0x80000001);
+        context.set_register(1, 0x00000002);
+        context.set_status(None, None, Some(true), Some(true));
+        execute_at(&mut context, 0x1000, 0xe0120190).unwrap();
+        assert_eq!(context.get_register(2), 0x00000002);
+        assert_eq!(get_flags(&context), 0b0011);
+
+        // mla r3, r0, r1, r2 and mls r3, r0, r1, r2
+        context.set_register(0, 0x00000006);
+        context.set_register(1, 0x00000007);
+        context.set_register(2, 0x00000064);
+        execute_at(&mut context, 0x1000, 0xe0232190).unwrap();
+        assert_eq!(context.get_register(3), 0x00000064 + 42);
+        execute_at(&mut context, 0x1000, 0xe0632190).unwrap();
+        assert_eq!(context.get_register(3), 0x00000064 - 42);
+    }
+
+    #[test]
+    fn long_multiplies_produce_both_words() {
+        let mut context = create_context();
+
+        // umull r2, r3, r0, r1 and smull r2, r3, r0, r1 with the same operands
+        context.set_register(0, 0xffffffff);
+        context.set_register(1, 0x00000002);
+        execute_at(&mut context, 0x1000, 0xe0832190).unwrap();
+        assert_eq!((context.get_register(3), context.get_register(2)), (0x00000001, 0xfffffffe));
+        execute_at(&mut context, 0x1000, 0xe0c32190).unwrap();
+        assert_eq!((context.get_register(3), context.get_register(2)), (0xffffffff, 0xfffffffe));
+
+        // umlal r2, r3, r0, r1 adds to the 64-bit accumulator, carrying into the high word
+        context.set_register(2, 0x00000002);
+        context.set_register(3, 0x00000000);
+        execute_at(&mut context, 0x1000, 0xe0a32190).unwrap();
+        assert_eq!((context.get_register(3), context.get_register(2)), (0x00000002, 0x00000000));
+
+        // smlal r2, r3, r0, r1: -1 * 2 + 2 = 0
+        context.set_register(2, 0x00000002);
+        context.set_register(3, 0x00000000);
+        execute_at(&mut context, 0x1000, 0xe0e32190).unwrap();
+        assert_eq!((context.get_register(3), context.get_register(2)), (0x00000000, 0x00000000));
+
+        // umaal r2, r3, r0, r1 adds both 32-bit accumulators, which can't overflow 64 bits
+        context.set_register(0, 0xffffffff);
+        context.set_register(1, 0xffffffff);
+        context.set_register(2, 0xffffffff);
+        context.set_register(3, 0xffffffff);
+        execute_at(&mut context, 0x1000, 0xe0432190).unwrap();
+        assert_eq!((context.get_register(3), context.get_register(2)), (0xffffffff, 0xffffffff));
+    }
+
+    #[test]
+    fn divides_round_towards_zero() {
+        let mut context = create_context();
+
+        // sdiv r2, r0, r1 and udiv r2, r0, r1 with the same operands
+        context.set_register(0, -7i32 as u32);
+        context.set_register(1, 0x00000002);
+        execute_at(&mut context, 0x1000, 0xe712f110).unwrap();
+        assert_eq!(context.get_register(2), -3i32 as u32);
+        execute_at(&mut context, 0x1000, 0xe732f110).unwrap();
+        assert_eq!(context.get_register(2), 0x7ffffffc);
+
+        // the one signed quotient that doesn't fit wraps around
+        context.set_register(0, 0x80000000);
+        context.set_register(1, 0xffffffff);
+        execute_at(&mut context, 0x1000, 0xe712f110).unwrap();
+        assert_eq!(context.get_register(2), 0x80000000);
+    }
+
+    #[test]
+    fn divide_by_zero_follows_the_configuration() {
+        let mut context = create_context();
+
+        context.set_register(0, 0x00000007);
+        context.set_register(1, 0x00000000);
+        context.set_register(2, 0x12345678);
+        execute_at(&mut context, 0x1000, 0xe712f110).unwrap();
+        assert_eq!(context.get_register(2), 0x00000000);
+        assert_eq!(context.get_program_counter(), 0x1004);
+
+        let mut context = CpuContext::create(CpuConfiguration { divide_by_zero: DivideByZeroBehaviour::Trap, ..Default::default() });
+
+        // the trap is an error at the divide, which leaves the destination and LR alone
+        context.set_register(0, 0x00000007);
+        context.set_register(1, 0x00000000);
+        context.set_register(2, 0x12345678);
+        context.set_register(14, 0x00002000);
+        assert!(matches!(execute_at(&mut context, 0x1000, 0xe732f110), Err(EmulatorError::UndefinedInstruction(0x1000))));
+        assert_eq!(context.get_register(2), 0x12345678);
+        assert_eq!(context.get_register(14), 0x00002000);
+    }
+
+    #[test]
+    fn push_and_pop_transfer_registers_in_order() {
+        let mut context = create_context();
+
+        for register in 0..=4 {
+            context.set_register(register, 0x10 + register as u32);
+        }
+
+        context.set_register(13, 0x2000);
+        context.set_register(14, 0x1234);
+
+        // push {r0, r1, r4, lr}: the lowest register goes to the lowest address
+        execute_at(&mut context, 0x1000, 0xe92d4013).unwrap();
+        assert_eq!(context.get_register(13), 0x1ff0);
+        assert_eq!(context.read_memory(0x1ff0, 16).unwrap(), [0x10, 0, 0, 0, 0x11, 0, 0, 0, 0x14, 0, 0, 0, 0x34, 0x12, 0, 0]);
+
+        // pop {r5, r6, r7, r8}
+        execute_at(&mut context, 0x1000, 0xe8bd01e0).unwrap();
+        assert_eq!(context.get_register(13), 0x2000);
+        assert_eq!([5, 6, 7, 8].map(|register| context.get_register(register)), [0x10, 0x11, 0x14, 0x1234]);
+    }
+
+    #[test]
+    fn block_transfer_addressing_modes() {
+        let mut context = create_context();
+
+        context.write_memory(0x2000, &[1, 0, 0, 0, 2, 0, 0, 0, 3, 0, 0, 0]).unwrap();
+        context.set_register(0, 0x2004);
+
+        // ldm r0, {r1, r2} starts at the base and leaves it alone
+        execute_at(&mut context, 0x1000, 0xe8900006).unwrap();
+        assert_eq!((context.get_register(1), context.get_register(2), context.get_register(0)), (2, 3, 0x2004));
+
+        // ldmda r0!, {r1, r2} ends at the base, and writes back the lowest address less 4
+        execute_at(&mut context, 0x1000, 0xe8300006).unwrap();
+        assert_eq!((context.get_register(1), context.get_register(2), context.get_register(0)), (1, 2, 0x1ffc));
+
+        // stmib r0, {r1, r2} starts just above the base
+        execute_at(&mut context, 0x1000, 0xe9800006).unwrap();
+        assert_eq!(context.read_memory(0x2000, 8).unwrap(), [1, 0, 0, 0, 2, 0, 0, 0]);
+    }
+
+    #[test]
+    fn doubleword_transfers_use_a_register_pair() {
+        let mut context = create_context();
+
+        context.set_register(0, 0x2008);
+        context.set_register(2, 0x11111111);
+        context.set_register(3, 0x22222222);
+
+        // strd r2, r3, [r0, #-8]!
+        execute_at(&mut context, 0x1000, 0xe16020f8).unwrap();
+        assert_eq!(context.get_register(0), 0x2000);
+        assert_eq!((context.read_word(0x2000).unwrap(), context.read_word(0x2004).unwrap()), (0x11111111, 0x22222222));
+
+        // ldrd r2, r3, [r0, #8]
+        context.write_memory(0x2008, &[0x44, 0x33, 0x22, 0x11, 0x88, 0x77, 0x66, 0x55]).unwrap();
+        execute_at(&mut context, 0x1000, 0xe1c020d8).unwrap();
+        assert_eq!((context.get_register(2), context.get_register(3)), (0x11223344, 0x55667788));
+        assert_eq!(context.get_register(0), 0x2000);
+    }
+
+    #[test]
+    fn signed_loads_extend_the_sign() {
+        let mut context = create_context();
+
+        context.write_memory(0x2000, &[0x80, 0x7f, 0x00, 0x80]).unwrap();
+        context.set_register(0, 0x2000);
+
+        // ldrsb r1, [r0]
+        execute_at(&mut context, 0x1000, 0xe1d010d0).unwrap();
+        assert_eq!(context.get_register(1), 0xffffff80);
+
+        // ldrsh r1, [r0, #2], against ldrh r1, [r0, #2]
+        execute_at(&mut context, 0x1000, 0xe1d010f2).unwrap();
+        assert_eq!(context.get_register(1), 0xffff8000);
+        execute_at(&mut context, 0x1000, 0xe1d010b2).unwrap();
+        assert_eq!(context.get_register(1), 0x00008000);
+    }
+
+    #[test]
+    fn immediate_shifts_set_the_shifter_carry() {
+        let mut context = create_context();
+
+        // lsrs r1, r0, #32 shifts everything out, leaving the top bit in carry
+        context.set_register(0, 0x80000000);
+        execute_at(&mut context, 0x1000, 0xe1b01020).unwrap();
+        assert_eq!(context.get_register(1), 0x00000000);
+        assert_eq!(get_flags(&context), 0b0110);
+
+        // asrs r1, r0, #32 fills with the sign
+        execute_at(&mut context, 0x1000, 0xe1b01040).unwrap();
+        assert_eq!(context.get_register(1), 0xffffffff);
+        assert_eq!(get_flags(&context), 0b1010);
+
+        // rrxs r1, r0 shifts carry in at the top and the bottom bit out
+        context.set_register(0, 0x00000002);
+        execute_at(&mut context, 0x1000, 0xe1b01060).unwrap();
+        assert_eq!(context.get_register(1), 0x80000001);
+        assert_eq!(get_flags(&context), 0b1000);
+
+        // ands r1, r0, #0xff has no rotation, so carry is left alone
+        context.set_status(None, None, Some(true), None);
+        execute_at(&mut context, 0x1000, 0xe21010ff).unwrap();
+        assert_eq!(get_flags(&context), 0b0010);
+    }
+
+    #[test]
+    fn register_shifts_by_32_and_more() {
+        let mut context = create_context();
+
+        // lsls r1, r0, r2: by 32 the last bit out is bit 0, beyond that carry is clear
+        context.set_register(0, 0x00000001);
+        context.set_register(2, 32);
+        execute_at(&mut context, 0x1000, 0xe1b01210).unwrap();
+        assert_eq!(context.get_register(1), 0x00000000);
+        assert_eq!(get_flags(&context), 0b0110);
+        context.set_register(2, 33);
+        execute_at(&mut context, 0x1000, 0xe1b01210).unwrap();
+        assert_eq!(get_flags(&context), 0b0100);
+
+        // rors r1, r0, r2 by 32 leaves the value alone, with bit 31 in carry
+        context.set_register(0, 0x80000001);
+        context.set_register(2, 32);
+        execute_at(&mut context, 0x1000, 0xe1b01270).unwrap();
+        assert_eq!(context.get_register(1), 0x80000001);
+        assert_eq!(get_flags(&context), 0b1010);
+
+        // only the bottom byte of the register counts, and a shift by zero leaves carry alone
+        context.set_register(2, 0xffffff00);
+        context.set_status(None, None, Some(false), None);
+        execute_at(&mut context, 0x1000, 0xe1b01230).unwrap();
+        assert_eq!(context.get_register(1), 0x80000001);
+        assert_eq!(get_flags(&context), 0b1000);
+    }
+
+    #[test]
+    fn load_offsets_go_through_the_shifter() {
+        let mut context = create_context();
+
+        // ldr r1, [r0, r2, asr #1] with a negative offset register
+        context.write_memory(0x1ffc, &[0x78, 0x56, 0x34, 0x12]).unwrap();
+        context.set_register(0, 0x2000);
+        context.set_register(2, -8i32 as u32);
+        execute_at(&mut context, 0x1000, 0xe79010c2).unwrap();
+        assert_eq!(context.get_register(1), 0x12345678);
+    }
+
+    #[test]
+    fn faults_are_returned_as_errors() {
+        let mut context = CpuContext::create(CpuConfiguration { memory_size: 0x1000, ..Default::default() });
+
+        // udf #0 has no meaning, so decoding it fails instead of panicking
+        assert!(matches!(execute_at(&mut context, 0x0000, 0xe7f000f0), Err(EmulatorError::UnknownInstruction { instruction: 0xe7f000f0, .. })));
+
+        // ldr r1, [r0] and str r1, [r0] past the end of memory
+        context.set_register(0, 0x1000);
+        context.set_register(1, 0x12345678);
+        assert!(matches!(execute_at(&mut context, 0x0000, 0xe5901000), Err(EmulatorError::MemoryFault(0x1000))));
+        assert_eq!(context.get_register(1), 0x12345678);
+        assert!(matches!(execute_at(&mut context, 0x0000, 0xe5801000), Err(EmulatorError::MemoryFault(0x1000))));
+
+        assert!(matches!(context.fetch_instruction(0x1000), Err(EmulatorError::MemoryFault(0x1000))));
+    }
+
+    fn create_context_with_alignment(alignment: AlignmentBehaviour) -> CpuContext {
+        let mut context = CpuContext::create(CpuConfiguration { alignment, ..Default::default() });
+
+        context.write_memory(0x2000, &[0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88]).unwrap();
+        context.set_register(0, 0x2001);
+        context
+    }
+
+    #[test]
+    fn unaligned_accesses_read_the_bytes_at_the_address() {
+        let mut context = create_context_with_alignment(AlignmentBehaviour::Unaligned);
+
+        // ldr r1, [r0] and ldrh r1, [r0]
+        execute_at(&mut context, 0x1000, 0xe5901000).unwrap();
+        assert_eq!(context.get_register(1), 0x55443322);
+        execute_at(&mut context, 0x1000, 0xe1d010b0).unwrap();
+        assert_eq!(context.get_register(1), 0x00003322);
+
+        // ldm r0, {r1, r2} still needs a word-aligned base
+        assert!(matches!(execute_at(&mut context, 0x1000, 0xe8900006), Err(EmulatorError::AlignmentFault(0x2001))));
+    }
+
+    #[test]
+    fn strict_alignment_faults_on_unaligned_words_and_halfwords() {
+        let mut context = create_context_with_alignment(AlignmentBehaviour::Strict);
+
+        // ldr r1, [r0], ldrh r1, [r0] and str r1, [r0]; ldrb r1, [r0] can't be unaligned
+        assert!(matches!(execute_at(&mut context, 0x1000, 0xe5901000), Err(EmulatorError::AlignmentFault(0x2001))));
+        assert!(matches!(execute_at(&mut context, 0x1000, 0xe1d010b0), Err(EmulatorError::AlignmentFault(0x2001))));
+        assert!(matches!(execute_at(&mut context, 0x1000, 0xe5801000), Err(EmulatorError::AlignmentFault(0x2001))));
+        execute_at(&mut context, 0x1000, 0xe5d01000).unwrap();
+        assert_eq!(context.get_register(1), 0x00000022);
+    }
+
+    #[test]
+    fn rotate_alignment_rotates_loads_and_aligns_stores() {
+        let mut context = create_context_with_alignment(AlignmentBehaviour::Rotate);
+
+        // ldr r1, [r0] rotates the aligned word so that the addressed byte ends up at the bottom
+        execute_at(&mut context, 0x1000, 0xe5901000).unwrap();
+        assert_eq!(context.get_register(1), 0x11443322);
+
+        // str r1, [r0] ignores the bottom address bits
+        context.set_register(1, 0xaabbccdd);
+        execute_at(&mut context, 0x1000, 0xe5801000).unwrap();
+        assert_eq!(context.read_word(0x2000).unwrap(), 0xaabbccdd);
+
+        // ldm r0, {r1, r2} does too
+        execute_at(&mut context, 0x1000, 0xe8900006).unwrap();
+        assert_eq!((context.get_register(1), context.get_register(2)), (0xaabbccdd, 0x88776655));
+    }
+
+    #[test]
+    fn setend_switches_the_byte_order_of_data_but_not_instructions() {
+        let mut context = create_context();
+
+        context.write_memory(0x2000, &[0x11, 0x22, 0x33, 0x44]).unwrap();
+        context.set_register(0, 0x2000);
+
+        // setend be, then ldr r1, [r0] and ldrh r1, [r0]
+        execute_at(&mut context, 0x1000, 0xf1010200).unwrap();
+        assert_eq!(context.get_endianness(), Endianness::Big);
+        assert_eq!(context.get_program_status_register() & (1 << 9), 1 << 9);
+        execute_at(&mut context, 0x1000, 0xe5901000).unwrap();
+        assert_eq!(context.get_register(1), 0x11223344);
+        execute_at(&mut context, 0x1000, 0xe1d010b0).unwrap();
+        assert_eq!(context.get_register(1), 0x00001122);
+
+        // strh r1, [r0] puts the most significant byte first
+        context.set_register(1, 0x0000aabb);
+        execute_at(&mut context, 0x1000, 0xe1c010b0).unwrap();
+        assert_eq!(context.read_memory(0x2000, 2).unwrap(), [0xaa, 0xbb]);
+
+        // instructions are still fetched little-endian
+        context.write_memory(0x3000, &[0x00, 0x10, 0x90, 0xe5]).unwrap();
+        assert_eq!(context.fetch_instruction(0x3000).unwrap(), 0xe5901000);
+
+        // setend le
+        execute_at(&mut context, 0x1000, 0xf1010000).unwrap();
+        execute_at(&mut context, 0x1000, 0xe5901000).unwrap();
+        assert_eq!(context.get_register(1), 0x4433bbaa);
+    }
+}
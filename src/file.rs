@@ -1,24 +1,565 @@
-use std::{fs, mem::size_of, path::Path};
+use std::fs;
 
-use crate::context::*;
+use crate::{context::*, error::EmulatorError, memory::Permissions};
 
+const ELF_MAGIC: [u8; 4] = [ 0x7F, 0x45, 0x4C, 0x46 ];
+const ELF_CLASS_OFFSET: usize = 0x04;
+const ELF_DATA_OFFSET: usize = 0x05;
+const ELF_VERSION_OFFSET: usize = 0x06;
+const ELF_TYPE_OFFSET: usize = 0x10;
+const ELF_MACHINE_OFFSET: usize = 0x12;
 const ELF_ENTRY_POINT_OFFSET: usize = 0x18;
-const ELF_ENTRY_POINT_SIZE: usize = size_of::<u32>();
+const ELF_PROGRAM_HEADER_OFFSET_OFFSET: usize = 0x1C;
+const ELF_SECTION_HEADER_OFFSET_OFFSET: usize = 0x20;
+const ELF_FLAGS_OFFSET: usize = 0x24;
+const ELF_PROGRAM_HEADER_ENTRY_SIZE_OFFSET: usize = 0x2A;
+const ELF_PROGRAM_HEADER_COUNT_OFFSET: usize = 0x2C;
+const ELF_SECTION_HEADER_ENTRY_SIZE_OFFSET: usize = 0x2E;
+const ELF_SECTION_HEADER_COUNT_OFFSET: usize = 0x30;
+const ELF_SECTION_NAME_TABLE_INDEX_OFFSET: usize = 0x32;
 
-pub fn read_memory_from_file(context: &mut CpuContext, path: &str) {
-    let path = Path::new(path);
-    let bytes = fs::read(path).unwrap();
+const ELF_CLASS_32: u8 = 1;
+const ELF_DATA_LITTLE_ENDIAN: u8 = 1;
+const ELF_DATA_BIG_ENDIAN: u8 = 2;
+const ELF_CURRENT_VERSION: u8 = 1;
+const ELF_TYPE_EXECUTABLE: u16 = 2;
+const ELF_MACHINE_ARM: u16 = 40;
+const ELF_FLAGS_ARM_BE8: u32 = 0x00800000;
+const ELF_FLAGS_ARM_EABI_VERSION_5: u32 = 0x05000000;
+const ELF_HEADER_SIZE: usize = 0x34;
 
-    println!("Read {} bytes from {}", bytes.len(), path.file_name().unwrap().to_str().unwrap());
+const PROGRAM_HEADER_SIZE: usize = 0x20;
+const PROGRAM_HEADER_TYPE_LOAD: u32 = 1;
+const PROGRAM_HEADER_TYPE_PROGRAM_HEADER: u32 = 6;
+const PROGRAM_HEADER_FLAG_EXECUTE: u32 = 1;
+const PROGRAM_HEADER_FLAG_WRITE: u32 = 2;
+const PROGRAM_HEADER_FLAG_READ: u32 = 4;
 
-    context.write_memory(&bytes);
+const SECTION_HEADER_SIZE: usize = 0x28;
+const SECTION_TYPE_PROGRAM_DATA: u32 = 1;
+const SECTION_TYPE_SYMBOL_TABLE: u32 = 2;
+const SECTION_TYPE_STRING_TABLE: u32 = 3;
+const SECTION_FLAG_WRITE: u32 = 1;
+const SECTION_FLAG_ALLOCATE: u32 = 2;
+const SECTION_FLAG_EXECUTE: u32 = 4;
+const SYMBOL_SIZE: usize = 0x10;
+const SYMBOL_TYPE_MASK: u8 = 0xf;
+const SYMBOL_TYPE_NONE: u8 = 0;
+const SYMBOL_TYPE_OBJECT: u8 = 1;
+const SYMBOL_TYPE_FUNCTION: u8 = 2;
+const SYMBOL_BINDING_GLOBAL: u8 = 1;
+const SECTION_INDEX_UNDEFINED: u16 = 0;
 
-    let elf_magic: [u8; 8] = [ 0x7F, 0x45, 0x4C, 0x46, 0x01, 0x01, 0x01, 0x00 ];
-    if bytes.starts_with(&elf_magic)
-    && bytes.len() > ELF_ENTRY_POINT_OFFSET + ELF_ENTRY_POINT_SIZE {
-        let entry_point = context.read_word(ELF_ENTRY_POINT_OFFSET as u32);
+// the sections create_executable writes: null, .text, .symtab, .strtab and .shstrtab
+const EXECUTABLE_SECTION_NAMES: &[u8] = b"\0.text\0.symtab\0.strtab\0.shstrtab\0";
+const EXECUTABLE_SECTION_COUNT: u16 = 5;
+const EXECUTABLE_TEXT_SECTION_INDEX: u16 = 1;
+const EXECUTABLE_SECTION_NAME_TABLE_INDEX: u16 = 4;
+const WORD_ALIGNMENT: u32 = 4;
 
-        println!("File is ELF; entry point offset is {:0>8X}", entry_point);
-        context.set_program_counter(entry_point);
+// what a process needs to know about the program it's running; for raw images only the entry point is known
+#[derive(Copy, Clone, Debug, Default)]
+pub struct LoadedImage {
+    pub entry_point: u32,
+    pub program_header_address: u32,
+    pub program_header_entry_size: u16,
+    pub program_header_count: u16,
+    pub end_address: u32,           // the first address after the highest loaded segment
+}
+
+// a named address from the program's symbol table, such as a function or a variable
+#[derive(Clone, Debug, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub address: u32,
+    pub size: u32,
+}
+
+// a part of a program that contains code: an executable section, or if there are no section headers, an executable segment
+#[derive(Clone, Debug)]
+pub struct CodeRegion {
+    pub name: Option<String>,      // the section name
+    pub address: u32,
+    pub data: Vec<u8>,
+}
+
+pub fn read_memory_from_file(context: &mut CpuContext, path: &str) -> Result<LoadedImage, EmulatorError> {
+    let bytes = fs::read(path)?;
+
+    let image = if bytes.starts_with(&ELF_MAGIC) {
+        load_elf(context, &bytes)?
+    } else {
+        // anything that isn't ELF is treated as a raw image, loaded at and started from address zero
+        context.load_memory(0, &bytes)?;
+        LoadedImage { end_address: bytes.len() as u32, ..LoadedImage::default() }
+    };
+
+    context.set_program_counter(image.entry_point);
+    Ok(image)
+}
+
+struct ProgramHeader {
+    segment_type: u32,
+    offset: u32,
+    virtual_address: u32,
+    file_size: u32,
+    memory_size: u32,
+    flags: u32,
+}
+
+// places every PT_LOAD segment at its virtual address, and returns the entry point and where the program headers ended up.
+// Big-endian executables start with big-endian data accesses enabled
+fn load_elf(context: &mut CpuContext, bytes: &[u8]) -> Result<LoadedImage, EmulatorError> {
+    let endianness = validate_elf_identification(bytes)?;
+
+    let program_header_offset = read_u32(bytes, ELF_PROGRAM_HEADER_OFFSET_OFFSET, endianness)? as usize;
+    let program_header_entry_size = read_u16(bytes, ELF_PROGRAM_HEADER_ENTRY_SIZE_OFFSET, endianness)? as usize;
+    let program_header_count = read_u16(bytes, ELF_PROGRAM_HEADER_COUNT_OFFSET, endianness)? as usize;
+
+    if program_header_count > 0 && program_header_entry_size < PROGRAM_HEADER_SIZE {
+        return Err(invalid_executable(format!("Program header entry size {} is too small", program_header_entry_size)));
+    }
+
+    let mut program_header_address = 0;
+    let mut end_address = 0;
+
+    for index in 0..program_header_count {
+        let header = read_program_header(bytes, program_header_offset + index * program_header_entry_size, endianness)?;
+
+        match header.segment_type {
+            PROGRAM_HEADER_TYPE_LOAD => {
+                load_segment(context, bytes, &header)?;
+                end_address = end_address.max(header.virtual_address.saturating_add(header.memory_size));
+
+                // without a PT_PHDR segment, the program headers are found in the segment loaded from the file offset they're at
+                let segment_offsets = (header.offset as usize)..(header.offset as usize + header.file_size as usize);
+                if program_header_address == 0 && segment_offsets.contains(&program_header_offset) {
+                    program_header_address = header.virtual_address.wrapping_add((program_header_offset - header.offset as usize) as u32);
+                }
+            },
+            PROGRAM_HEADER_TYPE_PROGRAM_HEADER => program_header_address = header.virtual_address,
+            _ => {},
+        }
+    }
+
+    context.set_endianness(endianness);
+
+    Ok(LoadedImage {
+        entry_point: read_u32(bytes, ELF_ENTRY_POINT_OFFSET, endianness)?,
+        program_header_address,
+        program_header_entry_size: program_header_entry_size as u16,
+        program_header_count: program_header_count as u16,
+        end_address,
+    })
+}
+
+// returns the byte order of the file
+fn validate_elf_identification(bytes: &[u8]) -> Result<Endianness, EmulatorError> {
+    let class = read_u8(bytes, ELF_CLASS_OFFSET)?;
+    let data = read_u8(bytes, ELF_DATA_OFFSET)?;
+    let version = read_u8(bytes, ELF_VERSION_OFFSET)?;
+
+    if class != ELF_CLASS_32 {
+        return Err(invalid_executable(format!("Unsupported ELF class {} (only 32-bit ELF files are supported)", class)));
+    }
+
+    let endianness = match data {
+        ELF_DATA_LITTLE_ENDIAN => Endianness::Little,
+        ELF_DATA_BIG_ENDIAN => Endianness::Big,
+        _ => return Err(invalid_executable(format!("Unsupported ELF data encoding {}", data))),
+    };
+
+    if version != ELF_CURRENT_VERSION {
+        return Err(invalid_executable(format!("Unsupported ELF version {}", version)));
+    }
+
+    let file_type = read_u16(bytes, ELF_TYPE_OFFSET, endianness)?;
+    let machine = read_u16(bytes, ELF_MACHINE_OFFSET, endianness)?;
+
+    if file_type != ELF_TYPE_EXECUTABLE {
+        return Err(invalid_executable(format!("Unsupported ELF file type {} (only executables are supported)", file_type)));
+    }
+
+    if machine != ELF_MACHINE_ARM {
+        return Err(invalid_executable(format!("Unsupported machine type {} (expected EM_ARM)", machine)));
+    }
+
+    // only BE8 images, where instructions stay little-endian, can run; BE32 images have big-endian instructions
+    if endianness == Endianness::Big && read_u32(bytes, ELF_FLAGS_OFFSET, endianness)? & ELF_FLAGS_ARM_BE8 == 0 {
+        return Err(invalid_executable(String::from("Unsupported big-endian executable (only BE8 executables are supported)")));
+    }
+
+    Ok(endianness)
+}
+
+fn read_program_header(bytes: &[u8], offset: usize, endianness: Endianness) -> Result<ProgramHeader, EmulatorError> {
+    Ok(ProgramHeader {
+        segment_type: read_u32(bytes, offset, endianness)?,
+        offset: read_u32(bytes, offset + 0x04, endianness)?,
+        virtual_address: read_u32(bytes, offset + 0x08, endianness)?,
+        file_size: read_u32(bytes, offset + 0x10, endianness)?,
+        memory_size: read_u32(bytes, offset + 0x14, endianness)?,
+        flags: read_u32(bytes, offset + 0x18, endianness)?,
+    })
+}
+
+fn load_segment(context: &mut CpuContext, bytes: &[u8], header: &ProgramHeader) -> Result<(), EmulatorError> {
+    let data = get_segment_data(bytes, header)?;
+
+    // each segment is a region of its own, so that its flags apply: writing to code or executing data faults
+    if header.memory_size > 0 {
+        let permissions = Permissions {
+            read: header.flags & PROGRAM_HEADER_FLAG_READ != 0,
+            write: header.flags & PROGRAM_HEADER_FLAG_WRITE != 0,
+            execute: header.flags & PROGRAM_HEADER_FLAG_EXECUTE != 0,
+        };
+
+        context.get_memory_mut().map_memory(&get_segment_name(header.virtual_address), header.virtual_address, header.memory_size as u64, permissions)?;
+    }
+
+    context.load_memory(header.virtual_address, data)?;
+
+    // whatever the file doesn't provide (typically .bss) is zero, even if something was loaded there before
+    let bss_address = header.virtual_address.wrapping_add(header.file_size);
+    context.get_memory_mut().clear(bss_address, (header.memory_size - header.file_size) as usize)
+}
+
+fn get_segment_name(address: u32) -> String {
+    format!("segment {:0>8X}", address)
+}
+
+// reads the functions, variables and labels from an ELF file's symbol table. Raw images and stripped files have no symbols,
+// and neither do ARM mapping symbols such as $a and $d
+pub fn read_symbols(path: &str) -> Result<Vec<Symbol>, EmulatorError> {
+    let bytes = fs::read(path)?;
+    if !bytes.starts_with(&ELF_MAGIC) {
+        return Ok(Vec::new());
+    }
+
+    let endianness = validate_elf_identification(&bytes)?;
+    let section_header_offset = read_u32(&bytes, ELF_SECTION_HEADER_OFFSET_OFFSET, endianness)? as usize;
+    let section_header_entry_size = read_u16(&bytes, ELF_SECTION_HEADER_ENTRY_SIZE_OFFSET, endianness)? as usize;
+    let section_header_count = read_u16(&bytes, ELF_SECTION_HEADER_COUNT_OFFSET, endianness)? as usize;
+
+    if section_header_count > 0 && section_header_entry_size < SECTION_HEADER_SIZE {
+        return Err(invalid_executable(format!("Section header entry size {} is too small", section_header_entry_size)));
+    }
+
+    let mut symbols = Vec::new();
+
+    for index in 0..section_header_count {
+        let offset = section_header_offset + index * section_header_entry_size;
+        if read_u32(&bytes, offset + 0x04, endianness)? != SECTION_TYPE_SYMBOL_TABLE {
+            continue;
+        }
+
+        let table_offset = read_u32(&bytes, offset + 0x10, endianness)? as usize;
+        let table_size = read_u32(&bytes, offset + 0x14, endianness)? as usize;
+        let string_table_index = read_u32(&bytes, offset + 0x18, endianness)? as usize;
+        let string_table_offset = read_u32(&bytes, section_header_offset + string_table_index * section_header_entry_size + 0x10, endianness)? as usize;
+
+        for symbol_offset in (table_offset..table_offset + table_size).step_by(SYMBOL_SIZE) {
+            let symbol_type = read_u8(&bytes, symbol_offset + 0x0C)? & SYMBOL_TYPE_MASK;
+            let section_index = read_u16(&bytes, symbol_offset + 0x0E, endianness)?;
+
+            if !matches!(symbol_type, SYMBOL_TYPE_NONE | SYMBOL_TYPE_OBJECT | SYMBOL_TYPE_FUNCTION) || section_index == SECTION_INDEX_UNDEFINED {
+                continue;
+            }
+
+            let name_offset = string_table_offset + read_u32(&bytes, symbol_offset, endianness)? as usize;
+            let name = read_string(&bytes, name_offset)?;
+
+            if name.is_empty() || name.starts_with('$') {
+                continue;
+            }
+
+            symbols.push(Symbol {
+                name,
+                address: read_u32(&bytes, symbol_offset + 0x04, endianness)?,
+                size: read_u32(&bytes, symbol_offset + 0x08, endianness)?,
+            });
+        }
+    }
+
+    Ok(symbols)
+}
+
+// the code in a program, for disassembling. Anything that isn't ELF is a raw image, which is code from address zero on
+pub fn read_code_regions(path: &str) -> Result<Vec<CodeRegion>, EmulatorError> {
+    let bytes = fs::read(path)?;
+    if !bytes.starts_with(&ELF_MAGIC) {
+        return Ok(vec![CodeRegion { name: None, address: 0, data: bytes }]);
+    }
+
+    let endianness = validate_elf_identification(&bytes)?;
+    let sections = read_code_sections(&bytes, endianness)?;
+    if !sections.is_empty() {
+        return Ok(sections);
+    }
+
+    let program_header_offset = read_u32(&bytes, ELF_PROGRAM_HEADER_OFFSET_OFFSET, endianness)? as usize;
+    let program_header_entry_size = read_u16(&bytes, ELF_PROGRAM_HEADER_ENTRY_SIZE_OFFSET, endianness)? as usize;
+    let program_header_count = read_u16(&bytes, ELF_PROGRAM_HEADER_COUNT_OFFSET, endianness)? as usize;
+
+    if program_header_count > 0 && program_header_entry_size < PROGRAM_HEADER_SIZE {
+        return Err(invalid_executable(format!("Program header entry size {} is too small", program_header_entry_size)));
+    }
+
+    let mut regions = Vec::new();
+
+    for index in 0..program_header_count {
+        let header = read_program_header(&bytes, program_header_offset + index * program_header_entry_size, endianness)?;
+
+        if header.segment_type == PROGRAM_HEADER_TYPE_LOAD && header.flags & PROGRAM_HEADER_FLAG_EXECUTE != 0 {
+            regions.push(CodeRegion { name: None, address: header.virtual_address, data: get_segment_data(&bytes, &header)?.to_vec() });
+        }
+    }
+
+    Ok(regions)
+}
+
+fn read_code_sections(bytes: &[u8], endianness: Endianness) -> Result<Vec<CodeRegion>, EmulatorError> {
+    let section_header_offset = read_u32(bytes, ELF_SECTION_HEADER_OFFSET_OFFSET, endianness)? as usize;
+    let section_header_entry_size = read_u16(bytes, ELF_SECTION_HEADER_ENTRY_SIZE_OFFSET, endianness)? as usize;
+    let section_header_count = read_u16(bytes, ELF_SECTION_HEADER_COUNT_OFFSET, endianness)? as usize;
+    let section_name_table_index = read_u16(bytes, ELF_SECTION_NAME_TABLE_INDEX_OFFSET, endianness)? as usize;
+
+    if section_header_count > 0 && section_header_entry_size < SECTION_HEADER_SIZE {
+        return Err(invalid_executable(format!("Section header entry size {} is too small", section_header_entry_size)));
+    }
+
+    let mut regions = Vec::new();
+
+    for index in 0..section_header_count {
+        let offset = section_header_offset + index * section_header_entry_size;
+        let section_type = read_u32(bytes, offset + 0x04, endianness)?;
+        let flags = read_u32(bytes, offset + 0x08, endianness)?;
+
+        if section_type != SECTION_TYPE_PROGRAM_DATA || flags & SECTION_FLAG_EXECUTE == 0 {
+            continue;
+        }
+
+        let name_table_offset = read_u32(bytes, section_header_offset + section_name_table_index * section_header_entry_size + 0x10, endianness)? as usize;
+        let name = read_string(bytes, name_table_offset + read_u32(bytes, offset, endianness)? as usize)?;
+
+        let start = read_u32(bytes, offset + 0x10, endianness)? as usize;
+        let size = read_u32(bytes, offset + 0x14, endianness)? as usize;
+        let data = start.checked_add(size)
+            .and_then(|end| bytes.get(start..end))
+            .ok_or_else(|| invalid_executable(format!("Section {} extends past the end of the file", name)))?;
+
+        regions.push(CodeRegion { name: Some(name), address: read_u32(bytes, offset + 0x0C, endianness)?, data: data.to_vec() });
+    }
+
+    Ok(regions)
+}
+
+fn get_segment_data<'a>(bytes: &'a [u8], header: &ProgramHeader) -> Result<&'a [u8], EmulatorError> {
+    if header.file_size > header.memory_size {
+        return Err(invalid_executable(format!("Segment at {:0>8X} is larger in the file than in memory", header.virtual_address)));
+    }
+
+    let start = header.offset as usize;
+    start.checked_add(header.file_size as usize)
+        .and_then(|end| bytes.get(start..end))
+        .ok_or_else(|| invalid_executable(format!("Segment at {:0>8X} extends past the end of the file", header.virtual_address)))
+}
+
+// the symbol the address is in, if any; symbols without a size extend up to the next one
+pub fn find_symbol(symbols: &[Symbol], address: u32) -> Option<&Symbol> {
+    symbols.iter()
+        .filter(|s| s.address <= address && (s.size == 0 || address - s.address < s.size))
+        .max_by_key(|s| s.address)
+}
+
+// builds a little-endian ELF executable with one segment holding the data, which is readable, writable and executable.
+// The same data is described as a .text section, next to a symbol table with the given symbols
+pub fn create_executable(address: u32, data: &[u8], entry_point: u32, symbols: &[Symbol]) -> Vec<u8> {
+    let data_offset = ELF_HEADER_SIZE + PROGRAM_HEADER_SIZE;
+    let mut bytes = Vec::new();
+
+    bytes.extend_from_slice(&ELF_MAGIC);
+    bytes.extend_from_slice(&[ ELF_CLASS_32, ELF_DATA_LITTLE_ENDIAN, ELF_CURRENT_VERSION ]);
+    bytes.resize(ELF_TYPE_OFFSET, 0);
+    push_u16(&mut bytes, ELF_TYPE_EXECUTABLE);
+    push_u16(&mut bytes, ELF_MACHINE_ARM);
+    push_u32(&mut bytes, ELF_CURRENT_VERSION as u32);
+    push_u32(&mut bytes, entry_point);
+    push_u32(&mut bytes, ELF_HEADER_SIZE as u32);
+    push_u32(&mut bytes, 0);        // the section header offset, filled in below
+    push_u32(&mut bytes, ELF_FLAGS_ARM_EABI_VERSION_5);
+    push_u16(&mut bytes, ELF_HEADER_SIZE as u16);
+    push_u16(&mut bytes, PROGRAM_HEADER_SIZE as u16);
+    push_u16(&mut bytes, 1);
+    push_u16(&mut bytes, SECTION_HEADER_SIZE as u16);
+    push_u16(&mut bytes, EXECUTABLE_SECTION_COUNT);
+    push_u16(&mut bytes, EXECUTABLE_SECTION_NAME_TABLE_INDEX);
+
+    let segment_flags = PROGRAM_HEADER_FLAG_READ | PROGRAM_HEADER_FLAG_WRITE | PROGRAM_HEADER_FLAG_EXECUTE;
+    for value in [ PROGRAM_HEADER_TYPE_LOAD, data_offset as u32, address, address, data.len() as u32, data.len() as u32, segment_flags, WORD_ALIGNMENT ].iter() {
+        push_u32(&mut bytes, *value);
+    }
+
+    bytes.extend_from_slice(data);
+
+    // index 0 of both tables is reserved: an empty name and a null symbol
+    let mut string_table = vec![0u8];
+    let mut symbol_table = vec![0u8; SYMBOL_SIZE];
+
+    for symbol in symbols.iter() {
+        push_u32(&mut symbol_table, string_table.len() as u32);
+        push_u32(&mut symbol_table, symbol.address);
+        push_u32(&mut symbol_table, symbol.size);
+        symbol_table.extend_from_slice(&[ (SYMBOL_BINDING_GLOBAL << 4) | SYMBOL_TYPE_NONE, 0 ]);
+        push_u16(&mut symbol_table, EXECUTABLE_TEXT_SECTION_INDEX);
+
+        string_table.extend_from_slice(symbol.name.as_bytes());
+        string_table.push(0);
+    }
+
+    let string_table_offset = bytes.len();
+    bytes.extend_from_slice(&string_table);
+
+    let section_name_table_offset = bytes.len();
+    bytes.extend_from_slice(EXECUTABLE_SECTION_NAMES);
+
+    bytes.resize((bytes.len() + 3) & !3, 0);
+    let symbol_table_offset = bytes.len();
+    bytes.extend_from_slice(&symbol_table);
+
+    let section_header_offset = bytes.len();
+    bytes[ELF_SECTION_HEADER_OFFSET_OFFSET..ELF_SECTION_HEADER_OFFSET_OFFSET + 4].copy_from_slice(&(section_header_offset as u32).to_le_bytes());
+
+    // name, type, flags, address, offset, size, link, info, alignment and entry size; the symbol table links to the string
+    // table, and its info field is the index of the first global symbol
+    let section_headers = [
+        [ 0; 10 ],
+        [ 1, SECTION_TYPE_PROGRAM_DATA, SECTION_FLAG_ALLOCATE | SECTION_FLAG_WRITE | SECTION_FLAG_EXECUTE, address, data_offset as u32, data.len() as u32, 0, 0, WORD_ALIGNMENT, 0 ],
+        [ 7, SECTION_TYPE_SYMBOL_TABLE, 0, 0, symbol_table_offset as u32, symbol_table.len() as u32, 3, 1, WORD_ALIGNMENT, SYMBOL_SIZE as u32 ],
+        [ 15, SECTION_TYPE_STRING_TABLE, 0, 0, string_table_offset as u32, string_table.len() as u32, 0, 0, 1, 0 ],
+        [ 23, SECTION_TYPE_STRING_TABLE, 0, 0, section_name_table_offset as u32, EXECUTABLE_SECTION_NAMES.len() as u32, 0, 0, 1, 0 ],
+    ];
+
+    for header in section_headers.iter() {
+        for value in header.iter() {
+            push_u32(&mut bytes, *value);
+        }
+    }
+
+    bytes
+}
+
+fn push_u16(bytes: &mut Vec<u8>, value: u16) {
+    bytes.extend_from_slice(&value.to_le_bytes());
+}
+
+fn push_u32(bytes: &mut Vec<u8>, value: u32) {
+    bytes.extend_from_slice(&value.to_le_bytes());
+}
+
+fn read_string(bytes: &[u8], offset: usize) -> Result<String, EmulatorError> {
+    let tail = bytes.get(offset..).unwrap_or_default();
+    let length = tail.iter().position(|&b| b == 0)
+        .ok_or_else(|| invalid_executable(format!("Unterminated string at offset {:0>8X}", offset)))?;
+
+    Ok(String::from_utf8_lossy(&tail[..length]).into_owned())
+}
+
+fn read_u8(bytes: &[u8], offset: usize) -> Result<u8, EmulatorError> {
+    bytes.get(offset)
+        .copied()
+        .ok_or_else(|| invalid_executable(String::from("File is too short to be an ELF file")))
+}
+
+fn read_u16(bytes: &[u8], offset: usize, endianness: Endianness) -> Result<u16, EmulatorError> {
+    let mut value = [0u8; 2];
+    value.copy_from_slice(read_bytes(bytes, offset, 2)?);
+
+    match endianness {
+        Endianness::Little => Ok(u16::from_le_bytes(value)),
+        Endianness::Big => Ok(u16::from_be_bytes(value)),
+    }
+}
+
+fn read_u32(bytes: &[u8], offset: usize, endianness: Endianness) -> Result<u32, EmulatorError> {
+    let mut value = [0u8; 4];
+    value.copy_from_slice(read_bytes(bytes, offset, 4)?);
+
+    match endianness {
+        Endianness::Little => Ok(u32::from_le_bytes(value)),
+        Endianness::Big => Ok(u32::from_be_bytes(value)),
+    }
+}
+
+fn read_bytes(bytes: &[u8], offset: usize, length: usize) -> Result<&[u8], EmulatorError> {
+    offset.checked_add(length)
+        .and_then(|end| bytes.get(offset..end))
+        .ok_or_else(|| invalid_executable(format!("Unexpected end of file reading offset {:0>8X}", offset)))
+}
+
+fn invalid_executable(message: String) -> EmulatorError {
+    EmulatorError::InvalidExecutable(message)
+}
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+    use crate::memory::MemoryAccess;
+
+    const PROGRAM_HEADER_MEMORY_SIZE_OFFSET: usize = ELF_HEADER_SIZE + 0x14;
+    const PROGRAM_HEADER_FLAGS_OFFSET: usize = ELF_HEADER_SIZE + 0x18;
+
+    // writes an executable with one segment at the address, holding the data followed by memory_size - data.len() zero bytes
+    fn write_executable(name: &str, address: u32, data: &[u8], memory_size: u32, flags: u32) -> String {
+        let mut bytes = create_executable(address, data, address, &[]);
+        bytes[PROGRAM_HEADER_MEMORY_SIZE_OFFSET..PROGRAM_HEADER_MEMORY_SIZE_OFFSET + 4].copy_from_slice(&memory_size.to_le_bytes());
+        bytes[PROGRAM_HEADER_FLAGS_OFFSET..PROGRAM_HEADER_FLAGS_OFFSET + 4].copy_from_slice(&flags.to_le_bytes());
+
+        let path = env::temp_dir().join(format!("rusty_arm_{}_{}.elf", name, std::process::id()));
+        fs::write(&path, bytes).unwrap();
+
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn segment_memory_beyond_the_file_is_cleared() {
+        let path = write_executable("bss", 0x8000, &[0xAA; 6], 0x3000, PROGRAM_HEADER_FLAG_READ | PROGRAM_HEADER_FLAG_WRITE);
+        let mut context = CpuContext::create(CpuConfiguration { memory_size: 0x10000, ..CpuConfiguration::default() });
+        context.write_memory(0x8000, &[0x55; 0x4000]).unwrap();
+
+        let image = read_memory_from_file(&mut context, &path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(image.unwrap().end_address, 0xB000);
+        assert_eq!(context.read_memory(0x8000, 8).unwrap(), [0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0, 0]);
+        assert!(context.read_memory(0x8006, 0x2FFA).unwrap().iter().all(|&b| b == 0));
+        assert_eq!(context.read_memory(0xB000, 4).unwrap(), [0x55; 4]);
+    }
+
+    #[test]
+    fn segments_are_mapped_with_their_flags() {
+        let path = write_executable("text", 0x20000, &[0xAA; 8], 0x2000, PROGRAM_HEADER_FLAG_READ | PROGRAM_HEADER_FLAG_EXECUTE);
+        let mut context = CpuContext::create(CpuConfiguration { memory_size: 0x10000, ..CpuConfiguration::default() });
+
+        let image = read_memory_from_file(&mut context, &path);
+        fs::remove_file(&path).unwrap();
+        image.unwrap();
+
+        // the segment is mapped even though it lies beyond the RAM at address zero
+        assert_eq!(context.get_memory().get_region_name(0x21FFF), Some("segment 00020000"));
+        assert_eq!(context.get_memory().get_region_name(0x22000), None);
+
+        assert_eq!(context.fetch_instruction(0x20004).unwrap(), 0xAAAAAAAA);
+        assert_eq!(context.read_memory(0x21FFC, 4).unwrap(), [0; 4]);
+        assert!(matches!(context.write_memory(0x20000, &[0]), Err(EmulatorError::PermissionFault { address: 0x20000, access: MemoryAccess::Write })));
+
+        let path = write_executable("data", 0x8000, &[0xAA; 8], 0x1000, PROGRAM_HEADER_FLAG_READ | PROGRAM_HEADER_FLAG_WRITE);
+        let image = read_memory_from_file(&mut context, &path);
+        fs::remove_file(&path).unwrap();
+        image.unwrap();
+
+        context.write_memory(0x8000, &[0x55]).unwrap();
+        assert!(matches!(context.fetch_instruction(0x8000), Err(EmulatorError::PermissionFault { address: 0x8000, access: MemoryAccess::Execute })));
     }
-}
\ No newline at end of file
+}
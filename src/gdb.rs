@@ -0,0 +1,477 @@
+use std::{
+    io::{self, Read, Write},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::{
+    context::{CpuContext, Endianness},
+    machine::{Breakpoint, Machine, StopReason, WatchKind, Watchpoint},
+    memory::MemoryAccess,
+};
+
+// how the client ended the session
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SessionEnd {
+    Detached,       // the guest should carry on without the debugger
+    Killed,         // the guest should be stopped
+    Disconnected,   // the connection was closed without either
+}
+
+// waits for a GDB client to connect to the port on the local machine, and serves it until it detaches, kills the guest or disconnects.
+// This speaks just enough of the remote serial protocol for registers, memory, stepping, breakpoints and watchpoints
+pub fn serve(machine: &mut Machine, port: u16) -> io::Result<SessionEnd> {
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    let (stream, _) = listener.accept()?;
+    stream.set_nodelay(true)?;
+
+    let mut session = GdbSession {
+        stream,
+        acknowledge: true,
+        last_stop: String::from(TRAP_STOP_REPLY),
+    };
+
+    session.run(machine)
+}
+
+struct GdbSession {
+    stream: TcpStream,
+    acknowledge: bool,      // until the client asks for no-ack mode, every packet is acknowledged with + or -
+    last_stop: String,
+}
+
+impl GdbSession {
+    fn run(&mut self, machine: &mut Machine) -> io::Result<SessionEnd> {
+        loop {
+            let packet = match self.receive_packet()? {
+                Some(packet) => packet,
+                None => return Ok(SessionEnd::Disconnected),
+            };
+
+            match packet.as_bytes().first() {
+                Some(b'k') => return Ok(SessionEnd::Killed),
+                Some(b'D') => {
+                    self.send_packet(OK_REPLY)?;
+                    return Ok(SessionEnd::Detached);
+                },
+                _ => {},
+            }
+
+            let reply = self.handle_packet(machine, &packet);
+            self.send_packet(&reply)?;
+
+            if packet == "QStartNoAckMode" {
+                self.acknowledge = false;
+            }
+        }
+    }
+
+    fn handle_packet(&mut self, machine: &mut Machine, packet: &str) -> String {
+        let (command, arguments) = packet.split_at(packet.chars().next().map_or(0, |c| c.len_utf8()));
+
+        let reply = match command {
+            INTERRUPT_PACKET => Some(String::from(INTERRUPT_STOP_REPLY)),
+            "?" => Some(self.last_stop.clone()),
+            "g" => Some(read_registers(machine.get_context())),
+            "G" => write_registers(machine.get_context_mut(), arguments),
+            "p" => parse_hex(arguments).and_then(|r| read_register(machine.get_context(), r)),
+            "P" => write_register(machine.get_context_mut(), arguments),
+            "m" => read_memory(machine.get_context(), arguments),
+            "M" => write_memory(machine.get_context_mut(), arguments),
+            "s" | "c" => {
+                if let Some(address) = parse_hex(arguments) {
+                    machine.get_context_mut().set_program_counter(address);
+                }
+
+                let reply = self.resume(machine, command == "s");
+                self.last_stop = reply.clone();
+                Some(reply)
+            },
+            "Z" | "z" => set_breakpoint(machine, arguments, command == "Z"),
+            "H" | "T" => Some(String::from(OK_REPLY)),
+            "q" | "Q" => Some(handle_query(packet)),
+            _ => Some(String::new()),   // an empty reply means the packet isn't supported
+        };
+
+        reply.unwrap_or_else(|| String::from(ERROR_REPLY))
+    }
+
+    // runs until the guest stops, and returns the stop reply
+    fn resume(&mut self, machine: &mut Machine, single_step: bool) -> String {
+        let result = if single_step {
+            machine.run_for(1)
+        } else {
+            let stream = &mut self.stream;
+            let mut count = 0u32;
+
+            machine.run_until(|_| {
+                count = count.wrapping_add(1);
+                count.is_multiple_of(INTERRUPT_CHECK_INTERVAL) && is_interrupted(stream)
+            })
+        };
+
+        match result {
+            Ok(StopReason::Halted) => format!("W{:0>2x}", machine.get_exit_status().unwrap_or(0) & 0xff),
+            Ok(StopReason::ConditionMet) => String::from(INTERRUPT_STOP_REPLY),
+            Ok(StopReason::Watchpoint { address, access }) => {
+                let kind = match access {
+                    MemoryAccess::Write => "watch",
+                    _ => "rwatch",
+                };
+
+                format!("T{:0>2x}{}:{:0>8x};", SIGTRAP, kind, address)
+            },
+            Ok(StopReason::Breakpoint(_)) | Ok(StopReason::CycleLimitReached) => String::from(TRAP_STOP_REPLY),
+            Err(e) => format!("S{:0>2x}", e.get_signal_number().unwrap_or(SIGABRT)),
+        }
+    }
+
+    // returns None when the client disconnects
+    fn receive_packet(&mut self) -> io::Result<Option<String>> {
+        loop {
+            match self.read_byte()? {
+                None => return Ok(None),
+                Some(INTERRUPT) => return Ok(Some(String::from(INTERRUPT_PACKET))),
+                Some(b'$') => {},
+                Some(_) => continue,    // acknowledgements, and noise between packets
+            }
+
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    None => return Ok(None),
+                    Some(b'#') => break,
+                    Some(byte) => data.push(byte),
+                }
+            }
+
+            let mut checksum = [0u8; 2];
+            for digit in checksum.iter_mut() {
+                match self.read_byte()? {
+                    None => return Ok(None),
+                    Some(byte) => *digit = byte,
+                }
+            }
+
+            let expected = std::str::from_utf8(&checksum).ok().and_then(|c| u8::from_str_radix(c, 16).ok());
+            let valid = expected == Some(get_checksum(&data));
+
+            if self.acknowledge {
+                self.stream.write_all(if valid { b"+" } else { b"-" })?;
+            }
+
+            if valid || !self.acknowledge {
+                return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+            }
+        }
+    }
+
+    fn send_packet(&mut self, data: &str) -> io::Result<()> {
+        let packet = format!("${}#{:0>2x}", data, get_checksum(data.as_bytes()));
+
+        loop {
+            self.stream.write_all(packet.as_bytes())?;
+
+            if !self.acknowledge {
+                return Ok(());
+            }
+
+            match self.read_byte()? {
+                Some(b'-') => continue,
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut byte = [0u8; 1];
+
+        match self.stream.read(&mut byte)? {
+            0 => Ok(None),
+            _ => Ok(Some(byte[0])),
+        }
+    }
+}
+
+// checks, without waiting, whether the client sent an interrupt (Ctrl-C) or closed the connection
+fn is_interrupted(stream: &mut TcpStream) -> bool {
+    let mut byte = [0u8; 1];
+
+    if stream.set_nonblocking(true).is_err() {
+        return false;
+    }
+
+    let result = stream.read(&mut byte);
+    let _ = stream.set_nonblocking(false);
+
+    match result {
+        Ok(0) => true,
+        Ok(_) => byte[0] == INTERRUPT,
+        Err(_) => false,
+    }
+}
+
+fn handle_query(packet: &str) -> String {
+    if packet.starts_with("qSupported") {
+        return format!("PacketSize={:x};qXfer:features:read+;QStartNoAckMode+", MAXIMUM_PACKET_SIZE);
+    }
+
+    if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+        return read_target_description(range).unwrap_or_else(|| String::from(ERROR_REPLY));
+    }
+
+    match packet {
+        "QStartNoAckMode" | "qSymbol::" => String::from(OK_REPLY),
+        "qAttached" => String::from("1"),
+        "qC" => String::from("QC1"),
+        "qfThreadInfo" => String::from("m1"),
+        "qsThreadInfo" => String::from("l"),
+        _ => String::new(),
+    }
+}
+
+// the target description tells the client which registers there are: r0-r15 and CPSR, in that order
+fn read_target_description(range: &str) -> Option<String> {
+    let (offset, length) = range.split_once(',')?;
+    let (offset, length) = (parse_hex(offset)? as usize, parse_hex(length)? as usize);
+
+    let mut description = String::from(concat!(
+        r#"<?xml version="1.0"?><!DOCTYPE target SYSTEM "gdb-target.dtd">"#,
+        r#"<target version="1.0"><architecture>arm</architecture><feature name="org.gnu.gdb.arm.core">"#,
+    ));
+    for register in 0..=12 {
+        description.push_str(&format!(r#"<reg name="r{}" bitsize="32" type="uint32"/>"#, register));
+    }
+    description.push_str(concat!(
+        r#"<reg name="sp" bitsize="32" type="data_ptr"/><reg name="lr" bitsize="32"/><reg name="pc" bitsize="32" type="code_ptr"/>"#,
+        r#"<reg name="cpsr" bitsize="32"/></feature></target>"#,
+    ));
+
+    let start = offset.min(description.len());
+    let end = offset.saturating_add(length).min(description.len());
+    let marker = if end < description.len() { 'm' } else { 'l' };
+
+    Some(format!("{}{}", marker, &description[start..end]))
+}
+
+fn read_registers(context: &CpuContext) -> String {
+    (0..REGISTER_COUNT)
+        .filter_map(|r| read_register(context, r))
+        .collect()
+}
+
+fn write_registers(context: &mut CpuContext, arguments: &str) -> Option<String> {
+    let values = decode_hex(arguments)?;
+
+    for (register, bytes) in values.chunks_exact(REGISTER_SIZE).take(REGISTER_COUNT as usize).enumerate() {
+        set_register(context, register as u32, decode_register_value(context, bytes));
+    }
+
+    Some(String::from(OK_REPLY))
+}
+
+fn read_register(context: &CpuContext, register: u32) -> Option<String> {
+    let value = match register {
+        0..=14 => context.get_register(register as u8),
+        PROGRAM_COUNTER_REGISTER => context.get_program_counter(),
+        STATUS_REGISTER => context.get_program_status_register(),
+        _ => return None,
+    };
+
+    let bytes = match context.get_endianness() {
+        Endianness::Little => value.to_le_bytes(),
+        Endianness::Big => value.to_be_bytes(),
+    };
+
+    Some(encode_hex(&bytes))
+}
+
+fn write_register(context: &mut CpuContext, arguments: &str) -> Option<String> {
+    let (register, value) = arguments.split_once('=')?;
+    let register = parse_hex(register)?;
+    let bytes = decode_hex(value)?;
+
+    if register >= REGISTER_COUNT || bytes.len() != REGISTER_SIZE {
+        return None;
+    }
+
+    set_register(context, register, decode_register_value(context, &bytes));
+    Some(String::from(OK_REPLY))
+}
+
+fn set_register(context: &mut CpuContext, register: u32, value: u32) {
+    match register {
+        STATUS_REGISTER => context.set_program_status_register(value),
+        _ => context.set_register(register as u8, value),
+    }
+}
+
+fn decode_register_value(context: &CpuContext, bytes: &[u8]) -> u32 {
+    let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+
+    match context.get_endianness() {
+        Endianness::Little => u32::from_le_bytes(bytes),
+        Endianness::Big => u32::from_be_bytes(bytes),
+    }
+}
+
+// m<address>,<length>
+fn read_memory(context: &CpuContext, arguments: &str) -> Option<String> {
+    let (address, length) = arguments.split_once(',')?;
+    let (address, length) = (parse_hex(address)?, parse_hex(length)?);
+
+    let mut data = vec![0u8; (length as usize).min(MAXIMUM_PACKET_SIZE / 2)];
+    context.get_memory().read(address, &mut data).ok()?;
+
+    Some(encode_hex(&data))
+}
+
+// M<address>,<length>:<data>; the debugger may write anywhere that is mapped, regardless of permissions
+fn write_memory(context: &mut CpuContext, arguments: &str) -> Option<String> {
+    let (location, data) = arguments.split_once(':')?;
+    let (address, _) = location.split_once(',')?;
+    let (address, data) = (parse_hex(address)?, decode_hex(data)?);
+
+    context.get_memory_mut().load(address, &data).ok()?;
+
+    Some(String::from(OK_REPLY))
+}
+
+// Z<type>,<address>,<kind or length> sets, and z removes, a breakpoint (types 0 and 1) or a write, read or access watchpoint (2, 3 and 4)
+fn set_breakpoint(machine: &mut Machine, arguments: &str, insert: bool) -> Option<String> {
+    let mut fields = arguments.split(',');
+    let kind = parse_hex(fields.next()?)?;
+    let address = parse_hex(fields.next()?)?;
+    let length = parse_hex(fields.next()?.split(';').next()?)?;
+
+    let watch_kind = match kind {
+        0 | 1 => {
+            if insert {
+                machine.add_breakpoint(Breakpoint::create(address));
+            } else {
+                machine.remove_breakpoint(address);
+            }
+
+            return Some(String::from(OK_REPLY));
+        },
+        2 => WatchKind::Write,
+        3 => WatchKind::Read,
+        4 => WatchKind::Access,
+        _ => return Some(String::new()),
+    };
+
+    let end = address.wrapping_add(length.max(1) - 1);
+    if insert {
+        machine.add_watchpoint(Watchpoint::create(address, end, watch_kind));
+    } else {
+        machine.remove_watchpoint(address, end, watch_kind);
+    }
+
+    Some(String::from(OK_REPLY))
+}
+
+fn get_checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b))
+}
+
+fn parse_hex(value: &str) -> Option<u32> {
+    u32::from_str_radix(value, 16).ok()
+}
+
+fn encode_hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:0>2x}", b)).collect()
+}
+
+fn decode_hex(value: &str) -> Option<Vec<u8>> {
+    if !value.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..value.len()).step_by(2)
+        .map(|i| value.get(i..i + 2).and_then(|b| u8::from_str_radix(b, 16).ok()))
+        .collect()
+}
+
+const INTERRUPT: u8 = 0x03;
+const INTERRUPT_PACKET: &str = "\x03";
+const INTERRUPT_CHECK_INTERVAL: u32 = 0x1000;
+const MAXIMUM_PACKET_SIZE: usize = 0x1000;
+
+const OK_REPLY: &str = "OK";
+const ERROR_REPLY: &str = "E01";
+const TRAP_STOP_REPLY: &str = "S05";
+const INTERRUPT_STOP_REPLY: &str = "S02";
+const SIGTRAP: u32 = 5;
+const SIGABRT: u32 = 6;
+
+const REGISTER_COUNT: u32 = 17;
+const REGISTER_SIZE: usize = 4;
+const PROGRAM_COUNTER_REGISTER: u32 = 15;
+const STATUS_REGISTER: u32 = 16;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::CpuConfiguration;
+
+    #[test]
+    fn checksum_is_the_sum_of_the_bytes_modulo_256() {
+        assert_eq!(get_checksum(b""), 0);
+        assert_eq!(get_checksum(b"OK"), 0x9a);
+        assert_eq!(get_checksum(&[0xff, 0x02]), 0x01);
+    }
+
+    #[test]
+    fn target_description_is_read_in_pieces() {
+        let first = read_target_description("0,10").unwrap();
+        assert_eq!(first, "m<?xml version=\"1");
+
+        // reading past the end ends the transfer
+        let whole = read_target_description("0,10000").unwrap();
+        assert!(whole.starts_with('l') && whole.ends_with("</target>"));
+        assert!(whole.contains(r#"<reg name="cpsr" bitsize="32"/>"#));
+
+        let rest = read_target_description(&format!("10,{:x}", whole.len())).unwrap();
+        assert_eq!(format!("{}{}", &first[1..], &rest[1..]), &whole[1..]);
+        assert_eq!(read_target_description("10000,10").unwrap(), "l");
+        assert_eq!(read_target_description("10"), None);
+    }
+
+    #[test]
+    fn breakpoint_packets_set_and_remove_breakpoints_and_watchpoints() {
+        let mut machine = Machine::create(CpuConfiguration::default());
+
+        assert_eq!(set_breakpoint(&mut machine, "0,1004,4", true).unwrap(), OK_REPLY);
+        assert_eq!(machine.get_breakpoints()[0].address, 0x1004);
+        assert_eq!(set_breakpoint(&mut machine, "1,1004,4", false).unwrap(), OK_REPLY);
+        assert!(machine.get_breakpoints().is_empty());
+
+        // a watchpoint covers the given number of bytes; conditions after the length are ignored
+        assert_eq!(set_breakpoint(&mut machine, "3,2000,4;X1,0", true).unwrap(), OK_REPLY);
+        assert_eq!(machine.get_watchpoints()[0], Watchpoint::create(0x2000, 0x2003, WatchKind::Read));
+        assert_eq!(set_breakpoint(&mut machine, "3,2000,4", false).unwrap(), OK_REPLY);
+        assert!(machine.get_watchpoints().is_empty());
+
+        assert_eq!(set_breakpoint(&mut machine, "5,2000,4", true).unwrap(), "");
+        assert_eq!(set_breakpoint(&mut machine, "0,1004", true), None);
+        assert_eq!(set_breakpoint(&mut machine, "0,xyz,4", true), None);
+    }
+
+    #[test]
+    fn registers_are_encoded_in_the_guest_byte_order() {
+        let mut context = CpuContext::create(CpuConfiguration::default());
+        context.set_register(1, 0x12345678);
+
+        assert_eq!(read_register(&context, 1).unwrap(), "78563412");
+        assert_eq!(write_register(&mut context, "2=efbeadde").unwrap(), OK_REPLY);
+        assert_eq!(context.get_register(2), 0xdeadbeef);
+
+        context.set_endianness(Endianness::Big);
+        assert_eq!(read_register(&context, 1).unwrap(), "12345678");
+        assert_eq!(write_register(&mut context, "2=efbeadde").unwrap(), OK_REPLY);
+        assert_eq!(context.get_register(2), 0xefbeadde);
+
+        assert_eq!(read_register(&context, REGISTER_COUNT), None);
+        assert_eq!(write_register(&mut context, "2=efbead"), None);
+        assert_eq!(read_registers(&context).len(), REGISTER_COUNT as usize * REGISTER_SIZE * 2);
+    }
+}
@@ -1,5 +1,7 @@
 use ux::{u12, u5, u4, u24};
 
+use crate::context::Endianness;
+
 pub type Register = u4;
 
 #[derive(Debug)]
@@ -33,6 +35,7 @@ pub enum ShiftType {
     LogicalShiftRight,
     ArithmeticShiftRight,
     RotateRight,
+    RotateRightExtended,    // encoded as ROR #0; always shifts by one bit through the carry flag
 }
 
 #[derive(Debug)]
@@ -87,6 +90,36 @@ pub struct LargeImmediateArguments {
     pub immediate: u16,
 }
 
+#[derive(Debug)]
+pub struct MultiplyArguments {
+    pub destination_register: Register,
+    pub first_operand_register: Register,
+    pub second_operand_register: Register,
+}
+
+#[derive(Debug)]
+pub struct MultiplyAccumulateArguments {
+    pub destination_register: Register,
+    pub first_operand_register: Register,
+    pub second_operand_register: Register,
+    pub accumulate_register: Register,
+}
+
+#[derive(Debug)]
+pub struct LongMultiplyArguments {
+    pub destination_register_low: Register,
+    pub destination_register_high: Register,
+    pub first_operand_register: Register,
+    pub second_operand_register: Register,
+}
+
+#[derive(Debug)]
+pub struct DivideArguments {
+    pub destination_register: Register,
+    pub dividend_register: Register,
+    pub divisor_register: Register,
+}
+
 #[derive(Debug)]
 pub enum UpdateStatusFlags {
     DoNotUpdateStatusFlags,
@@ -169,24 +202,62 @@ pub struct LoadStoreArguments {
     pub offset: LoadStoreOffset,
 }
 
+#[derive(Debug)]
+pub enum BlockTransferAddressingMode {
+    IncrementAfter,         // IA
+    IncrementBefore,        // IB
+    DecrementAfter,         // DA
+    DecrementBefore,        // DB
+}
+
+#[derive(Debug)]
+pub struct BlockTransferArguments {
+    pub addressing_mode: BlockTransferAddressingMode,
+    pub write_back: LoadStoreWriteBackFlag,
+    pub address_register: Register,
+    pub register_list: u16,
+}
+
 #[derive(Debug)]
 pub enum InstructionData {
     Add(ReadWriteDataArguments, UpdateStatusFlags),                 // ADD<c>[S]
     AddWithCarry(ReadWriteDataArguments, UpdateStatusFlags),        // ADC<c>[S]
     And(ReadWriteDataArguments, UpdateStatusFlags),                 // AND<c>[S]
+    BitClear(ReadWriteDataArguments, UpdateStatusFlags),            // BIC<c>[S]
     Branch(i32, BranchLinkFlag),                                    // B[L]<c>
     BranchExchange(Register),                                       // BX<c>
+    Breakpoint(u16),                                                // BKPT
     Compare(DataArguments),                                         // CMP<c>
-    Load(LoadArguments),                                            // LDR[B]<c>, LDRH<c>, LDRSH<c>, LDRD<c>, LDRSB<c>, POP<c>
+    CompareNegative(DataArguments),                                 // CMN<c>
+    ExclusiveOr(ReadWriteDataArguments, UpdateStatusFlags),         // EOR<c>[S]
+    Load(LoadArguments),                                            // LDR[B]<c>, LDRH<c>, LDRSH<c>, LDRD<c>, LDRSB<c>, POP<c> (single register)
+    LoadMultiple(BlockTransferArguments),                           // LDM{IA,IB,DA,DB}<c>, POP<c>
     Move(DataArguments, UpdateStatusFlags),                         // MOV<c>[S]
     MoveHalfWord(LargeImmediateArguments),                          // MOVW<c>
     MoveHalfWordTop(LargeImmediateArguments),                       // MOVT<c>
     MoveNot(DataArguments, UpdateStatusFlags),                      // MVN<c>[S]
     MoveStatusToRegister(Register),                                 // MRS<c>
+    Multiply(MultiplyArguments, UpdateStatusFlags),                 // MUL<c>[S]
+    MultiplyAccumulate(MultiplyAccumulateArguments, UpdateStatusFlags), // MLA<c>[S]
+    MultiplySubtract(MultiplyAccumulateArguments),                  // MLS<c>
     Or(ReadWriteDataArguments, UpdateStatusFlags),                  // ORR<c>[S]
+    ReverseSubtract(ReadWriteDataArguments, UpdateStatusFlags),     // RSB<c>[S]
+    ReverseSubtractWithCarry(ReadWriteDataArguments, UpdateStatusFlags), // RSC<c>[S]
+    SetEndianness(Endianness),                                      // SETEND
+    SignedDivide(DivideArguments),                                  // SDIV<c>
+    SignedMultiplyAccumulateLong(LongMultiplyArguments, UpdateStatusFlags), // SMLAL<c>[S]
+    SignedMultiplyLong(LongMultiplyArguments, UpdateStatusFlags),   // SMULL<c>[S]
     SupervisorCall(u24),                                            // SVC
-    Store(StoreArguments),                                          // STR[B]<c>, STRH<c>, STRSH<c>, STRD<c>, STRSB<c>, PUSH<c>
+    Store(StoreArguments),                                          // STR[B]<c>, STRH<c>, STRSH<c>, STRD<c>, STRSB<c>, PUSH<c> (single register)
+    StoreMultiple(BlockTransferArguments),                          // STM{IA,IB,DA,DB}<c>, PUSH<c>
     Subtract(ReadWriteDataArguments, UpdateStatusFlags),            // SUB<c>[S]
+    SubtractWithCarry(ReadWriteDataArguments, UpdateStatusFlags),   // SBC<c>[S]
+    Test(DataArguments),                                            // TST<c>
+    TestEquivalence(DataArguments),                                 // TEQ<c>
+    UnsignedDivide(DivideArguments),                                // UDIV<c>
+    UnsignedMultiplyAccumulateAccumulateLong(LongMultiplyArguments), // UMAAL<c>
+    UnsignedMultiplyAccumulateLong(LongMultiplyArguments, UpdateStatusFlags), // UMLAL<c>[S]
+    UnsignedMultiplyLong(LongMultiplyArguments, UpdateStatusFlags), // UMULL<c>[S]
 }
 
 pub type Instruction = (Condition, InstructionData);
\ No newline at end of file
@@ -0,0 +1,30 @@
+pub mod assembler;
+pub mod context;
+pub mod debugger;
+pub mod decoding;
+pub mod disassembler;
+pub mod error;
+pub mod exec;
+pub mod file;
+pub mod gdb;
+pub mod instructions;
+pub mod machine;
+pub mod memory;
+pub mod process;
+pub mod sandbox;
+pub mod semihosting;
+pub mod stream;
+pub mod syscall;
+
+pub use assembler::{assemble, encode};
+pub use context::{AlignmentBehaviour, CpuConfiguration, CpuContext, DivideByZeroBehaviour, Endianness, SystemCallAbi, StatusFlags};
+pub use decoding::decode;
+pub use disassembler::disassemble;
+pub use error::EmulatorError;
+pub use exec::execute;
+pub use machine::{Breakpoint, Machine, StopReason, Trigger, TriggerAction, WatchKind, Watchpoint};
+pub use memory::{Device, Memory, MemoryAccess, Permissions};
+pub use sandbox::Sandbox;
+pub use semihosting::SemihostingHandler;
+pub use stream::{FileTable, OutputBuffer, Stream};
+pub use syscall::{CourseSyscallHandler, LinuxSyscallHandler, SyscallHandler};
\ No newline at end of file
@@ -0,0 +1,567 @@
+use std::ops::RangeInclusive;
+
+use crate::{context::*, decoding::decode, error::EmulatorError, exec::execute, file::{self, LoadedImage}, memory::MemoryAccess, process, sandbox::Sandbox, syscall::{self, SyscallHandler}};
+
+const DEFAULT_SANDBOX_ROOT: &str = ".";
+
+pub struct Machine {
+    context: CpuContext,
+    syscall_handler: Box<dyn SyscallHandler>,
+    cycles: u64,
+    breakpoints: Vec<Breakpoint>,
+    watchpoints: Vec<Watchpoint>,
+    stop_address: Option<u32>,  // the breakpoint the last run stopped at, so that the next one can resume past it
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum StopReason {
+    Halted,             // the guest exited, or branched to itself
+    ConditionMet,       // the run_until predicate returned true
+    CycleLimitReached,  // run_for executed the requested number of instructions
+    Breakpoint(u32),    // the next instruction to execute is at a breakpoint
+    Watchpoint { address: u32, access: MemoryAccess },  // the last instruction accessed watched memory at the address
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    Access,     // either
+}
+
+impl WatchKind {
+    fn matches(&self, access: MemoryAccess) -> bool {
+        match self {
+            WatchKind::Read => access == MemoryAccess::Read,
+            WatchKind::Write => access == MemoryAccess::Write,
+            WatchKind::Access => true,
+        }
+    }
+}
+
+// what happens when a breakpoint or watchpoint triggers. Logging and dumping write to standard error and let the guest carry on
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum TriggerAction {
+    #[default]
+    Stop,       // the run stops with StopReason::Breakpoint or StopReason::Watchpoint
+    Log,        // print where it triggered
+    Dump,       // print where it triggered, the registers and flags, and for watchpoints the watched memory
+}
+
+// when a breakpoint or watchpoint triggers, and what it does then. By default it triggers on every hit and stops
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Trigger {
+    pub register_value: Option<(u8, u32)>,  // only count hits while the register holds the value
+    pub hit_count: Option<u64>,             // only trigger from this hit on
+    pub action: TriggerAction,
+    hits: u64,
+}
+
+impl Trigger {
+    // the number of hits so far, not counting those for which the register didn't hold the value
+    pub fn get_hits(&self) -> u64 {
+        self.hits
+    }
+
+    // counts the hit if the register condition holds, and returns whether it triggers
+    fn hit(&mut self, context: &CpuContext) -> bool {
+        if let Some((register, value)) = self.register_value {
+            let actual = match register {
+                PROGRAM_COUNTER => context.get_program_counter(),
+                _ => context.get_register(register),
+            };
+
+            if actual != value {
+                return false;
+            }
+        }
+
+        self.hits += 1;
+
+        self.hits >= self.hit_count.unwrap_or(1)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Breakpoint {
+    pub address: u32,
+    pub trigger: Trigger,
+}
+
+impl Breakpoint {
+    pub fn create(address: u32) -> Breakpoint {
+        Breakpoint { address, trigger: Trigger::default() }
+    }
+}
+
+// watches the bytes from start to end, inclusive
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Watchpoint {
+    pub start: u32,
+    pub end: u32,
+    pub kind: WatchKind,
+    pub trigger: Trigger,
+}
+
+impl Watchpoint {
+    pub fn create(start: u32, end: u32, kind: WatchKind) -> Watchpoint {
+        Watchpoint { start, end, kind, trigger: Trigger::default() }
+    }
+}
+
+impl Machine {
+    // the guest can open files in the working directory and below it; install a handler to choose another sandbox
+    pub fn create(configuration: CpuConfiguration) -> Machine {
+        Machine {
+            context: CpuContext::create(configuration),
+            syscall_handler: syscall::create_syscall_handler(configuration.system_call_abi, Sandbox::create(DEFAULT_SANDBOX_ROOT)),
+            cycles: 0,
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+            stop_address: None,
+        }
+    }
+
+    // replaces the handler for the configured ABI; install a handler before loading a program, so that it gets to prepare for it
+    pub fn set_syscall_handler(&mut self, handler: Box<dyn SyscallHandler>) {
+        self.syscall_handler = handler
+    }
+
+    pub fn load_file(&mut self, path: &str) -> Result<LoadedImage, EmulatorError> {
+        self.load_image(path, &[])
+    }
+
+    fn load_image(&mut self, path: &str, arguments: &[String]) -> Result<LoadedImage, EmulatorError> {
+        let image = file::read_memory_from_file(&mut self.context, path)?;
+        self.syscall_handler.prepare(&image, arguments);
+
+        Ok(image)
+    }
+
+    // loads a program and gives it the stack, arguments, environment and auxiliary vector a Linux process starts with.
+    // Like on Linux, the first argument is conventionally the program's path
+    pub fn load_process(&mut self, path: &str, arguments: &[String], environment: &[String]) -> Result<(), EmulatorError> {
+        let image = self.load_image(path, arguments)?;
+
+        process::set_up_linux_process(&mut self.context, &image, arguments, environment)
+    }
+
+    pub fn get_context(&self) -> &CpuContext {
+        &self.context
+    }
+
+    pub fn get_context_mut(&mut self) -> &mut CpuContext {
+        &mut self.context
+    }
+
+    pub fn get_cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.context.is_halted()
+    }
+
+    // the status the guest passed when it exited, if it exited through a system call rather than by branching to itself
+    pub fn get_exit_status(&self) -> Option<u32> {
+        self.context.get_exit_status()
+    }
+
+    // fetches, decodes and executes a single instruction. When this fails, PC is left pointing at the offending instruction
+    pub fn step(&mut self) -> Result<(), EmulatorError> {
+        let program_counter = self.context.get_program_counter();
+        self.stop_address = None;
+
+        let result = self.context.fetch_instruction(program_counter)
+            .and_then(decode)
+            .and_then(|instr| execute(&mut self.context, instr))
+            .and_then(|_| self.handle_supervisor_call())
+            .and_then(|_| self.handle_breakpoint());
+
+        if result.is_err() {
+            self.context.set_program_counter(program_counter);
+        } else {
+            self.cycles += 1;
+        }
+
+        result
+    }
+
+    fn handle_supervisor_call(&mut self) -> Result<(), EmulatorError> {
+        match self.context.take_pending_supervisor_call() {
+            Some(immediate) => self.syscall_handler.handle(immediate, &mut self.context),
+            None => Ok(()),
+        }
+    }
+
+    fn handle_breakpoint(&mut self) -> Result<(), EmulatorError> {
+        match self.context.take_pending_breakpoint() {
+            Some(immediate) => self.syscall_handler.handle_breakpoint(immediate, &mut self.context),
+            None => Ok(()),
+        }
+    }
+
+    // replaces any breakpoint at the same address
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        self.remove_breakpoint(breakpoint.address);
+        self.breakpoints.push(breakpoint);
+    }
+
+    // returns whether there was a breakpoint at the address
+    pub fn remove_breakpoint(&mut self, address: u32) -> bool {
+        let count = self.breakpoints.len();
+        self.breakpoints.retain(|b| b.address != address);
+
+        self.breakpoints.len() != count
+    }
+
+    pub fn get_breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.push(watchpoint);
+        self.context.set_recording_data_accesses(true);
+    }
+
+    // removes the watchpoints of the same kind on the same range, whatever their triggers; returns whether there were any
+    pub fn remove_watchpoint(&mut self, start: u32, end: u32, kind: WatchKind) -> bool {
+        let count = self.watchpoints.len();
+        self.watchpoints.retain(|w| (w.start, w.end, w.kind) != (start, end, kind));
+        self.context.set_recording_data_accesses(!self.watchpoints.is_empty());
+
+        self.watchpoints.len() != count
+    }
+
+    pub fn get_watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    pub fn run(&mut self) -> Result<StopReason, EmulatorError> {
+        self.run_until(|_| false)
+    }
+
+    // the predicate is evaluated before every instruction, so it can stop on an address before that instruction executes.
+    // Breakpoints are checked at the same point, except when resuming from the breakpoint the last run stopped at, so that
+    // a run can continue from it
+    pub fn run_until<F: FnMut(&CpuContext) -> bool>(&mut self, mut predicate: F) -> Result<StopReason, EmulatorError> {
+        loop {
+            if self.context.is_halted() {
+                return Ok(StopReason::Halted);
+            }
+
+            if predicate(&self.context) {
+                return Ok(StopReason::ConditionMet);
+            }
+
+            if let Some(reason) = self.step_watched()? {
+                return Ok(self.stop(reason));
+            }
+        }
+    }
+
+    pub fn run_for(&mut self, instructions: u64) -> Result<StopReason, EmulatorError> {
+        for _ in 0..instructions {
+            if self.context.is_halted() {
+                return Ok(StopReason::Halted);
+            }
+
+            if let Some(reason) = self.step_watched()? {
+                return Ok(self.stop(reason));
+            }
+        }
+
+        if self.context.is_halted() {
+            Ok(StopReason::Halted)
+        } else {
+            Ok(StopReason::CycleLimitReached)
+        }
+    }
+
+    // only a breakpoint is passed over when resuming; any other stop leaves a breakpoint at PC to trigger on the next run
+    fn stop(&mut self, reason: StopReason) -> StopReason {
+        if let StopReason::Breakpoint(address) = reason {
+            self.stop_address = Some(address);
+        }
+
+        reason
+    }
+
+    // steps unless a breakpoint stops it, and reports whether the instruction triggered a watchpoint that stops
+    fn step_watched(&mut self) -> Result<Option<StopReason>, EmulatorError> {
+        let program_counter = self.context.get_program_counter();
+
+        if self.stop_address != Some(program_counter) {
+            if let Some(breakpoint) = self.breakpoints.iter_mut().find(|b| b.address == program_counter) {
+                if breakpoint.trigger.hit(&self.context) {
+                    let message = format!("Breakpoint at {:0>8X} (hit {})", program_counter, breakpoint.trigger.hits);
+
+                    if !report_trigger(&self.context, &message, breakpoint.trigger.action, None) {
+                        return Ok(Some(StopReason::Breakpoint(program_counter)));
+                    }
+                }
+            }
+        }
+
+        if self.watchpoints.is_empty() {
+            self.step()?;
+            return Ok(None);
+        }
+
+        self.context.take_data_accesses();
+        self.step()?;
+
+        Ok(self.check_watchpoints())
+    }
+
+    // counts a hit for every watchpoint the last instruction accessed, and returns the first one that stops, if any
+    fn check_watchpoints(&mut self) -> Option<StopReason> {
+        let accesses = self.context.take_data_accesses();
+        let mut reason = None;
+
+        for watchpoint in self.watchpoints.iter_mut() {
+            let access = accesses.iter().find(|a| {
+                let last = a.address.saturating_add(a.length.saturating_sub(1));
+                a.address <= watchpoint.end && last >= watchpoint.start && watchpoint.kind.matches(a.access)
+            });
+
+            let access = match access {
+                Some(access) if watchpoint.trigger.hit(&self.context) => access,
+                _ => continue,
+            };
+
+            let address = access.address.max(watchpoint.start);
+            let message = format!("Watchpoint: {} at {:0>8X} (hit {})", access.access, address, watchpoint.trigger.hits);
+
+            if !report_trigger(&self.context, &message, watchpoint.trigger.action, Some(watchpoint.start..=watchpoint.end)) && reason.is_none() {
+                reason = Some(StopReason::Watchpoint { address, access: access.access });
+            }
+        }
+
+        reason
+    }
+}
+
+// carries out logging and dumping actions, and returns whether the action lets the guest carry on
+fn report_trigger(context: &CpuContext, message: &str, action: TriggerAction, memory: Option<RangeInclusive<u32>>) -> bool {
+    match action {
+        TriggerAction::Stop => return false,
+        TriggerAction::Log => eprintln!("{}", message),
+        TriggerAction::Dump => {
+            eprintln!("{}\nRegisters:\n{}\n{}", message, context.debug_get_registers(), context.debug_get_status());
+
+            if let Some(range) = memory {
+                eprintln!("{:0>8X}..{:0>8X} = {}", range.start(), range.end(), context.debug_get_memory_range(&range));
+            }
+        },
+    }
+
+    true
+}
+
+const PROGRAM_COUNTER: u8 = CpuContext::get_program_counter_register();
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+
+    const PROGRAM_ADDRESS: u32 = 0x1000;
+
+    // counts r0 from 1 to 3 in a loop, then branches to itself
+    fn create_machine() -> Machine {
+        create_machine_with_program(&[
+            0xe3a00001,     // mov r0, #1
+            0xe2800001,     // add r0, r0, #1
+            0xe3500003,     // cmp r0, #3
+            0x1afffffc,     // bne 0x1004
+            0xeafffffe,     // b 0x1010
+        ])
+    }
+
+    fn create_machine_with_program(program: &[u32]) -> Machine {
+        let bytes: Vec<u8> = program.iter().flat_map(|w| w.to_le_bytes()).collect();
+
+        let mut machine = Machine::create(CpuConfiguration::default());
+        machine.get_context_mut().load_memory(PROGRAM_ADDRESS, &bytes).unwrap();
+        machine.get_context_mut().set_program_counter(PROGRAM_ADDRESS);
+        machine
+    }
+
+    #[test]
+    fn step_executes_one_instruction() {
+        let mut machine = create_machine();
+
+        machine.step().unwrap();
+        assert_eq!(machine.get_context().get_program_counter(), 0x1004);
+        assert_eq!(machine.get_context().get_register(0), 1);
+        assert_eq!(machine.get_cycles(), 1);
+    }
+
+    #[test]
+    fn failed_step_leaves_pc_at_the_instruction() {
+        let mut machine = create_machine_with_program(&[
+            0xe3a00001,     // mov r0, #1
+            0xe7f000f0,     // udf #0
+        ]);
+
+        machine.step().unwrap();
+        assert!(machine.step().is_err());
+        assert_eq!(machine.get_context().get_program_counter(), 0x1004);
+        assert_eq!(machine.get_cycles(), 1);
+        assert!(machine.run().is_err());
+    }
+
+    #[test]
+    fn run_until_stops_before_the_instruction_the_predicate_matches() {
+        let mut machine = create_machine();
+
+        assert_eq!(machine.run_until(|context| context.get_register(0) == 2).unwrap(), StopReason::ConditionMet);
+        assert_eq!(machine.get_context().get_program_counter(), 0x1008);
+        assert_eq!(machine.get_cycles(), 2);
+
+        assert_eq!(machine.run_until(|context| context.get_register(0) == 2).unwrap(), StopReason::ConditionMet);
+        assert_eq!(machine.get_cycles(), 2);
+    }
+
+    #[test]
+    fn run_for_stops_after_the_instruction_count_or_when_halted() {
+        let mut machine = create_machine();
+
+        assert_eq!(machine.run_for(3).unwrap(), StopReason::CycleLimitReached);
+        assert_eq!(machine.get_context().get_program_counter(), 0x100c);
+        assert_eq!(machine.get_cycles(), 3);
+
+        assert_eq!(machine.run_for(100).unwrap(), StopReason::Halted);
+        assert!(machine.is_halted());
+        assert_eq!(machine.get_context().get_register(0), 3);
+        assert_eq!(machine.run_for(1).unwrap(), StopReason::Halted);
+    }
+
+    #[test]
+    fn breakpoint_at_the_first_instruction_stops_before_it_executes() {
+        let mut machine = create_machine();
+        machine.add_breakpoint(Breakpoint::create(PROGRAM_ADDRESS));
+
+        assert_eq!(machine.run().unwrap(), StopReason::Breakpoint(PROGRAM_ADDRESS));
+        assert_eq!(machine.get_cycles(), 0);
+
+        assert_eq!(machine.run().unwrap(), StopReason::Halted);
+        assert_eq!(machine.get_context().get_register(0), 3);
+    }
+
+    #[test]
+    fn resuming_from_a_breakpoint_stops_there_again_on_the_next_hit() {
+        let mut machine = create_machine();
+        machine.add_breakpoint(Breakpoint::create(0x1004));
+
+        assert_eq!(machine.run().unwrap(), StopReason::Breakpoint(0x1004));
+        assert_eq!(machine.get_context().get_register(0), 1);
+
+        assert_eq!(machine.run_for(10).unwrap(), StopReason::Breakpoint(0x1004));
+        assert_eq!(machine.get_context().get_register(0), 2);
+
+        assert_eq!(machine.run().unwrap(), StopReason::Halted);
+        assert_eq!(machine.get_context().get_register(0), 3);
+        assert_eq!(machine.get_breakpoints()[0].trigger.get_hits(), 2);
+    }
+
+    // records the immediate and r7 of every SVC, and exits with r0 on SVC 0
+    struct RecordingHandler {
+        calls: Rc<RefCell<Vec<(u32, u32)>>>,
+    }
+
+    impl SyscallHandler for RecordingHandler {
+        fn handle(&mut self, immediate: u32, context: &mut CpuContext) -> Result<(), EmulatorError> {
+            self.calls.borrow_mut().push((immediate, context.get_register(7)));
+
+            if immediate == 0 {
+                context.exit(context.get_register(0));
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn supervisor_calls_go_to_the_installed_handler() {
+        let mut machine = create_machine_with_program(&[
+            0xe3a00007,     // mov r0, #7
+            0xef000005,     // svc #5
+            0xe3a07001,     // mov r7, #1
+            0xef000000,     // svc #0
+            0xeafffffe,     // b 0x1010
+        ]);
+        let calls = Rc::new(RefCell::new(Vec::new()));
+
+        machine.set_syscall_handler(Box::new(RecordingHandler { calls: calls.clone() }));
+
+        assert_eq!(machine.run().unwrap(), StopReason::Halted);
+        assert_eq!(machine.get_exit_status(), Some(7));
+        assert_eq!(*calls.borrow(), [(5, 0), (0, 1)]);
+        assert_eq!(machine.get_context().get_program_counter(), 0x1010);
+    }
+
+    #[test]
+    fn breakpoint_where_a_time_slice_ends_stops_the_next_run() {
+        let mut machine = create_machine();
+        machine.add_breakpoint(Breakpoint::create(0x1004));
+
+        // mov r0, #1 runs, leaving PC on the breakpoint without having hit it
+        assert_eq!(machine.run_for(1).unwrap(), StopReason::CycleLimitReached);
+        assert_eq!(machine.run().unwrap(), StopReason::Breakpoint(0x1004));
+        assert_eq!(machine.get_breakpoints()[0].trigger.get_hits(), 1);
+
+        // the same goes for a run_until predicate that stops there
+        let mut machine = create_machine();
+        machine.add_breakpoint(Breakpoint::create(0x1008));
+
+        assert_eq!(machine.run_until(|context| context.get_program_counter() == 0x1008).unwrap(), StopReason::ConditionMet);
+        assert_eq!(machine.run().unwrap(), StopReason::Breakpoint(0x1008));
+    }
+
+    #[test]
+    fn watchpoints_stop_after_the_access() {
+        let mut machine = create_machine_with_program(&[
+            0xe3a00a02,     // mov r0, #0x2000
+            0xe3a01005,     // mov r1, #5
+            0xe5801004,     // str r1, [r0, #4]
+            0xe5902004,     // ldr r2, [r0, #4]
+            0xeafffffe,     // b 0x1010
+        ]);
+        machine.add_watchpoint(Watchpoint::create(0x2006, 0x2006, WatchKind::Write));
+        machine.add_watchpoint(Watchpoint::create(0x2000, 0x2007, WatchKind::Read));
+
+        // the address reported is the first watched byte the access touched
+        assert_eq!(machine.run().unwrap(), StopReason::Watchpoint { address: 0x2006, access: MemoryAccess::Write });
+        assert_eq!(machine.get_context().get_program_counter(), 0x100c);
+
+        assert_eq!(machine.run().unwrap(), StopReason::Watchpoint { address: 0x2004, access: MemoryAccess::Read });
+        assert_eq!(machine.get_context().get_register(2), 5);
+
+        assert!(machine.remove_watchpoint(0x2000, 0x2007, WatchKind::Read));
+        assert!(!machine.remove_watchpoint(0x2000, 0x2007, WatchKind::Read));
+        assert_eq!(machine.run().unwrap(), StopReason::Halted);
+        assert_eq!(machine.get_watchpoints()[0].trigger.get_hits(), 1);
+    }
+
+    #[test]
+    fn triggers_count_hits_and_only_stop_from_the_hit_count() {
+        let mut machine = create_machine();
+        let mut breakpoint = Breakpoint::create(0x1004);
+        breakpoint.trigger.hit_count = Some(2);
+        machine.add_breakpoint(breakpoint);
+
+        assert_eq!(machine.run().unwrap(), StopReason::Breakpoint(0x1004));
+        assert_eq!(machine.get_context().get_register(0), 2);
+
+        // logging lets the guest carry on
+        let mut machine = create_machine();
+        let mut breakpoint = Breakpoint::create(0x1004);
+        breakpoint.trigger.action = TriggerAction::Log;
+        machine.add_breakpoint(breakpoint);
+
+        assert_eq!(machine.run().unwrap(), StopReason::Halted);
+        assert_eq!(machine.get_breakpoints()[0].trigger.get_hits(), 2);
+    }
+}
@@ -1,67 +1,338 @@
-mod decoding;
-mod context;
-mod exec;
-mod file;
-mod instructions;
-mod syscall;
+use std::{env, fs, io::{self, stdin, stdout, ErrorKind, Write}, process};
 
-use std::{env, ops::RangeInclusive};
-
-use exec::execute;
-use decoding::decode;
+use rusty_arm::{assembler, debugger, disassembler, file::{self, CodeRegion, Symbol}, gdb::{self, SessionEnd}, memory::MAXIMUM_MEMORY_SIZE, syscall, AlignmentBehaviour, CpuConfiguration, CpuContext, DivideByZeroBehaviour, EmulatorError, Machine, Sandbox, StopReason, SystemCallAbi, WatchKind};
 use stopwatch::Stopwatch;
 
-use crate::context::CpuContext;
+// when the guest doesn't get to exit by itself, the emulator exits with one of these. Guest crashes follow the shell's
+// convention of 128 + the number of the signal that killed the process
+const EXIT_USAGE_ERROR: i32 = 2;
+const EXIT_LOAD_ERROR: i32 = 126;
+const EXIT_SIGNAL_BASE: i32 = 128;
+const EXIT_HOST_ERROR: i32 = 1;
+const EXIT_KILLED: i32 = EXIT_SIGNAL_BASE + 9;     // SIGKILL
+const EXIT_STOPPED: i32 = EXIT_SIGNAL_BASE + 5;    // SIGTRAP, for breakpoints and watchpoints that stop outside the debugger
 
 fn main() {
-    let mut context = CpuContext::create();
+    // rusty_arm disas <file> prints the program's code, like objdump -d, instead of running it; rusty_arm asm <file.s>
+    // [-o <output>] assembles it into an ELF file that can be run
+    match env::args().nth(1).as_deref() {
+        Some("disas") => {
+            match env::args().nth(2) {
+                Some(file_name) => process::exit(disassemble_file(&file_name)),
+                None => {
+                    eprintln!("File name required.");
+                    process::exit(EXIT_USAGE_ERROR);
+                }
+            }
+        },
+        Some("asm") => {
+            let arguments: Vec<String> = env::args().skip(2).collect();
+
+            match arguments.as_slice() {
+                [source_file_name] => process::exit(assemble_file(source_file_name, &format!("{}.elf", source_file_name))),
+                [source_file_name, option, output_file_name] if option == "-o" => process::exit(assemble_file(source_file_name, output_file_name)),
+                _ => {
+                    eprintln!("Usage: rusty_arm asm <file.s> [-o <output>]");
+                    process::exit(EXIT_USAGE_ERROR);
+                }
+            }
+        },
+        _ => {},
+    }
 
-    let file_name = env::args().skip(1).next();
+    let mut configuration = CpuConfiguration::default();
+    let mut file_name = None;
+    let mut sandbox_root = None;
+    let mut gdb_port = None;
+    let mut debug = false;
+    let mut breakpoint_options = Vec::new();
+    let mut watchpoint_options = Vec::new();
+    let mut arguments = env::args().skip(1);
+
+    // options come before the file name; everything after it is passed on to the guest
+    while let Some(argument) = arguments.next() {
+        match argument.as_str() {
+            "--divide-by-zero=zero" => configuration.divide_by_zero = DivideByZeroBehaviour::ReturnZero,
+            "--divide-by-zero=trap" => configuration.divide_by_zero = DivideByZeroBehaviour::Trap,
+            "--abi=linux" => configuration.system_call_abi = SystemCallAbi::Linux,
+            "--abi=course" => configuration.system_call_abi = SystemCallAbi::Course,
+            "--abi=semihosting" => configuration.system_call_abi = SystemCallAbi::Semihosting,
+            "--alignment=unaligned" => configuration.alignment = AlignmentBehaviour::Unaligned,
+            "--alignment=strict" => configuration.alignment = AlignmentBehaviour::Strict,
+            "--alignment=rotate" => configuration.alignment = AlignmentBehaviour::Rotate,
+            _ if argument.starts_with("--memory-size=") => {
+                match parse_memory_size(&argument["--memory-size=".len()..]) {
+                    Some(v) => configuration.memory_size = v,
+                    None => {
+                        eprintln!("Invalid memory size {}; expected a RAM size between 1 and 4G.", argument);
+                        process::exit(EXIT_USAGE_ERROR);
+                    }
+                }
+            },
+            _ if argument.starts_with("--sandbox=") => sandbox_root = Some(String::from(&argument["--sandbox=".len()..])),
+            "--gdb" => {
+                match arguments.next().and_then(|v| v.parse::<u16>().ok()) {
+                    Some(v) => gdb_port = Some(v),
+                    None => {
+                        eprintln!("--gdb requires a port number.");
+                        process::exit(EXIT_USAGE_ERROR);
+                    }
+                }
+            },
+            "--debug" => debug = true,
+            _ if argument.starts_with("--break=") => breakpoint_options.push(String::from(&argument["--break=".len()..])),
+            _ if argument.starts_with("--watch=") => watchpoint_options.push(String::from(&argument["--watch=".len()..])),
+            _ if argument.starts_with("--") => {
+                eprintln!("Unknown option {}.", argument);
+                process::exit(EXIT_USAGE_ERROR);
+            },
+            _ => {
+                file_name = Some(argument);
+                break;
+            },
+        }
+    }
 
     let file_name = match file_name {
         Some(v) => v,
         None => {
             eprintln!("File name required.");
-            return;
+            process::exit(EXIT_USAGE_ERROR);
         }
     };
 
-    file::read_memory_from_file(&mut context, &file_name);
+    let mut machine = Machine::create(configuration);
 
-    let breakpoints = [];
-    let memory_ranges: &[RangeInclusive<u32>] = &[];
+    if let Some(root) = sandbox_root {
+        machine.set_syscall_handler(syscall::create_syscall_handler(configuration.system_call_abi, Sandbox::create(root)));
+    }
 
-    let mut cycles = 0u32;
-    let mut stopwatch = Stopwatch::start_new();
+    let guest_arguments: Vec<String> = std::iter::once(file_name.clone()).chain(arguments).collect();
+    let guest_environment: Vec<String> = env::vars().map(|(key, value)| format!("{}={}", key, value)).collect();
 
-    while !context.is_halted() {
-        let program_counter = context.get_program_counter();
-        let word = context.read_word(program_counter);
-        let instr = decode(word);
+    // course programs set up their own stack, and take no arguments
+    let result = match configuration.system_call_abi {
+        SystemCallAbi::Course => machine.load_file(&file_name).map(|_| ()),
+        _ => machine.load_process(&file_name, &guest_arguments, &guest_environment),
+    };
+
+    if let Err(e) = result {
+        eprintln!("Error loading {}: {}", file_name, e);
+        process::exit(EXIT_LOAD_ERROR);
+    }
 
-        let instr = match instr {
-            Ok(i) => i,
-            Err(e) => panic!("Error decoding instruction at {:0>8X}: {}", program_counter, e),
-        };
+    // symbols are a convenience; without them, addresses still work
+    let symbols = file::read_symbols(&file_name).unwrap_or_default();
+
+    for option in breakpoint_options.iter() {
+        match debugger::parse_breakpoint(&option.split(',').collect::<Vec<_>>(), &symbols) {
+            Ok(breakpoint) => machine.add_breakpoint(breakpoint),
+            Err(e) => {
+                eprintln!("Invalid breakpoint {}: {}", option, e);
+                process::exit(EXIT_USAGE_ERROR);
+            }
+        }
+    }
 
-        if cfg!(feature = "breakpoints") && breakpoints.contains(&program_counter) {
-            println!("Breakpoint: {:0>8X}\nRegisters:\n{}\n{}", program_counter, context.debug_get_registers(), context.debug_get_status());
+    for option in watchpoint_options.iter() {
+        match debugger::parse_watchpoint(&option.split(',').collect::<Vec<_>>(), WatchKind::Write, &symbols) {
+            Ok(watchpoint) => machine.add_watchpoint(watchpoint),
+            Err(e) => {
+                eprintln!("Invalid watchpoint {}: {}", option, e);
+                process::exit(EXIT_USAGE_ERROR);
+            }
         }
+    }
 
-        execute(&mut context, instr);
+    if let Some(port) = gdb_port {
+        eprintln!("Waiting for GDB to connect on port {}.", port);
 
-        if cfg!(feature = "memory_watch") {
-            for range in memory_ranges.iter() {
-                println!("{:0>8X}..{:0>8X} = {}", range.start(), range.end(), context.debug_get_memory_range(range));
+        // once the debugger detaches or disconnects, the guest carries on by itself
+        match gdb::serve(&mut machine, port) {
+            Ok(SessionEnd::Killed) => process::exit(EXIT_KILLED),
+            Ok(_) => {},
+            Err(e) => {
+                eprintln!("GDB connection failed: {}", e);
+                process::exit(EXIT_HOST_ERROR);
             }
         }
+    }
+
+    if debug {
+        if let Err(e) = debugger::run_debugger(&mut machine, &symbols, stdin().lock(), stdout()) {
+            eprintln!("Debugger failed: {}", e);
+            process::exit(EXIT_HOST_ERROR);
+        }
+
+        // quitting the debugger before the program ends kills it
+        match machine.get_exit_status() {
+            Some(status) => process::exit(status as i32),
+            None if machine.is_halted() => process::exit(0),
+            None => process::exit(EXIT_KILLED),
+        }
+    }
 
-        cycles += 1;
+    let mut stopwatch = Stopwatch::start_new();
+
+    match machine.run() {
+        Ok(StopReason::Halted) => {},
+        Ok(reason) => {
+            let location = match reason {
+                StopReason::Watchpoint { address, access } => format!("watchpoint: {} at {:0>8X}", access, address),
+                _ => format!("breakpoint at {:0>8X}", machine.get_context().get_program_counter()),
+            };
+
+            let context = machine.get_context();
+            eprintln!("Stopped: {}\nRegisters:\n{}\n{}", location, context.debug_get_registers(), context.debug_get_status());
+            process::exit(EXIT_STOPPED);
+        },
+        Err(e) => {
+            report_guest_crash(machine.get_context(), &e);
+            process::exit(get_crash_exit_code(&e));
+        }
     }
 
     stopwatch.stop();
 
+    let cycles = machine.get_cycles();
+
+    // statistics go to standard error, so that standard output only holds what the guest wrote
     const NANOSECONDS_PER_SECOND: u128 = 1_000_000_000;
     let cycles_per_second = (cycles as u128) * NANOSECONDS_PER_SECOND / stopwatch.elapsed().as_nanos();
-    println!("Took {} ns ({} ms) to execute {} cycles. ~ {} cycles per second", stopwatch.elapsed().as_nanos(), stopwatch.elapsed().as_millis(), cycles, cycles_per_second);
+    eprintln!("Took {} ns ({} ms) to execute {} cycles. ~ {} cycles per second", stopwatch.elapsed().as_nanos(), stopwatch.elapsed().as_millis(), cycles, cycles_per_second);
+
+    process::exit(machine.get_exit_status().unwrap_or(0) as i32);
+}
+
+fn disassemble_file(file_name: &str) -> i32 {
+    let regions = match file::read_code_regions(file_name) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("Error loading {}: {}", file_name, e);
+            return EXIT_LOAD_ERROR;
+        }
+    };
+    let symbols = file::read_symbols(file_name).unwrap_or_default();
+
+    // a closed pipe, as with disas program.elf | head, just ends the listing
+    match print_code_regions(file_name, &regions, &symbols) {
+        Ok(()) => 0,
+        Err(e) if e.kind() == ErrorKind::BrokenPipe => 0,
+        Err(e) => {
+            eprintln!("Error writing the disassembly: {}", e);
+            EXIT_HOST_ERROR
+        }
+    }
+}
+
+fn assemble_file(source_file_name: &str, output_file_name: &str) -> i32 {
+    let program = match fs::read_to_string(source_file_name).map_err(EmulatorError::from).and_then(|source| assembler::assemble(&source, assembler::DEFAULT_ADDRESS)) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("Error assembling {}: {}", source_file_name, e);
+            return EXIT_LOAD_ERROR;
+        }
+    };
+
+    let executable = file::create_executable(program.address, &program.data, program.entry_point, &program.symbols);
+
+    match fs::write(output_file_name, executable) {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("Error writing {}: {}", output_file_name, e);
+            EXIT_HOST_ERROR
+        }
+    }
+}
+
+fn print_code_regions(file_name: &str, regions: &[CodeRegion], symbols: &[Symbol]) -> io::Result<()> {
+    let mut output = stdout().lock();
+    writeln!(output, "{}:", file_name)?;
+
+    for region in regions {
+        match region.name {
+            Some(ref name) => writeln!(output, "\nDisassembly of section {}:", name)?,
+            None => writeln!(output, "\nDisassembly of {:0>8x}..{:0>8x}:", region.address, region.address.wrapping_add(region.data.len() as u32))?,
+        }
+
+        for line in disassembler::disassemble_region(&region.data, region.address, symbols) {
+            writeln!(output, "{}", line)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn report_guest_crash(context: &CpuContext, error: &EmulatorError) {
+    eprintln!("Guest crashed at {:0>8X}: {}\nRegisters:\n{}\n{}", context.get_program_counter(), error, context.debug_get_registers(), context.debug_get_status());
+}
+
+fn get_crash_exit_code(error: &EmulatorError) -> i32 {
+    match error.get_signal_number() {
+        Some(signal) => EXIT_SIGNAL_BASE + signal as i32,
+        None => EXIT_HOST_ERROR,
+    }
+}
+
+// accepts a decimal or 0x-prefixed hexadecimal number, optionally followed by K, M or G
+fn parse_memory_size(value: &str) -> Option<u64> {
+    let (number, multiplier) = match value.chars().last()?.to_ascii_uppercase() {
+        'K' => (&value[..value.len() - 1], 1u64 << 10),
+        'M' => (&value[..value.len() - 1], 1u64 << 20),
+        'G' => (&value[..value.len() - 1], 1u64 << 30),
+        _ => (value, 1u64),
+    };
+
+    let number = match number.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok()?,
+        None => number.parse::<u64>().ok()?,
+    };
+
+    let size = number.checked_mul(multiplier)?;
+
+    if size == 0 || size > MAXIMUM_MEMORY_SIZE {
+        return None;
+    }
+
+    Some(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use rusty_arm::memory::MemoryAccess;
+
+    use super::*;
+
+    #[test]
+    fn guest_crashes_exit_like_a_signal_killed_them() {
+        let unknown = EmulatorError::UnknownInstruction { instruction: 0xffffffff, message: String::from("Unknown instruction") };
+
+        assert_eq!(get_crash_exit_code(&unknown), 132);
+        assert_eq!(get_crash_exit_code(&EmulatorError::UndefinedInstruction(0x1000)), 132);
+        assert_eq!(get_crash_exit_code(&EmulatorError::Breakpoint(0)), 133);
+        assert_eq!(get_crash_exit_code(&EmulatorError::AlignmentFault(0x1001)), 135);
+        assert_eq!(get_crash_exit_code(&EmulatorError::MemoryFault(0x10000)), 139);
+        assert_eq!(get_crash_exit_code(&EmulatorError::PermissionFault { address: 0x8000, access: MemoryAccess::Write }), 139);
+        assert_eq!(get_crash_exit_code(&EmulatorError::UnsupportedSystemCall(0x1234)), 159);
+    }
+
+    #[test]
+    fn host_errors_exit_with_a_plain_failure() {
+        let error = EmulatorError::Io(io::Error::other("disk on fire"));
+
+        assert_eq!(get_crash_exit_code(&error), EXIT_HOST_ERROR);
+        assert_eq!(get_crash_exit_code(&EmulatorError::InvalidExecutable(String::from("truncated"))), EXIT_HOST_ERROR);
+    }
+
+    #[test]
+    fn memory_sizes_take_a_unit() {
+        assert_eq!(parse_memory_size("4096"), Some(0x1000));
+        assert_eq!(parse_memory_size("0x100000"), Some(0x100000));
+        assert_eq!(parse_memory_size("64K"), Some(0x10000));
+        assert_eq!(parse_memory_size("4g"), Some(MAXIMUM_MEMORY_SIZE));
+        assert_eq!(parse_memory_size("0"), None);
+        assert_eq!(parse_memory_size("5G"), None);
+        assert_eq!(parse_memory_size("M"), None);
+        assert_eq!(parse_memory_size(""), None);
+    }
 }
@@ -0,0 +1,394 @@
+use std::{cell::RefCell, fmt};
+
+use crate::error::EmulatorError;
+
+pub const PAGE_SIZE: usize = 0x1000;
+pub const MAXIMUM_MEMORY_SIZE: u64 = 0x1_0000_0000;
+
+const PAGE_TABLE_SIZE: usize = 0x400;
+const PAGE_TABLE_SHIFT: u32 = 22;
+const PAGE_SHIFT: u32 = 12;
+
+const DEFAULT_REGION_NAME: &str = "RAM";
+
+type Page = [u8; PAGE_SIZE];
+type PageTable = Box<[Option<Box<Page>>]>;
+
+// a memory-mapped peripheral. Offsets are relative to the start of the region the device is mapped at,
+// and an access never straddles the end of the region
+pub trait Device {
+    fn read(&mut self, offset: u32, buffer: &mut [u8]) -> Result<(), EmulatorError>;
+    fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), EmulatorError>;
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Permissions {
+    pub read: bool,
+    pub write: bool,
+    pub execute: bool,
+}
+
+impl Permissions {
+    pub const ROM: Permissions = Permissions { read: true, write: false, execute: true };
+    pub const RAM: Permissions = Permissions { read: true, write: true, execute: true };
+    pub const DATA: Permissions = Permissions { read: true, write: true, execute: false };
+    pub const MMIO: Permissions = Permissions { read: true, write: true, execute: false };
+
+    fn allows(&self, access: MemoryAccess) -> bool {
+        match access {
+            MemoryAccess::Read => self.read,
+            MemoryAccess::Write => self.write,
+            MemoryAccess::Execute => self.execute,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MemoryAccess {
+    Read,
+    Write,
+    Execute,
+}
+
+impl fmt::Display for MemoryAccess {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MemoryAccess::Read => write!(f, "read"),
+            MemoryAccess::Write => write!(f, "write"),
+            MemoryAccess::Execute => write!(f, "execute"),
+        }
+    }
+}
+
+struct Region {
+    name: String,
+    start: u32,
+    end: u64,           // exclusive
+    permissions: Permissions,
+    device: Option<RefCell<Box<dyn Device>>>,   // None for ROM and RAM, which live in the page store
+}
+
+// the guest address space: a list of named regions, each either backed by the page store or routed to a device.
+// Regions mapped later take precedence over earlier ones they overlap, and accesses outside every region fault
+pub struct Memory {
+    regions: Vec<Region>,
+    pages: PageStore,
+}
+
+impl Memory {
+    // creates an address space with a single readable, writable and executable region of the given size, starting at zero.
+    // Sizes beyond the 4 GiB address space are capped, and with a size of zero nothing is mapped
+    pub fn create(size: u64) -> Memory {
+        let mut memory = Memory {
+            regions: Vec::new(),
+            pages: PageStore::create(),
+        };
+
+        memory.map_memory(DEFAULT_REGION_NAME, 0, size.min(MAXIMUM_MEMORY_SIZE), Permissions::RAM).ok();
+        memory
+    }
+
+    // fails if the region is empty or extends past the end of the address space
+    pub fn map_memory(&mut self, name: &str, start: u32, size: u64, permissions: Permissions) -> Result<(), EmulatorError> {
+        self.add_region(name, start, size, permissions, None)
+    }
+
+    pub fn map_device(&mut self, name: &str, start: u32, size: u64, permissions: Permissions, device: Box<dyn Device>) -> Result<(), EmulatorError> {
+        self.add_region(name, start, size, permissions, Some(RefCell::new(device)))
+    }
+
+    // removes every region with the given name; returns whether there was one
+    pub fn unmap(&mut self, name: &str) -> bool {
+        let count = self.regions.len();
+        self.regions.retain(|r| r.name != name);
+
+        self.regions.len() != count
+    }
+
+    pub fn get_region_name(&self, address: u32) -> Option<&str> {
+        self.find_region(address).map(|r| self.regions[r].name.as_str())
+    }
+
+    pub fn read(&self, address: u32, buffer: &mut [u8]) -> Result<(), EmulatorError> {
+        self.read_checked(address, buffer, MemoryAccess::Read)
+    }
+
+    pub fn fetch(&self, address: u32, buffer: &mut [u8]) -> Result<(), EmulatorError> {
+        self.read_checked(address, buffer, MemoryAccess::Execute)
+    }
+
+    pub fn write(&mut self, address: u32, data: &[u8]) -> Result<(), EmulatorError> {
+        self.write_checked(address, data, Some(MemoryAccess::Write))
+    }
+
+    // writes regardless of permissions, so that loaders can fill ROM; the addresses must still be mapped
+    pub fn load(&mut self, address: u32, data: &[u8]) -> Result<(), EmulatorError> {
+        self.write_checked(address, data, None)
+    }
+
+    // zeroes memory the way a fresh mapping would be: pages that are covered entirely are released instead of written to,
+    // so clearing a large range allocates nothing. Like load, it ignores permissions, but the addresses must be mapped
+    pub fn clear(&mut self, address: u32, length: usize) -> Result<(), EmulatorError> {
+        let mut done = 0;
+        while done < length {
+            let current_address = address.wrapping_add(done as u32);
+            let (index, chunk_length) = self.get_chunk(current_address, length - done, None)?;
+            let region = &self.regions[index];
+
+            match &region.device {
+                Some(device) => device.borrow_mut().write(current_address - region.start, &vec![0u8; chunk_length])?,
+                None => self.pages.clear(current_address, chunk_length),
+            }
+
+            done += chunk_length;
+        }
+
+        Ok(())
+    }
+
+    fn read_checked(&self, address: u32, buffer: &mut [u8], access: MemoryAccess) -> Result<(), EmulatorError> {
+        let mut done = 0;
+        while done < buffer.len() {
+            let current_address = address.wrapping_add(done as u32);
+            let (index, length) = self.get_chunk(current_address, buffer.len() - done, Some(access))?;
+            let region = &self.regions[index];
+            let destination = &mut buffer[done..done + length];
+
+            match &region.device {
+                Some(device) => device.borrow_mut().read(current_address - region.start, destination)?,
+                None => self.pages.read(current_address, destination),
+            }
+
+            done += length;
+        }
+
+        Ok(())
+    }
+
+    fn write_checked(&mut self, address: u32, data: &[u8], access: Option<MemoryAccess>) -> Result<(), EmulatorError> {
+        let mut done = 0;
+        while done < data.len() {
+            let current_address = address.wrapping_add(done as u32);
+            let (index, length) = self.get_chunk(current_address, data.len() - done, access)?;
+            let region = &self.regions[index];
+            let source = &data[done..done + length];
+
+            match &region.device {
+                Some(device) => device.borrow_mut().write(current_address - region.start, source)?,
+                None => self.pages.write(current_address, source),
+            }
+
+            done += length;
+        }
+
+        Ok(())
+    }
+
+    // finds the region containing the address, checks the access, and returns how much of the remaining length falls within the region
+    fn get_chunk(&self, address: u32, remaining: usize, access: Option<MemoryAccess>) -> Result<(usize, usize), EmulatorError> {
+        let index = self.find_region(address).ok_or(EmulatorError::MemoryFault(address))?;
+        let region = &self.regions[index];
+
+        if let Some(access) = access {
+            if !region.permissions.allows(access) {
+                return Err(EmulatorError::PermissionFault { address, access });
+            }
+        }
+
+        // the chunk also ends where a region taking precedence over this one begins
+        let end = self.regions[index + 1..].iter()
+            .map(|r| r.start as u64)
+            .filter(|&start| start > address as u64)
+            .fold(region.end, u64::min);
+        let length = (end - address as u64).min(remaining as u64) as usize;
+
+        Ok((index, length))
+    }
+
+    fn find_region(&self, address: u32) -> Option<usize> {
+        let address = address as u64;
+
+        self.regions.iter()
+            .rposition(|r| (r.start as u64) <= address && address < r.end)
+    }
+
+    fn add_region(&mut self, name: &str, start: u32, size: u64, permissions: Permissions, device: Option<RefCell<Box<dyn Device>>>) -> Result<(), EmulatorError> {
+        let end = (start as u64).saturating_add(size);
+        if size == 0 || end > MAXIMUM_MEMORY_SIZE {
+            return Err(EmulatorError::InvalidMemoryRegion { start, size });
+        }
+
+        self.regions.push(Region { name: String::from(name), start, end, permissions, device });
+        Ok(())
+    }
+}
+
+// a sparse, two-level paged backing store: pages are only allocated once they are written to, and untouched memory reads as zero
+struct PageStore {
+    page_directory: Box<[Option<PageTable>]>,
+}
+
+impl PageStore {
+    fn create() -> PageStore {
+        PageStore {
+            page_directory: vec![None; PAGE_TABLE_SIZE].into_boxed_slice(),
+        }
+    }
+
+    fn read(&self, address: u32, buffer: &mut [u8]) {
+        let mut done = 0;
+        while done < buffer.len() {
+            let current_address = address.wrapping_add(done as u32);
+            let offset = current_address as usize % PAGE_SIZE;
+            let length = (PAGE_SIZE - offset).min(buffer.len() - done);
+            let destination = &mut buffer[done..done + length];
+
+            match self.get_page(current_address) {
+                Some(page) => destination.copy_from_slice(&page[offset..offset + length]),
+                None => destination.fill(0),
+            }
+
+            done += length;
+        }
+    }
+
+    fn write(&mut self, address: u32, data: &[u8]) {
+        let mut done = 0;
+        while done < data.len() {
+            let current_address = address.wrapping_add(done as u32);
+            let offset = current_address as usize % PAGE_SIZE;
+            let length = (PAGE_SIZE - offset).min(data.len() - done);
+
+            let page = self.get_page_mut(current_address);
+            page[offset..offset + length].copy_from_slice(&data[done..done + length]);
+
+            done += length;
+        }
+    }
+
+    fn clear(&mut self, address: u32, length: usize) {
+        let mut done = 0;
+        while done < length {
+            let current_address = address.wrapping_add(done as u32);
+            let offset = current_address as usize % PAGE_SIZE;
+            let page_length = (PAGE_SIZE - offset).min(length - done);
+            let (table_index, page_index) = get_page_indices(current_address);
+
+            if let Some(table) = self.page_directory[table_index].as_mut() {
+                if page_length == PAGE_SIZE {
+                    table[page_index] = None;
+                } else if let Some(page) = table[page_index].as_mut() {
+                    page[offset..offset + page_length].fill(0);
+                }
+            }
+
+            done += page_length;
+        }
+    }
+
+    fn get_page(&self, address: u32) -> Option<&Page> {
+        let (table_index, page_index) = get_page_indices(address);
+
+        self.page_directory[table_index].as_ref()
+            .and_then(|table| table[page_index].as_deref())
+    }
+
+    fn get_page_mut(&mut self, address: u32) -> &mut Page {
+        let (table_index, page_index) = get_page_indices(address);
+
+        let table = self.page_directory[table_index].get_or_insert_with(|| vec![None; PAGE_TABLE_SIZE].into_boxed_slice());
+        table[page_index].get_or_insert_with(|| Box::new([0u8; PAGE_SIZE]))
+    }
+}
+
+fn get_page_indices(address: u32) -> (usize, usize) {
+    let table_index = (address >> PAGE_TABLE_SHIFT) as usize;
+    let page_index = ((address >> PAGE_SHIFT) as usize) % PAGE_TABLE_SIZE;
+
+    (table_index, page_index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn map_memory_rejects_regions_outside_the_address_space() {
+        let mut memory = Memory::create(0x10000);
+
+        assert!(matches!(memory.map_memory("empty", 0x20000, 0, Permissions::RAM), Err(EmulatorError::InvalidMemoryRegion { start: 0x20000, size: 0 })));
+        assert!(matches!(memory.map_memory("too big", 0xfffff000, 0x2000, Permissions::RAM), Err(EmulatorError::InvalidMemoryRegion { .. })));
+        assert!(matches!(memory.map_memory("huge", 0x00001000, u64::MAX, Permissions::RAM), Err(EmulatorError::InvalidMemoryRegion { .. })));
+        assert_eq!(memory.get_region_name(0xfffff000), None);
+
+        memory.map_memory("top", 0xfffff000, 0x1000, Permissions::RAM).unwrap();
+        assert_eq!(memory.get_region_name(0xffffffff), Some("top"));
+    }
+
+    fn get_page_count(memory: &Memory) -> usize {
+        memory.pages.page_directory.iter()
+            .flatten()
+            .map(|table| table.iter().filter(|page| page.is_some()).count())
+            .sum()
+    }
+
+    #[test]
+    fn clear_releases_whole_pages_and_zeroes_partial_ones() {
+        let mut memory = Memory::create(0x10000);
+        memory.write(0x1000, &[0xAA; 0x3000]).unwrap();
+        assert_eq!(get_page_count(&memory), 3);
+
+        memory.clear(0x1800, 0x2000).unwrap();
+        assert_eq!(get_page_count(&memory), 2);
+
+        let mut buffer = vec![0u8; 0x3000];
+        memory.read(0x1000, &mut buffer).unwrap();
+        assert!(buffer[..0x800].iter().all(|&b| b == 0xAA));
+        assert!(buffer[0x800..0x2800].iter().all(|&b| b == 0));
+        assert!(buffer[0x2800..].iter().all(|&b| b == 0xAA));
+
+        // clearing memory nothing was written to allocates nothing, but it must be mapped
+        memory.clear(0x8000, 0x8000).unwrap();
+        assert_eq!(get_page_count(&memory), 2);
+        assert!(matches!(memory.clear(0xF000, 0x2000), Err(EmulatorError::MemoryFault(0x10000))));
+    }
+    #[test]
+    fn pages_are_only_allocated_when_written() {
+        let mut memory = Memory::create(MAXIMUM_MEMORY_SIZE);
+        let mut buffer = [0xFFu8; 8];
+
+        memory.read(0x12345678, &mut buffer).unwrap();
+        assert_eq!(buffer, [0; 8]);
+        assert_eq!(get_page_count(&memory), 0);
+
+        // a write that straddles a page boundary allocates both pages
+        memory.write(0x7FFFFFFC, &[1, 2, 3, 4, 5, 6, 7, 8]).unwrap();
+        assert_eq!(get_page_count(&memory), 2);
+
+        memory.read(0x7FFFFFFC, &mut buffer).unwrap();
+        assert_eq!(buffer, [1, 2, 3, 4, 5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn later_regions_take_precedence() {
+        let mut memory = Memory::create(0x10000);
+        memory.map_memory("rom", 0x4000, 0x1000, Permissions::ROM).unwrap();
+
+        assert_eq!(memory.get_region_name(0x3FFF), Some("RAM"));
+        assert_eq!(memory.get_region_name(0x4000), Some("rom"));
+        assert_eq!(memory.get_region_name(0x5000), Some("RAM"));
+
+        // a write spanning both regions is checked against each of them, but loading ignores permissions
+        assert!(matches!(memory.write(0x3FFC, &[0; 8]), Err(EmulatorError::PermissionFault { address: 0x4000, access: MemoryAccess::Write })));
+        memory.load(0x3FFC, &[1, 2, 3, 4, 5, 6, 7, 8]).unwrap();
+
+        let mut buffer = [0u8; 4];
+        memory.fetch(0x4000, &mut buffer).unwrap();
+        assert_eq!(buffer, [5, 6, 7, 8]);
+
+        assert!(memory.unmap("rom"));
+        assert!(!memory.unmap("rom"));
+        assert_eq!(memory.get_region_name(0x4000), Some("RAM"));
+        assert!(matches!(memory.read(0x10000, &mut buffer), Err(EmulatorError::MemoryFault(0x10000))));
+    }
+}
@@ -0,0 +1,145 @@
+use std::{collections::hash_map::RandomState, hash::{BuildHasher, Hasher}, mem::size_of};
+
+use crate::{context::CpuContext, error::EmulatorError, file::LoadedImage, memory::{Permissions, PAGE_SIZE}};
+
+// the stack sits just below where the kernel would be in a 3G/1G split
+const STACK_TOP: u32 = 0xC0000000;
+const STACK_SIZE: u32 = 0x00800000;
+const STACK_REGION_NAME: &str = "stack";
+const STACK_ALIGNMENT: u32 = 16;
+const RANDOM_BYTES_SIZE: usize = 16;
+
+const STACK_POINTER_REGISTER: u8 = 13;
+
+const AT_NULL: u32 = 0;
+const AT_PHDR: u32 = 3;
+const AT_PHENT: u32 = 4;
+const AT_PHNUM: u32 = 5;
+const AT_PAGESZ: u32 = 6;
+const AT_ENTRY: u32 = 9;
+const AT_HWCAP: u32 = 16;
+const AT_RANDOM: u32 = 25;
+
+const HWCAP_HALF: u32 = 1 << 1;
+const HWCAP_FAST_MULT: u32 = 1 << 4;
+const HWCAP_IDIVA: u32 = 1 << 17;
+
+// sets up the initial stack of a Linux user-mode process the way the kernel does: from SP upwards there's argc, the argv pointers,
+// a null pointer, the envp pointers, another null pointer and the auxiliary vector. The strings they point to sit above these
+pub fn set_up_linux_process(context: &mut CpuContext, image: &LoadedImage, arguments: &[String], environment: &[String]) -> Result<(), EmulatorError> {
+    context.get_memory_mut().map_memory(STACK_REGION_NAME, STACK_TOP - STACK_SIZE, STACK_SIZE as u64, Permissions::DATA)?;
+
+    let mut top = STACK_TOP;
+
+    top -= RANDOM_BYTES_SIZE as u32;
+    let random_address = top;
+    context.load_memory(random_address, &get_random_bytes())?;
+
+    let environment_addresses = push_strings(context, &mut top, environment)?;
+    let argument_addresses = push_strings(context, &mut top, arguments)?;
+
+    let auxiliary_vector = [
+        (AT_PHDR, image.program_header_address),
+        (AT_PHENT, image.program_header_entry_size as u32),
+        (AT_PHNUM, image.program_header_count as u32),
+        (AT_PAGESZ, PAGE_SIZE as u32),
+        (AT_ENTRY, image.entry_point),
+        (AT_HWCAP, HWCAP_HALF | HWCAP_FAST_MULT | HWCAP_IDIVA),
+        (AT_RANDOM, random_address),
+        (AT_NULL, 0),
+    ];
+
+    let mut words = vec![arguments.len() as u32];
+    words.extend(&argument_addresses);
+    words.push(0);
+    words.extend(&environment_addresses);
+    words.push(0);
+    words.extend(auxiliary_vector.iter().flat_map(|&(key, value)| [key, value]));
+
+    let stack_pointer = (top - (words.len() * size_of::<u32>()) as u32) & !(STACK_ALIGNMENT - 1);
+
+    for (index, &word) in words.iter().enumerate() {
+        context.write_word(stack_pointer + (index * size_of::<u32>()) as u32, word)?;
+    }
+
+    context.set_register(STACK_POINTER_REGISTER, stack_pointer);
+
+    Ok(())
+}
+
+// copies NUL-terminated strings below top, and returns their addresses in the original order
+fn push_strings(context: &mut CpuContext, top: &mut u32, strings: &[String]) -> Result<Vec<u32>, EmulatorError> {
+    let mut addresses = vec![0u32; strings.len()];
+
+    for (index, string) in strings.iter().enumerate().rev() {
+        let mut bytes = string.as_bytes().to_vec();
+        bytes.push(0);
+
+        *top -= bytes.len() as u32;
+        context.load_memory(*top, &bytes)?;
+        addresses[index] = *top;
+    }
+
+    Ok(addresses)
+}
+
+// the lowest and highest addresses of the stack, exclusive of the latter
+pub fn get_stack_range() -> (u32, u32) {
+    (STACK_TOP - STACK_SIZE, STACK_TOP)
+}
+
+// AT_RANDOM points at 16 unpredictable bytes, which libc uses to seed its stack protector
+fn get_random_bytes() -> [u8; RANDOM_BYTES_SIZE] {
+    let state = RandomState::new();
+    let mut bytes = [0u8; RANDOM_BYTES_SIZE];
+
+    for (index, chunk) in bytes.chunks_mut(size_of::<u64>()).enumerate() {
+        let mut hasher = state.build_hasher();
+        hasher.write_usize(index);
+        chunk.copy_from_slice(&hasher.finish().to_le_bytes());
+    }
+
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::CpuConfiguration;
+
+    #[test]
+    fn stack_holds_arguments_environment_and_auxiliary_vector() {
+        let mut context = CpuContext::create(CpuConfiguration::default());
+        let image = LoadedImage { entry_point: 0x8000, program_header_address: 0x8034, program_header_entry_size: 32, program_header_count: 2, end_address: 0x9000 };
+        let arguments = [String::from("program"), String::from("first")];
+        let environment = [String::from("HOME=/")];
+
+        set_up_linux_process(&mut context, &image, &arguments, &environment).unwrap();
+
+        let stack_pointer = context.get_register(STACK_POINTER_REGISTER);
+        let word = |index: u32| context.read_word(stack_pointer + index * size_of::<u32>() as u32).unwrap();
+
+        assert_eq!(stack_pointer % STACK_ALIGNMENT, 0);
+        assert!(stack_pointer > STACK_TOP - STACK_SIZE && stack_pointer < STACK_TOP);
+
+        // argc, argv, a null pointer, envp and another null pointer
+        assert_eq!(word(0), 2);
+        assert_eq!(context.read_memory(word(1), 8).unwrap(), b"program\0");
+        assert_eq!(context.read_memory(word(2), 6).unwrap(), b"first\0");
+        assert_eq!(word(3), 0);
+        assert_eq!(context.read_memory(word(4), 7).unwrap(), b"HOME=/\0");
+        assert_eq!(word(5), 0);
+
+        // the auxiliary vector, as key and value pairs up to AT_NULL
+        let auxiliary_vector: Vec<(u32, u32)> = (0..8).map(|index| (word(6 + index * 2), word(7 + index * 2))).collect();
+
+        assert!(auxiliary_vector.contains(&(AT_ENTRY, 0x8000)));
+        assert!(auxiliary_vector.contains(&(AT_PHDR, 0x8034)));
+        assert!(auxiliary_vector.contains(&(AT_PHNUM, 2)));
+        assert!(auxiliary_vector.contains(&(AT_PAGESZ, PAGE_SIZE as u32)));
+        assert_eq!(auxiliary_vector.last(), Some(&(AT_NULL, 0)));
+
+        let (_, random_address) = auxiliary_vector.iter().find(|&&(key, _)| key == AT_RANDOM).unwrap();
+        assert!(*random_address > stack_pointer && *random_address + RANDOM_BYTES_SIZE as u32 <= STACK_TOP);
+    }
+}
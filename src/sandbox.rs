@@ -0,0 +1,119 @@
+use std::{fs, io::ErrorKind, path::{Component, Path, PathBuf}};
+
+// the part of the host file system the guest can see. Guest paths are resolved below the root: absolute paths start at the root,
+// and neither `..` nor symbolic links lead out of it
+pub struct Sandbox {
+    root: PathBuf,
+}
+
+impl Sandbox {
+    pub fn create<P: Into<PathBuf>>(root: P) -> Sandbox {
+        Sandbox { root: root.into() }
+    }
+
+    pub fn get_root(&self) -> &Path {
+        &self.root
+    }
+
+    // returns None for paths that would end up outside the root, including through symbolic links. The last component may be
+    // missing, so that files can be created, but if it's a symbolic link, it must point to something that exists inside the root.
+    // The link itself is returned, so that removing or renaming it affects only the link
+    pub fn resolve(&self, guest_path: &str) -> Option<PathBuf> {
+        let mut relative = PathBuf::new();
+
+        for component in Path::new(guest_path).components() {
+            match component {
+                Component::Normal(name) => relative.push(name),
+                Component::ParentDir => {
+                    if !relative.pop() {
+                        return None;
+                    }
+                },
+                Component::CurDir | Component::RootDir | Component::Prefix(_) => {},
+            }
+        }
+
+        let root = self.root.canonicalize().ok()?;
+
+        let name = match relative.file_name() {
+            Some(name) => name.to_owned(),
+            None => return Some(root),
+        };
+
+        let directory = match self.root.join(relative.parent()?).canonicalize() {
+            Ok(directory) => directory,
+            // without the directory, the path can't exist or be created either, so the host reports it missing
+            Err(e) if e.kind() == ErrorKind::NotFound => return Some(root.join(relative)),
+            Err(_) => return None,
+        };
+
+        if !directory.starts_with(&root) {
+            return None;
+        }
+
+        let path = directory.join(name);
+        let is_link = fs::symlink_metadata(&path).is_ok_and(|m| m.file_type().is_symlink());
+
+        if is_link && !path.canonicalize().is_ok_and(|target| target.starts_with(&root)) {
+            return None;
+        }
+
+        Some(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a fresh directory with a sandbox directory inside it, and a file next to the sandbox
+    fn create_test_directory(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("rusty_arm_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+
+        fs::create_dir_all(directory.join("root/sub")).unwrap();
+        fs::write(directory.join("root/inside.txt"), b"inside").unwrap();
+        fs::write(directory.join("outside.txt"), b"outside").unwrap();
+
+        directory
+    }
+
+    #[test]
+    fn resolves_paths_below_the_root() {
+        let directory = create_test_directory("paths");
+        let sandbox = Sandbox::create(directory.join("root"));
+        let root = directory.join("root").canonicalize().unwrap();
+
+        assert_eq!(sandbox.resolve("/inside.txt"), Some(root.join("inside.txt")));
+        assert_eq!(sandbox.resolve("sub/../inside.txt"), Some(root.join("inside.txt")));
+        assert_eq!(sandbox.resolve("sub/new.txt"), Some(root.join("sub/new.txt")));
+        assert_eq!(sandbox.resolve("/"), Some(root.clone()));
+        assert_eq!(sandbox.resolve("../outside.txt"), None);
+        assert_eq!(sandbox.resolve("missing/new.txt"), Some(root.join("missing/new.txt")));
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symbolic_links_cannot_leave_the_root() {
+        use std::os::unix::fs::symlink;
+
+        let directory = create_test_directory("links");
+        let sandbox = Sandbox::create(directory.join("root"));
+        let root = directory.join("root").canonicalize().unwrap();
+
+        symlink(directory.join("outside.txt"), directory.join("root/file_link")).unwrap();
+        symlink(&directory, directory.join("root/directory_link")).unwrap();
+        symlink(directory.join("created.txt"), directory.join("root/dangling_link")).unwrap();
+        symlink(root.join("inside.txt"), directory.join("root/inside_link")).unwrap();
+
+        assert_eq!(sandbox.resolve("file_link"), None);
+        assert_eq!(sandbox.resolve("directory_link/outside.txt"), None);
+        assert_eq!(sandbox.resolve("directory_link/new.txt"), None);
+        assert_eq!(sandbox.resolve("dangling_link"), None);
+        assert_eq!(sandbox.resolve("inside_link"), Some(root.join("inside_link")));
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
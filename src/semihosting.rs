@@ -0,0 +1,491 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, SeekFrom},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    context::CpuContext,
+    error::EmulatorError,
+    file::LoadedImage,
+    process,
+    sandbox::Sandbox,
+    stream::{FileTable, STANDARD_ERROR, STANDARD_INPUT, STANDARD_OUTPUT},
+    syscall::SyscallHandler,
+};
+
+// the ARM semihosting protocol, as used by newlib's rdimon: SVC 0x123456 or BKPT 0xAB, with the operation in r0 and
+// its parameter (usually the address of a block of words) in r1. The result is returned in r0
+pub struct SemihostingHandler {
+    files: FileTable,       // indexed by handle; the console is reached by opening ":tt", which duplicates 0, 1 or 2
+    command_line: String,
+    heap_base: u32,
+    last_error: u32,
+    start_time: Instant,
+}
+
+impl SemihostingHandler {
+    pub fn create(sandbox: Sandbox) -> SemihostingHandler {
+        SemihostingHandler {
+            files: FileTable::create(sandbox),
+            command_line: String::new(),
+            heap_base: 0,
+            last_error: 0,
+            start_time: Instant::now(),
+        }
+    }
+
+    pub fn get_files_mut(&mut self) -> &mut FileTable {
+        &mut self.files
+    }
+
+    // host errors are reported to the guest as -1, with the error code available through SYS_ERRNO
+    fn fail(&mut self, error: io::Error) -> u32 {
+        self.last_error = error.raw_os_error().unwrap_or(EIO) as u32;
+        FAILURE
+    }
+}
+
+impl SyscallHandler for SemihostingHandler {
+    fn handle(&mut self, immediate: u32, context: &mut CpuContext) -> Result<(), EmulatorError> {
+        if immediate != SEMIHOSTING_SUPERVISOR_CALL {
+            return Err(EmulatorError::UnsupportedSupervisorCall(immediate));
+        }
+
+        execute_semihosting_operation(self, context)
+    }
+
+    fn handle_breakpoint(&mut self, immediate: u32, context: &mut CpuContext) -> Result<(), EmulatorError> {
+        if immediate != SEMIHOSTING_BREAKPOINT {
+            return Err(EmulatorError::Breakpoint(immediate));
+        }
+
+        execute_semihosting_operation(self, context)
+    }
+
+    fn prepare(&mut self, image: &LoadedImage, arguments: &[String]) {
+        self.command_line = arguments.join(" ");
+        self.heap_base = image.end_address;
+    }
+}
+
+fn execute_semihosting_operation(handler: &mut SemihostingHandler, context: &mut CpuContext) -> Result<(), EmulatorError> {
+    let operation = context.get_register(0);
+    let parameter = context.get_register(1);
+
+    let result = match operation {
+        SYS_OPEN => open(handler, context, parameter)?,
+        SYS_CLOSE => close(handler, context, parameter)?,
+        SYS_WRITEC => write_console(handler, &[context.read_byte(parameter)?]),
+        SYS_WRITE0 => {
+            let string = read_string(context, parameter)?;
+            write_console(handler, &string)
+        },
+        SYS_WRITE => write(handler, context, parameter)?,
+        SYS_READ => read(handler, context, parameter)?,
+        SYS_READC => read_console(handler),
+        SYS_ISERROR => if (context.read_word(parameter)? as i32) < 0 { 1 } else { 0 },
+        SYS_ISTTY => is_terminal(handler, context, parameter)?,
+        SYS_SEEK => seek(handler, context, parameter)?,
+        SYS_FLEN => get_length(handler, context, parameter)?,
+        SYS_REMOVE => remove(handler, context, parameter)?,
+        SYS_RENAME => rename(handler, context, parameter)?,
+        SYS_CLOCK => (handler.start_time.elapsed().as_millis() / 10) as u32,
+        SYS_TIME => SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as u32,
+        SYS_ERRNO => handler.last_error,
+        SYS_GET_CMDLINE => get_command_line(handler, context, parameter)?,
+        SYS_HEAPINFO => get_heap_info(handler, context, parameter)?,
+        SYS_EXIT => {
+            context.exit(if parameter == ADP_STOPPED_APPLICATION_EXIT { 0 } else { 1 });
+            0
+        },
+        SYS_EXIT_EXTENDED => {
+            let reason = context.read_word(parameter)?;
+            let status = context.read_word(parameter.wrapping_add(WORD_SIZE))?;
+
+            context.exit(if reason == ADP_STOPPED_APPLICATION_EXIT { status } else { 1 });
+            0
+        },
+        SYS_ELAPSED => {
+            let ticks = handler.start_time.elapsed().as_micros() as u64;
+
+            context.write_word(parameter, ticks as u32)?;
+            context.write_word(parameter.wrapping_add(WORD_SIZE), (ticks >> 32) as u32)?;
+            0
+        },
+        SYS_TICKFREQ => TICKS_PER_SECOND,
+        // SYS_SYSTEM and SYS_TMPNAM would give the guest too much access to the host
+        _ => FAILURE,
+    };
+
+    context.set_register(0, result);
+    Ok(())
+}
+
+fn get_parameters<const N: usize>(context: &CpuContext, address: u32) -> Result<[u32; N], EmulatorError> {
+    let mut parameters = [0u32; N];
+
+    for (index, parameter) in parameters.iter_mut().enumerate() {
+        *parameter = context.read_word(address.wrapping_add(index as u32 * WORD_SIZE))?;
+    }
+
+    Ok(parameters)
+}
+
+// the parameter block holds the address of the file name, the fopen() mode as a number from 0 to 11, and the length of the name.
+// The special name ":tt" opens the console
+fn open(handler: &mut SemihostingHandler, context: &CpuContext, parameter: u32) -> Result<u32, EmulatorError> {
+    let [name_address, mode, name_length] = get_parameters(context, parameter)?;
+    let name = String::from_utf8_lossy(&context.read_memory(name_address, name_length as usize)?).into_owned();
+
+    if name == CONSOLE_NAME {
+        let descriptor = match mode / 4 {
+            0 => STANDARD_INPUT,
+            1 => STANDARD_OUTPUT,
+            _ => STANDARD_ERROR,
+        };
+
+        let stream = match handler.files.get(descriptor).map(|s| s.try_clone()) {
+            Some(Ok(stream)) => stream,
+            Some(Err(e)) => return Ok(handler.fail(e)),
+            None => return Ok(FAILURE),
+        };
+
+        return Ok(handler.files.add(stream));
+    }
+
+    // modes come in groups of four: r, w and a, each with b, + and +b variants
+    let mut options = OpenOptions::new();
+    let update = mode & 0x2 != 0;
+    match mode / 4 {
+        0 => options.read(true).write(update),
+        1 => options.write(true).read(update).create(true).truncate(true),
+        2 => options.append(true).read(update).create(true),
+        _ => return Ok(FAILURE),
+    };
+
+    match handler.files.open(&name, &options) {
+        Ok(handle) => Ok(handle),
+        Err(e) => Ok(handler.fail(e)),
+    }
+}
+
+fn close(handler: &mut SemihostingHandler, context: &CpuContext, parameter: u32) -> Result<u32, EmulatorError> {
+    let [handle] = get_parameters(context, parameter)?;
+
+    if !handler.files.close(handle) {
+        return Ok(FAILURE);
+    }
+
+    Ok(0)
+}
+
+// returns the number of bytes that were not written
+fn write(handler: &mut SemihostingHandler, context: &CpuContext, parameter: u32) -> Result<u32, EmulatorError> {
+    let [handle, address, length] = get_parameters(context, parameter)?;
+    let data = context.read_memory(address, length as usize)?;
+
+    let result = match handler.files.get(handle) {
+        Some(stream) => stream.write(&data),
+        None => return Ok(length),
+    };
+
+    match result {
+        Ok(()) => Ok(0),
+        Err(e) => {
+            handler.fail(e);
+            Ok(length)
+        }
+    }
+}
+
+// returns the number of bytes that were not read, so reading nothing at all signals the end of the file
+fn read(handler: &mut SemihostingHandler, context: &mut CpuContext, parameter: u32) -> Result<u32, EmulatorError> {
+    let [handle, address, length] = get_parameters(context, parameter)?;
+    let mut buffer = vec![0u8; length.min(MAXIMUM_READ_SIZE) as usize];
+
+    let result = match handler.files.get(handle) {
+        Some(stream) => stream.read(&mut buffer),
+        None => return Ok(length),
+    };
+
+    match result {
+        Ok(count) => {
+            context.write_memory(address, &buffer[..count])?;
+            Ok(length - count as u32)
+        },
+        Err(e) => {
+            handler.fail(e);
+            Ok(length)
+        }
+    }
+}
+
+// the console is whatever the guest's standard output and input are connected to
+fn write_console(handler: &mut SemihostingHandler, data: &[u8]) -> u32 {
+    let result = match handler.files.get(STANDARD_OUTPUT) {
+        Some(stream) => stream.write(data),
+        None => return FAILURE,
+    };
+
+    match result {
+        Ok(()) => 0,
+        Err(e) => handler.fail(e),
+    }
+}
+
+fn read_console(handler: &mut SemihostingHandler) -> u32 {
+    let mut character = [0u8; 1];
+
+    let result = match handler.files.get(STANDARD_INPUT) {
+        Some(stream) => stream.read(&mut character),
+        None => return FAILURE,
+    };
+
+    match result {
+        Ok(0) => FAILURE,
+        Ok(_) => character[0] as u32,
+        Err(e) => handler.fail(e),
+    }
+}
+
+fn is_terminal(handler: &mut SemihostingHandler, context: &CpuContext, parameter: u32) -> Result<u32, EmulatorError> {
+    let [handle] = get_parameters(context, parameter)?;
+
+    let terminal = handler.files.get(handle).is_some_and(|s| s.is_terminal());
+
+    Ok(terminal as u32)
+}
+
+fn seek(handler: &mut SemihostingHandler, context: &CpuContext, parameter: u32) -> Result<u32, EmulatorError> {
+    let [handle, position] = get_parameters(context, parameter)?;
+
+    let result = match handler.files.get(handle) {
+        Some(stream) if stream.is_seekable() => stream.seek(SeekFrom::Start(position as u64)),
+        _ => return Ok(FAILURE),
+    };
+
+    match result {
+        Ok(_) => Ok(0),
+        Err(e) => Ok(handler.fail(e)),
+    }
+}
+
+fn get_length(handler: &mut SemihostingHandler, context: &CpuContext, parameter: u32) -> Result<u32, EmulatorError> {
+    let [handle] = get_parameters(context, parameter)?;
+
+    let result = match handler.files.get(handle).and_then(|s| s.get_metadata()) {
+        Some(result) => result,
+        None => return Ok(FAILURE),
+    };
+
+    match result {
+        Ok(metadata) => Ok(metadata.len() as u32),
+        Err(e) => Ok(handler.fail(e)),
+    }
+}
+
+fn remove(handler: &mut SemihostingHandler, context: &CpuContext, parameter: u32) -> Result<u32, EmulatorError> {
+    let [name_address, name_length] = get_parameters(context, parameter)?;
+    let name = String::from_utf8_lossy(&context.read_memory(name_address, name_length as usize)?).into_owned();
+
+    let result = match handler.files.get_sandbox().resolve(&name) {
+        Some(path) => fs::remove_file(path),
+        None => Err(io::Error::from(io::ErrorKind::PermissionDenied)),
+    };
+
+    match result {
+        Ok(()) => Ok(0),
+        Err(e) => Ok(handler.fail(e)),
+    }
+}
+
+fn rename(handler: &mut SemihostingHandler, context: &CpuContext, parameter: u32) -> Result<u32, EmulatorError> {
+    let [from_address, from_length, to_address, to_length] = get_parameters(context, parameter)?;
+    let from = String::from_utf8_lossy(&context.read_memory(from_address, from_length as usize)?).into_owned();
+    let to = String::from_utf8_lossy(&context.read_memory(to_address, to_length as usize)?).into_owned();
+
+    let sandbox = handler.files.get_sandbox();
+    let result = match (sandbox.resolve(&from), sandbox.resolve(&to)) {
+        (Some(from), Some(to)) => fs::rename(from, to),
+        _ => Err(io::Error::from(io::ErrorKind::PermissionDenied)),
+    };
+
+    match result {
+        Ok(()) => Ok(0),
+        Err(e) => Ok(handler.fail(e)),
+    }
+}
+
+// the parameter block holds the address and the size of a buffer; the size is updated to the length of the command line
+fn get_command_line(handler: &mut SemihostingHandler, context: &mut CpuContext, parameter: u32) -> Result<u32, EmulatorError> {
+    let [address, size] = get_parameters(context, parameter)?;
+    let command_line = handler.command_line.as_bytes();
+
+    if command_line.len() as u32 >= size {
+        return Ok(FAILURE);
+    }
+
+    let mut data = command_line.to_vec();
+    data.push(0);
+
+    context.write_memory(address, &data)?;
+    context.write_word(parameter.wrapping_add(WORD_SIZE), command_line.len() as u32)?;
+
+    Ok(0)
+}
+
+// the parameter points to the address of a block that receives the heap base and limit, and the stack base and limit.
+// The stack grows down, so its base is the top of the stack, which crt0 loads into SP; the heap may grow up to the stack's limit
+fn get_heap_info(handler: &mut SemihostingHandler, context: &mut CpuContext, parameter: u32) -> Result<u32, EmulatorError> {
+    let block = context.read_word(parameter)?;
+    let (stack_limit, stack_base) = process::get_stack_range();
+    let values = [handler.heap_base, stack_limit, stack_base, stack_limit];
+
+    for (index, value) in values.iter().enumerate() {
+        context.write_word(block.wrapping_add(index as u32 * WORD_SIZE), *value)?;
+    }
+
+    Ok(0)
+}
+
+fn read_string(context: &CpuContext, address: u32) -> Result<Vec<u8>, EmulatorError> {
+    let mut bytes = Vec::new();
+
+    loop {
+        let byte = context.read_byte(address.wrapping_add(bytes.len() as u32))?;
+        if byte == 0 {
+            return Ok(bytes);
+        }
+
+        bytes.push(byte);
+    }
+}
+
+const SEMIHOSTING_SUPERVISOR_CALL: u32 = 0x123456;
+const SEMIHOSTING_BREAKPOINT: u32 = 0xab;
+
+const SYS_OPEN: u32 = 0x01;
+const SYS_CLOSE: u32 = 0x02;
+const SYS_WRITEC: u32 = 0x03;
+const SYS_WRITE0: u32 = 0x04;
+const SYS_WRITE: u32 = 0x05;
+const SYS_READ: u32 = 0x06;
+const SYS_READC: u32 = 0x07;
+const SYS_ISERROR: u32 = 0x08;
+const SYS_ISTTY: u32 = 0x09;
+const SYS_SEEK: u32 = 0x0a;
+const SYS_FLEN: u32 = 0x0c;
+const SYS_REMOVE: u32 = 0x0e;
+const SYS_RENAME: u32 = 0x0f;
+const SYS_CLOCK: u32 = 0x10;
+const SYS_TIME: u32 = 0x11;
+const SYS_ERRNO: u32 = 0x13;
+const SYS_GET_CMDLINE: u32 = 0x15;
+const SYS_HEAPINFO: u32 = 0x16;
+const SYS_EXIT: u32 = 0x18;
+const SYS_EXIT_EXTENDED: u32 = 0x20;
+const SYS_ELAPSED: u32 = 0x30;
+const SYS_TICKFREQ: u32 = 0x31;
+
+const ADP_STOPPED_APPLICATION_EXIT: u32 = 0x20026;
+const CONSOLE_NAME: &str = ":tt";
+const FAILURE: u32 = u32::MAX;     // -1
+const EIO: i32 = 5;
+const TICKS_PER_SECOND: u32 = 1_000_000;
+const MAXIMUM_READ_SIZE: u32 = 0x100000;
+const WORD_SIZE: u32 = 4;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{context::CpuConfiguration, stream::{OutputBuffer, Stream}};
+
+    fn create_handler() -> (SemihostingHandler, OutputBuffer) {
+        let mut handler = SemihostingHandler::create(Sandbox::create("."));
+        let output = OutputBuffer::create();
+
+        handler.get_files_mut().redirect(STANDARD_OUTPUT, Stream::Buffer(output.clone()));
+        (handler, output)
+    }
+
+    fn call(handler: &mut SemihostingHandler, context: &mut CpuContext, operation: u32, parameter: u32) -> u32 {
+        context.set_register(0, operation);
+        context.set_register(1, parameter);
+
+        handler.handle(SEMIHOSTING_SUPERVISOR_CALL, context).unwrap();
+        context.get_register(0)
+    }
+
+    #[test]
+    fn console_output_goes_to_standard_output() {
+        let mut context = CpuContext::create(CpuConfiguration::default());
+        let (mut handler, output) = create_handler();
+
+        context.write_memory(0x2000, b"Hello\0").unwrap();
+        assert_eq!(call(&mut handler, &mut context, SYS_WRITE0, 0x2000), 0);
+        assert_eq!(call(&mut handler, &mut context, SYS_WRITEC, 0x2000), 0);
+
+        // SYS_WRITE takes a handle, an address and a length, and returns how much wasn't written
+        context.write_memory(0x3000, &[1, 0, 0, 0, 0x01, 0x20, 0, 0, 0x02, 0, 0, 0]).unwrap();
+        assert_eq!(call(&mut handler, &mut context, SYS_WRITE, 0x3000), 0);
+
+        // BKPT 0xAB works like SVC 0x123456
+        context.set_register(0, SYS_WRITEC);
+        context.set_register(1, 0x2004);
+        handler.handle_breakpoint(SEMIHOSTING_BREAKPOINT, &mut context).unwrap();
+
+        assert_eq!(output.get_text(), "HelloHelo");
+    }
+
+    #[test]
+    fn other_immediates_are_not_semihosting() {
+        let mut context = CpuContext::create(CpuConfiguration::default());
+        let (mut handler, _) = create_handler();
+
+        assert!(matches!(handler.handle(0, &mut context), Err(EmulatorError::UnsupportedSupervisorCall(0))));
+        assert!(matches!(handler.handle_breakpoint(0x01, &mut context), Err(EmulatorError::Breakpoint(0x01))));
+    }
+
+    #[test]
+    fn exit_reports_success_only_for_a_normal_exit() {
+        let mut context = CpuContext::create(CpuConfiguration::default());
+        let (mut handler, _) = create_handler();
+
+        call(&mut handler, &mut context, SYS_EXIT, ADP_STOPPED_APPLICATION_EXIT);
+        assert_eq!(context.get_exit_status(), Some(0));
+
+        let mut context = CpuContext::create(CpuConfiguration::default());
+        call(&mut handler, &mut context, SYS_EXIT, 0x20023);
+        assert_eq!(context.get_exit_status(), Some(1));
+
+        // SYS_EXIT_EXTENDED takes the reason and the status from a parameter block
+        let mut context = CpuContext::create(CpuConfiguration::default());
+        context.write_memory(0x2000, &[0x26, 0x00, 0x02, 0x00, 0x2a, 0x00, 0x00, 0x00]).unwrap();
+        call(&mut handler, &mut context, SYS_EXIT_EXTENDED, 0x2000);
+        assert_eq!(context.get_exit_status(), Some(42));
+    }
+
+    #[test]
+    fn command_line_and_heap_info_describe_the_program() {
+        let mut context = CpuContext::create(CpuConfiguration::default());
+        let (mut handler, _) = create_handler();
+        let image = LoadedImage { entry_point: 0x8000, program_header_address: 0x8034, program_header_entry_size: 32, program_header_count: 1, end_address: 0x9000 };
+
+        handler.prepare(&image, &[String::from("program"), String::from("argument")]);
+
+        // SYS_GET_CMDLINE fills in the buffer and its length
+        context.write_memory(0x2000, &[0x00, 0x30, 0, 0, 0x40, 0, 0, 0]).unwrap();
+        assert_eq!(call(&mut handler, &mut context, SYS_GET_CMDLINE, 0x2000), 0);
+        assert_eq!(context.read_memory(0x3000, 17).unwrap(), b"program argument\0");
+        assert_eq!(context.read_word(0x2004).unwrap(), 16);
+
+        // SYS_HEAPINFO: the heap starts after the program, and the stack base is where SP starts
+        let (stack_bottom, stack_top) = process::get_stack_range();
+        context.write_word(0x2000, 0x4000).unwrap();
+        assert_eq!(call(&mut handler, &mut context, SYS_HEAPINFO, 0x2000), 0);
+
+        let block: Vec<u32> = (0..4).map(|index| context.read_word(0x4000 + index * WORD_SIZE).unwrap()).collect();
+        assert_eq!(block, [0x9000, stack_bottom, stack_top, stack_bottom]);
+    }
+}
@@ -0,0 +1,173 @@
+use std::{
+    cell::RefCell,
+    fs::{File, Metadata, OpenOptions},
+    io::{self, stderr, stdin, stdout, ErrorKind, IsTerminal, Read, Seek, SeekFrom, Write},
+    rc::Rc,
+};
+
+use crate::sandbox::Sandbox;
+
+pub const STANDARD_INPUT: u32 = 0;
+pub const STANDARD_OUTPUT: u32 = 1;
+pub const STANDARD_ERROR: u32 = 2;
+
+// collects guest output in memory. Clones share the same buffer, so an embedder can keep one and hand another to the guest
+#[derive(Clone, Default)]
+pub struct OutputBuffer {
+    data: Rc<RefCell<Vec<u8>>>,
+}
+
+impl OutputBuffer {
+    pub fn create() -> OutputBuffer {
+        OutputBuffer::default()
+    }
+
+    pub fn get_contents(&self) -> Vec<u8> {
+        self.data.borrow().clone()
+    }
+
+    // the contents as text, with invalid UTF-8 replaced
+    pub fn get_text(&self) -> String {
+        String::from_utf8_lossy(&self.data.borrow()).into_owned()
+    }
+
+    pub fn clear(&self) {
+        self.data.borrow_mut().clear()
+    }
+}
+
+// what a file descriptor refers to
+pub enum Stream {
+    StandardInput,          // the emulator's own standard input
+    StandardOutput,         // the emulator's own standard output
+    StandardError,          // the emulator's own standard error
+    File(File),
+    Buffer(OutputBuffer),   // write-only
+}
+
+impl Stream {
+    // reading from a stream that can only be written to fails with ErrorKind::Unsupported, and vice versa
+    pub fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::StandardInput => stdin().read(buffer),
+            Stream::File(file) => file.read(buffer),
+            _ => Err(io::Error::from(ErrorKind::Unsupported)),
+        }
+    }
+
+    pub fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        match self {
+            Stream::StandardOutput => stdout().write_all(data).and_then(|_| stdout().flush()),
+            Stream::StandardError => stderr().write_all(data),
+            Stream::File(file) => file.write_all(data),
+            Stream::Buffer(buffer) => {
+                buffer.data.borrow_mut().extend_from_slice(data);
+                Ok(())
+            },
+            Stream::StandardInput => Err(io::Error::from(ErrorKind::Unsupported)),
+        }
+    }
+
+    pub fn is_seekable(&self) -> bool {
+        matches!(self, Stream::File(_))
+    }
+
+    pub fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+        match self {
+            Stream::File(file) => file.seek(position),
+            _ => Err(io::Error::from(ErrorKind::Unsupported)),
+        }
+    }
+
+    // None for streams that aren't backed by a file
+    pub fn get_metadata(&self) -> Option<io::Result<Metadata>> {
+        match self {
+            Stream::File(file) => Some(file.metadata()),
+            _ => None,
+        }
+    }
+
+    pub fn is_terminal(&self) -> bool {
+        match self {
+            Stream::StandardInput => stdin().is_terminal(),
+            Stream::StandardOutput => stdout().is_terminal(),
+            Stream::StandardError => stderr().is_terminal(),
+            Stream::File(_) | Stream::Buffer(_) => false,
+        }
+    }
+
+    // like dup(), the copy of a file shares its position with the original
+    pub fn try_clone(&self) -> io::Result<Stream> {
+        match self {
+            Stream::StandardInput => Ok(Stream::StandardInput),
+            Stream::StandardOutput => Ok(Stream::StandardOutput),
+            Stream::StandardError => Ok(Stream::StandardError),
+            Stream::File(file) => Ok(Stream::File(file.try_clone()?)),
+            Stream::Buffer(buffer) => Ok(Stream::Buffer(buffer.clone())),
+        }
+    }
+}
+
+// the guest's open files, indexed by descriptor. Descriptors 0, 1 and 2 start out connected to the emulator's own standard
+// streams; embedders can redirect them, for example to capture the guest's output. Files are opened inside the sandbox
+pub struct FileTable {
+    streams: Vec<Option<Stream>>,
+    sandbox: Sandbox,
+}
+
+impl FileTable {
+    pub fn create(sandbox: Sandbox) -> FileTable {
+        FileTable {
+            streams: vec![Some(Stream::StandardInput), Some(Stream::StandardOutput), Some(Stream::StandardError)],
+            sandbox,
+        }
+    }
+
+    pub fn get_sandbox(&self) -> &Sandbox {
+        &self.sandbox
+    }
+
+    pub fn get(&mut self, descriptor: u32) -> Option<&mut Stream> {
+        self.streams.get_mut(descriptor as usize).and_then(|s| s.as_mut())
+    }
+
+    // like the kernel, this hands out the lowest free descriptor
+    pub fn add(&mut self, stream: Stream) -> u32 {
+        match self.streams.iter().position(|s| s.is_none()) {
+            Some(descriptor) => {
+                self.streams[descriptor] = Some(stream);
+                descriptor as u32
+            },
+            None => {
+                self.streams.push(Some(stream));
+                (self.streams.len() - 1) as u32
+            }
+        }
+    }
+
+    // connects the descriptor to another stream, closing whatever it referred to before
+    pub fn redirect(&mut self, descriptor: u32, stream: Stream) {
+        let index = descriptor as usize;
+        if index >= self.streams.len() {
+            self.streams.resize_with(index + 1, || None);
+        }
+
+        self.streams[index] = Some(stream);
+    }
+
+    // returns whether the descriptor was open
+    pub fn close(&mut self, descriptor: u32) -> bool {
+        match self.streams.get_mut(descriptor as usize) {
+            Some(stream) => stream.take().is_some(),
+            None => false,
+        }
+    }
+
+    // paths outside the sandbox fail with ErrorKind::PermissionDenied
+    pub fn open(&mut self, guest_path: &str, options: &OpenOptions) -> io::Result<u32> {
+        let path = self.sandbox.resolve(guest_path).ok_or_else(|| io::Error::from(ErrorKind::PermissionDenied))?;
+        let file = options.open(path)?;
+
+        Ok(self.add(Stream::File(file)))
+    }
+}
@@ -1,27 +1,1029 @@
-use std::io::{stdout, Write};
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, ErrorKind, SeekFrom},
+    mem::size_of,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
 
-use crate::context::CpuContext;
+use crate::{
+    context::{CpuContext, Endianness, SystemCallAbi},
+    error::EmulatorError,
+    file::LoadedImage,
+    memory::{Permissions, MAXIMUM_MEMORY_SIZE, PAGE_SIZE},
+    sandbox::Sandbox,
+    semihosting::SemihostingHandler,
+    stream::{FileTable, Stream},
+};
 
-pub fn execute_system_call(context: &mut CpuContext) {
-    const SYSTEM_CALL_REGISTER: u8 = 7;
-    let system_call = context.get_register(SYSTEM_CALL_REGISTER);
+const SYSTEM_CALL_REGISTER: u8 = 7;
+const SYSTEM_CALL_IMMEDIATE: u32 = 0;
+
+// receives every SVC the guest executes, together with the immediate encoded in the instruction. Embedders can install
+// their own handler through Machine::set_syscall_handler, for example to intercept the guest's I/O
+pub trait SyscallHandler {
+    fn handle(&mut self, immediate: u32, context: &mut CpuContext) -> Result<(), EmulatorError>;
+
+    // BKPT stops the program unless the handler gives it a meaning, as semihosting does
+    fn handle_breakpoint(&mut self, immediate: u32, _context: &mut CpuContext) -> Result<(), EmulatorError> {
+        Err(EmulatorError::Breakpoint(immediate))
+    }
+
+    // called once a program has been loaded, before it starts running, with the arguments it is started with
+    fn prepare(&mut self, _image: &LoadedImage, _arguments: &[String]) {}
+}
 
-    match system_call {
-        EXIT_SYSTEM_CALL => context.halt(),
-        WRITE_SYSTEM_CALL => write(context),
-        _ => panic!("Unsupported system call {:0>8X}", system_call)
+// the guest only gets to open files inside the sandbox
+pub fn create_syscall_handler(abi: SystemCallAbi, sandbox: Sandbox) -> Box<dyn SyscallHandler> {
+    match abi {
+        SystemCallAbi::Linux => Box::new(LinuxSyscallHandler::create(sandbox)),
+        SystemCallAbi::Course => Box::new(CourseSyscallHandler::create(FileTable::create(sandbox))),
+        SystemCallAbi::Semihosting => Box::new(SemihostingHandler::create(sandbox)),
     }
 }
 
-fn write(context: &CpuContext) {
+// the ABI the programs in asm/ were written for: write takes a length-prefixed UTF-16 string, nothing is returned, and exit
+// takes its status in r0 like on Linux
+pub struct CourseSyscallHandler {
+    files: FileTable,
+}
+
+impl CourseSyscallHandler {
+    pub fn create(files: FileTable) -> CourseSyscallHandler {
+        CourseSyscallHandler { files }
+    }
+
+    pub fn get_files_mut(&mut self) -> &mut FileTable {
+        &mut self.files
+    }
+}
+
+impl SyscallHandler for CourseSyscallHandler {
+    fn handle(&mut self, immediate: u32, context: &mut CpuContext) -> Result<(), EmulatorError> {
+        if immediate != SYSTEM_CALL_IMMEDIATE {
+            return Err(EmulatorError::UnsupportedSupervisorCall(immediate));
+        }
+
+        let system_call = context.get_register(SYSTEM_CALL_REGISTER);
+
+        match system_call {
+            EXIT_SYSTEM_CALL => {
+                context.exit(context.get_register(0) & EXIT_STATUS_MASK);
+                Ok(())
+            },
+            WRITE_SYSTEM_CALL => write_string(&mut self.files, context),
+            _ => Err(EmulatorError::UnsupportedSystemCall(system_call))
+        }
+    }
+}
+
+fn write_string(files: &mut FileTable, context: &CpuContext) -> Result<(), EmulatorError> {
     let file_descriptor = context.get_register(0);
     let address = context.get_register(1);
 
-    assert!(file_descriptor == 1);
+    let data = context.read_string(address)?;
+    let stream = files.get(file_descriptor).ok_or(EmulatorError::UnsupportedFileDescriptor(file_descriptor))?;
+
+    stream.write(data.as_bytes()).map_err(|e| match e.kind() {
+        ErrorKind::Unsupported => EmulatorError::UnsupportedFileDescriptor(file_descriptor),
+        _ => EmulatorError::Io(e),
+    })
+}
+
+// the Linux ARM EABI: the system call number is in r7, arguments are in r0-r6, and the result is returned in r0,
+// with errors returned as negated errno values
+pub struct LinuxSyscallHandler {
+    process: ProcessState,
+}
+
+impl LinuxSyscallHandler {
+    pub fn create(sandbox: Sandbox) -> LinuxSyscallHandler {
+        LinuxSyscallHandler {
+            process: ProcessState::create(FileTable::create(sandbox)),
+        }
+    }
+
+    pub fn get_files_mut(&mut self) -> &mut FileTable {
+        &mut self.process.files
+    }
+
+    pub fn get_thread_id_address(&self) -> u32 {
+        self.process.thread_id_address
+    }
+
+    pub fn get_thread_local_storage(&self) -> u32 {
+        self.process.thread_local_storage
+    }
+}
+
+impl SyscallHandler for LinuxSyscallHandler {
+    fn handle(&mut self, immediate: u32, context: &mut CpuContext) -> Result<(), EmulatorError> {
+        if immediate != SYSTEM_CALL_IMMEDIATE {
+            return Err(EmulatorError::UnsupportedSupervisorCall(immediate));
+        }
+
+        execute_linux_system_call(&mut self.process, context);
+        Ok(())
+    }
+
+    fn prepare(&mut self, image: &LoadedImage, _arguments: &[String]) {
+        self.process.set_initial_program_break(image.end_address);
+    }
+}
+
+fn execute_linux_system_call(process: &mut ProcessState, context: &mut CpuContext) {
+    let system_call = context.get_register(SYSTEM_CALL_REGISTER);
+
+    let result = match system_call {
+        EXIT_SYSTEM_CALL | EXIT_GROUP_SYSTEM_CALL => exit(context),
+        READ_SYSTEM_CALL => read(process, context),
+        WRITE_SYSTEM_CALL => write(process, context),
+        OPEN_SYSTEM_CALL => open(process, context),
+        CLOSE_SYSTEM_CALL => close(process, context),
+        LSEEK_SYSTEM_CALL => seek(process, context),
+        GETPID_SYSTEM_CALL => Ok(get_process_id()),
+        BRK_SYSTEM_CALL => set_program_break(process, context),
+        IOCTL_SYSTEM_CALL => io_control(process, context),
+        GETTIMEOFDAY_SYSTEM_CALL => get_time_of_day(context),
+        MUNMAP_SYSTEM_CALL => unmap_memory(process, context),
+        FSTAT_SYSTEM_CALL => get_file_status(process, context, write_stat),
+        UNAME_SYSTEM_CALL => get_system_name(context),
+        LLSEEK_SYSTEM_CALL => seek_long(process, context),
+        WRITEV_SYSTEM_CALL => write_vector(process, context),
+        MMAP2_SYSTEM_CALL => map_memory(process, context),
+        FSTAT64_SYSTEM_CALL => get_file_status(process, context, write_stat64),
+        SET_TID_ADDRESS_SYSTEM_CALL => set_thread_id_address(process, context),
+        CLOCK_GETTIME_SYSTEM_CALL => get_clock_time(process, context),
+        OPENAT_SYSTEM_CALL => open_at(process, context),
+        ARM_CACHEFLUSH_SYSTEM_CALL => Ok(0),
+        ARM_SET_TLS_SYSTEM_CALL => set_thread_local_storage(process, context),
+        _ => Err(ENOSYS),
+    };
+
+    let value = match result {
+        Ok(value) => value,
+        Err(errno) => (-errno) as u32,
+    };
+
+    context.set_register(0, value);
+}
+
+type SystemCallResult = Result<u32, i32>;
+
+struct Mapping {
+    start: u32,
+    length: u32,
+}
+
+// the kernel's view of the process: its open files, heap and memory mappings
+struct ProcessState {
+    files: FileTable,
+    initial_program_break: u32,
+    program_break: u32,
+    next_mapping_address: u32,
+    mappings: Vec<Mapping>,
+    thread_id_address: u32,
+    thread_local_storage: u32,
+    start_time: Instant,
+}
+
+impl ProcessState {
+    fn create(files: FileTable) -> ProcessState {
+        ProcessState {
+            files,
+            initial_program_break: 0,
+            program_break: 0,
+            next_mapping_address: MAPPING_AREA_START,
+            mappings: Vec::new(),
+            thread_id_address: 0,
+            thread_local_storage: 0,
+            start_time: Instant::now(),
+        }
+    }
+
+    // the heap starts at the first page after the loaded program
+    fn set_initial_program_break(&mut self, address: u32) {
+        let address = align_to_page(address);
+
+        self.initial_program_break = address;
+        self.program_break = address;
+    }
+
+    fn get_file(&mut self, descriptor: u32) -> Result<&mut Stream, i32> {
+        self.files.get(descriptor).ok_or(EBADF)
+    }
+}
+
+// like on Linux, only the low byte of the status reaches the parent
+fn exit(context: &mut CpuContext) -> SystemCallResult {
+    context.exit(context.get_register(0) & EXIT_STATUS_MASK);
+    Ok(0)
+}
+
+fn read(process: &mut ProcessState, context: &mut CpuContext) -> SystemCallResult {
+    let descriptor = context.get_register(0);
+    let address = context.get_register(1);
+    let count = context.get_register(2);
+
+    // reads may be shorter than requested, which keeps the buffer for absurd counts in check
+    let mut buffer = vec![0u8; count.min(MAXIMUM_READ_SIZE) as usize];
+    let length = process.get_file(descriptor)?.read(&mut buffer).map_err(get_errno)?;
+
+    write_guest_memory(context, address, &buffer[..length])?;
+    Ok(length as u32)
+}
+
+fn write(process: &mut ProcessState, context: &mut CpuContext) -> SystemCallResult {
+    let descriptor = context.get_register(0);
+    let address = context.get_register(1);
+    let count = context.get_register(2);
 
-    let data = context.read_string(address);
-    stdout().write(data.as_bytes()).unwrap();
+    let data = read_guest_memory(context, address, count)?;
+    write_file(process, descriptor, &data)
 }
 
-const EXIT_SYSTEM_CALL: u32 = 0x1;
-const WRITE_SYSTEM_CALL: u32 = 0x4;
\ No newline at end of file
+// gathers the buffers described by an array of struct iovec { void *base; size_t length; } into a single write
+fn write_vector(process: &mut ProcessState, context: &mut CpuContext) -> SystemCallResult {
+    let descriptor = context.get_register(0);
+    let vector_address = context.get_register(1);
+    let count = context.get_register(2);
+
+    let mut data = Vec::new();
+    for index in 0..count {
+        let entry_address = vector_address.wrapping_add(index * IOVEC_SIZE);
+        let base = read_guest_word(context, entry_address)?;
+        let length = read_guest_word(context, entry_address.wrapping_add(WORD_SIZE))?;
+
+        data.extend(read_guest_memory(context, base, length)?);
+    }
+
+    write_file(process, descriptor, &data)
+}
+
+fn write_file(process: &mut ProcessState, descriptor: u32, data: &[u8]) -> SystemCallResult {
+    process.get_file(descriptor)?.write(data).map_err(get_errno)?;
+
+    Ok(data.len() as u32)
+}
+
+fn open(process: &mut ProcessState, context: &mut CpuContext) -> SystemCallResult {
+    let path_address = context.get_register(0);
+    let flags = context.get_register(1);
+
+    open_file(process, context, path_address, flags)
+}
+
+// only paths relative to the current directory (AT_FDCWD) or absolute paths are supported; both start at the sandbox root
+fn open_at(process: &mut ProcessState, context: &mut CpuContext) -> SystemCallResult {
+    let directory = context.get_register(0) as i32;
+    let path_address = context.get_register(1);
+    let flags = context.get_register(2);
+
+    let path = read_guest_string(context, path_address)?;
+    if directory != AT_FDCWD && !path.starts_with('/') {
+        return Err(EBADF);
+    }
+
+    open_file(process, context, path_address, flags)
+}
+
+fn open_file(process: &mut ProcessState, context: &mut CpuContext, path_address: u32, flags: u32) -> SystemCallResult {
+    let path = read_guest_string(context, path_address)?;
+
+    let mut options = OpenOptions::new();
+    match flags & O_ACCMODE {
+        O_RDONLY => options.read(true),
+        O_WRONLY => options.write(true),
+        O_RDWR => options.read(true).write(true),
+        _ => return Err(EINVAL),
+    };
+
+    options.append(flags & O_APPEND != 0)
+        .truncate(flags & O_TRUNC != 0);
+
+    if flags & O_CREAT != 0 {
+        if flags & O_EXCL != 0 {
+            options.create_new(true);
+        } else {
+            options.create(true);
+        }
+    }
+
+    process.files.open(&path, &options).map_err(get_errno)
+}
+
+fn close(process: &mut ProcessState, context: &mut CpuContext) -> SystemCallResult {
+    let descriptor = context.get_register(0);
+
+    if !process.files.close(descriptor) {
+        return Err(EBADF);
+    }
+
+    Ok(0)
+}
+
+fn seek(process: &mut ProcessState, context: &mut CpuContext) -> SystemCallResult {
+    let descriptor = context.get_register(0);
+    let offset = context.get_register(1) as i32 as i64;
+    let whence = context.get_register(2);
+
+    let position = seek_file(process, descriptor, offset, whence)?;
+    if position > i32::MAX as u64 {
+        return Err(EOVERFLOW);
+    }
+
+    Ok(position as u32)
+}
+
+// _llseek(fd, offset_high, offset_low, loff_t *result, whence)
+fn seek_long(process: &mut ProcessState, context: &mut CpuContext) -> SystemCallResult {
+    let descriptor = context.get_register(0);
+    let offset = (((context.get_register(1) as u64) << 32) | context.get_register(2) as u64) as i64;
+    let result_address = context.get_register(3);
+    let whence = context.get_register(4);
+
+    let position = seek_file(process, descriptor, offset, whence)?;
+
+    let mut data = [0u8; size_of::<u64>()];
+    put_u64(context, &mut data, 0, position);
+    write_guest_memory(context, result_address, &data)?;
+
+    Ok(0)
+}
+
+fn seek_file(process: &mut ProcessState, descriptor: u32, offset: i64, whence: u32) -> Result<u64, i32> {
+    let position = match whence {
+        SEEK_SET if offset >= 0 => SeekFrom::Start(offset as u64),
+        SEEK_CUR => SeekFrom::Current(offset),
+        SEEK_END => SeekFrom::End(offset),
+        _ => return Err(EINVAL),
+    };
+
+    let stream = process.get_file(descriptor)?;
+    if !stream.is_seekable() {
+        return Err(ESPIPE);
+    }
+
+    stream.seek(position).map_err(get_errno)
+}
+
+fn get_file_status(process: &mut ProcessState, context: &mut CpuContext, write: fn(&mut CpuContext, u32, &FileStatus) -> Result<(), i32>) -> SystemCallResult {
+    let descriptor = context.get_register(0);
+    let address = context.get_register(1);
+
+    let status = match process.get_file(descriptor)?.get_metadata() {
+        Some(metadata) => FileStatus::from_metadata(&metadata.map_err(get_errno)?),
+        None => FileStatus::character_device(),
+    };
+
+    write(context, address, &status)?;
+    Ok(0)
+}
+
+struct FileStatus {
+    mode: u32,
+    size: u64,
+    modified: u32,
+}
+
+impl FileStatus {
+    fn from_metadata(metadata: &fs::Metadata) -> FileStatus {
+        let mode = match (metadata.is_dir(), metadata.permissions().readonly()) {
+            (true, _) => S_IFDIR | 0o755,
+            (false, true) => S_IFREG | 0o444,
+            (false, false) => S_IFREG | 0o644,
+        };
+        let modified = metadata.modified().ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |d| d.as_secs() as u32);
+
+        FileStatus { mode, size: metadata.len(), modified }
+    }
+
+    fn character_device() -> FileStatus {
+        FileStatus { mode: S_IFCHR | 0o620, size: 0, modified: 0 }
+    }
+}
+
+// struct stat from the ARM asm/stat.h
+fn write_stat(context: &mut CpuContext, address: u32, status: &FileStatus) -> Result<(), i32> {
+    let mut data = vec![0u8; STAT_SIZE];
+
+    put_u16(context, &mut data, 0x08, status.mode as u16);
+    put_u16(context, &mut data, 0x0A, 1);
+    put_u32(context, &mut data, 0x14, status.size.min(u32::MAX as u64) as u32);
+    put_u32(context, &mut data, 0x18, PAGE_SIZE as u32);
+    put_u32(context, &mut data, 0x1C, status.size.div_ceil(512) as u32);
+    for offset in [0x20, 0x28, 0x30] {
+        put_u32(context, &mut data, offset, status.modified);
+    }
+
+    write_guest_memory(context, address, &data)
+}
+
+// struct stat64 from the ARM asm/stat.h, with its EABI padding
+fn write_stat64(context: &mut CpuContext, address: u32, status: &FileStatus) -> Result<(), i32> {
+    let mut data = vec![0u8; STAT64_SIZE];
+
+    put_u32(context, &mut data, 0x10, status.mode);
+    put_u32(context, &mut data, 0x14, 1);
+    put_u64(context, &mut data, 0x30, status.size);
+    put_u32(context, &mut data, 0x38, PAGE_SIZE as u32);
+    put_u64(context, &mut data, 0x40, status.size.div_ceil(512));
+    for offset in [0x48, 0x50, 0x58] {
+        put_u32(context, &mut data, offset, status.modified);
+    }
+
+    write_guest_memory(context, address, &data)
+}
+
+fn get_process_id() -> u32 {
+    std::process::id()
+}
+
+fn set_thread_id_address(process: &mut ProcessState, context: &mut CpuContext) -> SystemCallResult {
+    process.thread_id_address = context.get_register(0);
+    Ok(get_process_id())
+}
+
+fn set_thread_local_storage(process: &mut ProcessState, context: &mut CpuContext) -> SystemCallResult {
+    process.thread_local_storage = context.get_register(0);
+    Ok(0)
+}
+
+// the heap is a single region from the initial break up to the current one; failure is signalled by returning the old break
+fn set_program_break(process: &mut ProcessState, context: &mut CpuContext) -> SystemCallResult {
+    let requested = context.get_register(0);
+    let (initial, current) = (process.initial_program_break, process.program_break);
+
+    if requested < initial || requested > MAPPING_AREA_START {
+        return Ok(current);
+    }
+
+    let memory = context.get_memory_mut();
+    memory.unmap(HEAP_REGION_NAME);
+
+    let end = align_to_page(requested);
+    if end > initial && memory.map_memory(HEAP_REGION_NAME, initial, (end - initial) as u64, Permissions::DATA).is_err() {
+        return Ok(current);
+    }
+
+    // memory the heap grows into is always zeroed
+    if requested > current {
+        zero_guest_memory(context, current, requested - current)?;
+    }
+
+    process.program_break = requested;
+    Ok(requested)
+}
+
+// mmap2(addr, length, prot, flags, fd, pgoffset); the offset is in pages
+fn map_memory(process: &mut ProcessState, context: &mut CpuContext) -> SystemCallResult {
+    let requested_address = context.get_register(0);
+    let length = context.get_register(1);
+    let protection = context.get_register(2);
+    let flags = context.get_register(3);
+    let descriptor = context.get_register(4);
+    let page_offset = context.get_register(5);
+
+    if length == 0 || length > MAPPING_AREA_END - MAPPING_AREA_START {
+        return Err(EINVAL);
+    }
+
+    let length = align_to_page(length);
+
+    let address = if flags & MAP_FIXED != 0 {
+        if !requested_address.is_multiple_of(PAGE_SIZE as u32) {
+            return Err(EINVAL);
+        }
+
+        // like the kernel, refuse fixed mappings that run past the end of the address space
+        if requested_address as u64 + length as u64 > MAXIMUM_MEMORY_SIZE {
+            return Err(ENOMEM);
+        }
+
+        check_mappings_are_covered(process, requested_address, length)?;
+        requested_address
+    } else {
+        if process.next_mapping_address > MAPPING_AREA_END - length {
+            return Err(ENOMEM);
+        }
+        process.next_mapping_address
+    };
+
+    let data = if flags & MAP_ANONYMOUS == 0 {
+        let mut data = vec![0u8; length as usize];
+
+        let stream = process.get_file(descriptor)?;
+        if !stream.is_seekable() {
+            return Err(ENODEV);
+        }
+
+        stream.seek(SeekFrom::Start(page_offset as u64 * PAGE_SIZE as u64)).map_err(get_errno)?;
+        read_fully(stream, &mut data).map_err(get_errno)?;
+
+        Some(data)
+    } else {
+        None
+    };
+
+    let permissions = Permissions {
+        read: protection & PROT_READ != 0,
+        write: protection & PROT_WRITE != 0,
+        execute: protection & PROT_EXEC != 0,
+    };
+    // a fixed mapping replaces the ones it covers
+    if flags & MAP_FIXED != 0 {
+        remove_mappings(process, context, address, length);
+    }
+    context.get_memory_mut().map_memory(&get_mapping_name(address), address, length as u64, permissions).map_err(|_| EINVAL)?;
+
+    if flags & MAP_FIXED == 0 {
+        process.next_mapping_address += length;
+    }
+    process.mappings.push(Mapping { start: address, length });
+
+    match data {
+        Some(data) => context.load_memory(address, &data).map_err(|_| EFAULT)?,
+        None => zero_guest_memory(context, address, length)?,
+    }
+
+    Ok(address)
+}
+
+// only whole mappings can be unmapped, as mappings aren't split
+fn unmap_memory(process: &mut ProcessState, context: &mut CpuContext) -> SystemCallResult {
+    let address = context.get_register(0);
+    let length = context.get_register(1);
+
+    if !address.is_multiple_of(PAGE_SIZE as u32) || length == 0 {
+        return Err(EINVAL);
+    }
+
+    let length = align_to_page(length);
+    check_mappings_are_covered(process, address, length)?;
+    remove_mappings(process, context, address, length);
+
+    Ok(0)
+}
+
+// fails with EINVAL if a mapping overlaps the range without lying entirely inside it, since that would split the mapping
+fn check_mappings_are_covered(process: &ProcessState, address: u32, length: u32) -> Result<(), i32> {
+    let (start, end) = (address as u64, address as u64 + length as u64);
+
+    let is_split = process.mappings.iter()
+        .map(|m| (m.start as u64, m.start as u64 + m.length as u64))
+        .any(|(mapping_start, mapping_end)| mapping_start < end && start < mapping_end && (mapping_start < start || mapping_end > end));
+
+    if is_split {
+        return Err(EINVAL);
+    }
+
+    Ok(())
+}
+
+// removes the mappings lying entirely inside the range
+fn remove_mappings(process: &mut ProcessState, context: &mut CpuContext, address: u32, length: u32) {
+    let (start, end) = (address as u64, address as u64 + length as u64);
+
+    let (removed, kept): (Vec<Mapping>, Vec<Mapping>) = process.mappings.drain(..)
+        .partition(|m| m.start as u64 >= start && m.start as u64 + m.length as u64 <= end);
+    process.mappings = kept;
+
+    for mapping in removed {
+        context.get_memory_mut().unmap(&get_mapping_name(mapping.start));
+    }
+}
+
+fn get_mapping_name(address: u32) -> String {
+    format!("mmap {:0>8X}", address)
+}
+
+// struct utsname consists of six 65-byte strings
+fn get_system_name(context: &mut CpuContext) -> SystemCallResult {
+    let address = context.get_register(0);
+    let fields = ["Linux", "rusty_arm", "5.15.0", "#1", "armv7l", "(none)"];
+
+    let mut data = vec![0u8; fields.len() * UTSNAME_FIELD_SIZE];
+    for (index, field) in fields.iter().enumerate() {
+        let offset = index * UTSNAME_FIELD_SIZE;
+        data[offset..offset + field.len()].copy_from_slice(field.as_bytes());
+    }
+
+    write_guest_memory(context, address, &data)?;
+    Ok(0)
+}
+
+// only TCGETS is supported, which is how libc's isatty() finds out whether a descriptor is a terminal
+fn io_control(process: &mut ProcessState, context: &mut CpuContext) -> SystemCallResult {
+    let descriptor = context.get_register(0);
+    let request = context.get_register(1);
+    let address = context.get_register(2);
+
+    let is_terminal = process.get_file(descriptor)?.is_terminal();
+
+    if request != TCGETS || !is_terminal {
+        return Err(ENOTTY);
+    }
+
+    // struct termios with the flags of a terminal in canonical mode with echo
+    let mut data = vec![0u8; TERMIOS_SIZE];
+    put_u32(context, &mut data, 0x0, TERMIOS_INPUT_FLAGS);
+    put_u32(context, &mut data, 0x4, TERMIOS_OUTPUT_FLAGS);
+    put_u32(context, &mut data, 0x8, TERMIOS_CONTROL_FLAGS);
+    put_u32(context, &mut data, 0xC, TERMIOS_LOCAL_FLAGS);
+
+    write_guest_memory(context, address, &data)?;
+    Ok(0)
+}
+
+fn get_time_of_day(context: &mut CpuContext) -> SystemCallResult {
+    let address = context.get_register(0);
+
+    if address != 0 {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+
+        write_guest_word(context, address, now.as_secs() as u32)?;
+        write_guest_word(context, address.wrapping_add(WORD_SIZE), now.subsec_micros())?;
+    }
+
+    Ok(0)
+}
+
+// time_t is 32 bits wide; every clock other than the real-time one counts from the start of the process
+fn get_clock_time(process: &mut ProcessState, context: &mut CpuContext) -> SystemCallResult {
+    let clock = context.get_register(0);
+    let address = context.get_register(1);
+
+    let time = match clock {
+        CLOCK_REALTIME => SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default(),
+        CLOCK_MONOTONIC | CLOCK_PROCESS_CPUTIME_ID | CLOCK_THREAD_CPUTIME_ID => process.start_time.elapsed(),
+        _ => return Err(EINVAL),
+    };
+
+    write_guest_word(context, address, time.as_secs() as u32)?;
+    write_guest_word(context, address.wrapping_add(WORD_SIZE), time.subsec_nanos())?;
+
+    Ok(0)
+}
+
+fn read_fully(stream: &mut Stream, buffer: &mut [u8]) -> io::Result<()> {
+    let mut done = 0;
+    while done < buffer.len() {
+        match stream.read(&mut buffer[done..])? {
+            0 => break,
+            length => done += length,
+        }
+    }
+
+    Ok(())
+}
+
+fn read_guest_memory(context: &CpuContext, address: u32, length: u32) -> Result<Vec<u8>, i32> {
+    context.read_memory(address, length as usize).map_err(|_| EFAULT)
+}
+
+fn write_guest_memory(context: &mut CpuContext, address: u32, data: &[u8]) -> Result<(), i32> {
+    context.write_memory(address, data).map_err(|_| EFAULT)
+}
+
+// discards the pages rather than writing zeroes to them, so that large mappings cost nothing until they're used
+fn zero_guest_memory(context: &mut CpuContext, address: u32, length: u32) -> Result<(), i32> {
+    context.get_memory_mut().clear(address, length as usize).map_err(|_| EFAULT)
+}
+
+fn read_guest_word(context: &CpuContext, address: u32) -> Result<u32, i32> {
+    context.read_word(address).map_err(|_| EFAULT)
+}
+
+fn write_guest_word(context: &mut CpuContext, address: u32, value: u32) -> Result<(), i32> {
+    context.write_word(address, value).map_err(|_| EFAULT)
+}
+
+// reads a NUL-terminated string, such as a path
+fn read_guest_string(context: &CpuContext, address: u32) -> Result<String, i32> {
+    let mut bytes = Vec::new();
+
+    loop {
+        let byte = context.read_byte(address.wrapping_add(bytes.len() as u32)).map_err(|_| EFAULT)?;
+        if byte == 0 {
+            break;
+        }
+
+        bytes.push(byte);
+        if bytes.len() >= PATH_MAX {
+            return Err(ENAMETOOLONG);
+        }
+    }
+
+    String::from_utf8(bytes).map_err(|_| EINVAL)
+}
+
+// structures are laid out in the guest's byte order
+fn put_u16(context: &CpuContext, data: &mut [u8], offset: usize, value: u16) {
+    let bytes = match context.get_endianness() {
+        Endianness::Little => value.to_le_bytes(),
+        Endianness::Big => value.to_be_bytes(),
+    };
+
+    data[offset..offset + size_of::<u16>()].copy_from_slice(&bytes);
+}
+
+fn put_u32(context: &CpuContext, data: &mut [u8], offset: usize, value: u32) {
+    let bytes = match context.get_endianness() {
+        Endianness::Little => value.to_le_bytes(),
+        Endianness::Big => value.to_be_bytes(),
+    };
+
+    data[offset..offset + size_of::<u32>()].copy_from_slice(&bytes);
+}
+
+fn put_u64(context: &CpuContext, data: &mut [u8], offset: usize, value: u64) {
+    let bytes = match context.get_endianness() {
+        Endianness::Little => value.to_le_bytes(),
+        Endianness::Big => value.to_be_bytes(),
+    };
+
+    data[offset..offset + size_of::<u64>()].copy_from_slice(&bytes);
+}
+
+fn align_to_page(address: u32) -> u32 {
+    address.wrapping_add(PAGE_SIZE as u32 - 1) & !(PAGE_SIZE as u32 - 1)
+}
+
+fn get_errno(error: io::Error) -> i32 {
+    match error.kind() {
+        ErrorKind::NotFound => ENOENT,
+        ErrorKind::PermissionDenied => EACCES,
+        ErrorKind::AlreadyExists => EEXIST,
+        ErrorKind::InvalidInput => EINVAL,
+        ErrorKind::IsADirectory => EISDIR,
+        ErrorKind::NotADirectory => ENOTDIR,
+        ErrorKind::Unsupported => EBADF,    // the descriptor isn't open for reading or writing
+        _ => EIO,
+    }
+}
+
+const EXIT_SYSTEM_CALL: u32 = 1;
+const READ_SYSTEM_CALL: u32 = 3;
+const WRITE_SYSTEM_CALL: u32 = 4;
+const OPEN_SYSTEM_CALL: u32 = 5;
+const CLOSE_SYSTEM_CALL: u32 = 6;
+const LSEEK_SYSTEM_CALL: u32 = 19;
+const GETPID_SYSTEM_CALL: u32 = 20;
+const BRK_SYSTEM_CALL: u32 = 45;
+const IOCTL_SYSTEM_CALL: u32 = 54;
+const GETTIMEOFDAY_SYSTEM_CALL: u32 = 78;
+const MUNMAP_SYSTEM_CALL: u32 = 91;
+const FSTAT_SYSTEM_CALL: u32 = 108;
+const UNAME_SYSTEM_CALL: u32 = 122;
+const LLSEEK_SYSTEM_CALL: u32 = 140;
+const WRITEV_SYSTEM_CALL: u32 = 146;
+const MMAP2_SYSTEM_CALL: u32 = 192;
+const FSTAT64_SYSTEM_CALL: u32 = 197;
+const EXIT_GROUP_SYSTEM_CALL: u32 = 248;
+const SET_TID_ADDRESS_SYSTEM_CALL: u32 = 256;
+const CLOCK_GETTIME_SYSTEM_CALL: u32 = 263;
+const OPENAT_SYSTEM_CALL: u32 = 322;
+const ARM_CACHEFLUSH_SYSTEM_CALL: u32 = 0xf0002;
+const ARM_SET_TLS_SYSTEM_CALL: u32 = 0xf0005;
+
+const EXIT_STATUS_MASK: u32 = 0xff;
+
+const ENOENT: i32 = 2;
+const EIO: i32 = 5;
+const EBADF: i32 = 9;
+const ENOMEM: i32 = 12;
+const EACCES: i32 = 13;
+const EFAULT: i32 = 14;
+const EEXIST: i32 = 17;
+const ENODEV: i32 = 19;
+const ENOTDIR: i32 = 20;
+const EISDIR: i32 = 21;
+const EINVAL: i32 = 22;
+const ENOTTY: i32 = 25;
+const ESPIPE: i32 = 29;
+const ENAMETOOLONG: i32 = 36;
+const ENOSYS: i32 = 38;
+const EOVERFLOW: i32 = 75;
+
+const AT_FDCWD: i32 = -100;
+const O_ACCMODE: u32 = 0o3;
+const O_RDONLY: u32 = 0o0;
+const O_WRONLY: u32 = 0o1;
+const O_RDWR: u32 = 0o2;
+const O_CREAT: u32 = 0o100;
+const O_EXCL: u32 = 0o200;
+const O_TRUNC: u32 = 0o1000;
+const O_APPEND: u32 = 0o2000;
+const PATH_MAX: usize = 4096;
+
+const SEEK_SET: u32 = 0;
+const SEEK_CUR: u32 = 1;
+const SEEK_END: u32 = 2;
+
+const S_IFCHR: u32 = 0o020000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
+const STAT_SIZE: usize = 64;
+const STAT64_SIZE: usize = 104;
+
+const PROT_READ: u32 = 0x1;
+const PROT_WRITE: u32 = 0x2;
+const PROT_EXEC: u32 = 0x4;
+const MAP_FIXED: u32 = 0x10;
+const MAP_ANONYMOUS: u32 = 0x20;
+
+const CLOCK_REALTIME: u32 = 0;
+const CLOCK_MONOTONIC: u32 = 1;
+const CLOCK_PROCESS_CPUTIME_ID: u32 = 2;
+const CLOCK_THREAD_CPUTIME_ID: u32 = 3;
+
+const TCGETS: u32 = 0x5401;
+const TERMIOS_SIZE: usize = 36;
+const TERMIOS_INPUT_FLAGS: u32 = 0o2400;       // ICRNL | IXON
+const TERMIOS_OUTPUT_FLAGS: u32 = 0o5;         // OPOST | ONLCR
+const TERMIOS_CONTROL_FLAGS: u32 = 0o277;      // B38400 | CS8 | CREAD
+const TERMIOS_LOCAL_FLAGS: u32 = 0o105073;     // ISIG | ICANON | ECHO | ECHOE | ECHOK | ECHOCTL | ECHOKE | IEXTEN
+
+const UTSNAME_FIELD_SIZE: usize = 65;
+const IOVEC_SIZE: u32 = 8;
+const WORD_SIZE: u32 = 4;
+const MAXIMUM_READ_SIZE: u32 = 0x100000;
+
+// anonymous and file mappings are placed from here upwards, leaving room below for the program and its heap
+const MAPPING_AREA_START: u32 = 0x40000000;
+const MAPPING_AREA_END: u32 = 0xB0000000;
+const HEAP_REGION_NAME: &str = "heap";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{context::CpuConfiguration, stream::{OutputBuffer, STANDARD_OUTPUT}};
+
+    fn call(handler: &mut LinuxSyscallHandler, context: &mut CpuContext, system_call: u32, arguments: &[u32]) -> u32 {
+        for (register, value) in arguments.iter().enumerate() {
+            context.set_register(register as u8, *value);
+        }
+        context.set_register(SYSTEM_CALL_REGISTER, system_call);
+
+        handler.handle(SYSTEM_CALL_IMMEDIATE, context).unwrap();
+        context.get_register(0)
+    }
+
+    #[test]
+    fn fixed_mapping_past_the_end_of_the_address_space_fails() {
+        let mut context = CpuContext::create(CpuConfiguration::default());
+        let mut handler = LinuxSyscallHandler::create(Sandbox::create("."));
+        let flags = MAP_FIXED | MAP_ANONYMOUS;
+
+        let result = call(&mut handler, &mut context, MMAP2_SYSTEM_CALL, &[0xfffff000, 0x2000, PROT_READ | PROT_WRITE, flags, u32::MAX, 0]);
+        assert_eq!(result, -ENOMEM as u32);
+        assert_eq!(context.get_memory().get_region_name(0xfffff000), Some("RAM"));
+
+        let result = call(&mut handler, &mut context, MMAP2_SYSTEM_CALL, &[0xfffff000, 0x1000, PROT_READ | PROT_WRITE, flags, u32::MAX, 0]);
+        assert_eq!(result, 0xfffff000);
+        assert_eq!(context.get_memory().get_region_name(0xfffff000), Some("mmap FFFFF000"));
+    }
+
+    #[test]
+    fn mappings_are_only_replaced_or_removed_whole() {
+        let mut context = CpuContext::create(CpuConfiguration::default());
+        let mut handler = LinuxSyscallHandler::create(Sandbox::create("."));
+        let flags = MAP_FIXED | MAP_ANONYMOUS;
+
+        context.write_memory(0x10000, &[0xff; 0x3000]).unwrap();
+        assert_eq!(call(&mut handler, &mut context, MMAP2_SYSTEM_CALL, &[0x10000, 0x2000, PROT_READ | PROT_WRITE, flags, u32::MAX, 0]), 0x10000);
+        assert_eq!(context.read_memory(0x10000, 0x2000).unwrap(), vec![0; 0x2000]);
+        assert_eq!(context.read_word(0x12000).unwrap(), 0xffffffff);
+
+        // a range that covers part of a mapping can't split it
+        assert_eq!(call(&mut handler, &mut context, MMAP2_SYSTEM_CALL, &[0x11000, 0x2000, PROT_READ, flags, u32::MAX, 0]), -EINVAL as u32);
+        assert_eq!(call(&mut handler, &mut context, MUNMAP_SYSTEM_CALL, &[0x10000, 0x1000]), -EINVAL as u32);
+        assert_eq!(context.get_memory().get_region_name(0x11000), Some("mmap 00010000"));
+
+        // one that covers all of it replaces it
+        assert_eq!(call(&mut handler, &mut context, MMAP2_SYSTEM_CALL, &[0x10000, 0x3000, PROT_READ, flags, u32::MAX, 0]), 0x10000);
+        assert_eq!(context.get_memory().get_region_name(0x11000), Some("mmap 00010000"));
+        assert!(matches!(context.write_memory(0x11000, &[0]), Err(EmulatorError::PermissionFault { .. })));
+
+        assert_eq!(call(&mut handler, &mut context, MUNMAP_SYSTEM_CALL, &[0x10000, 0x3000]), 0);
+        assert_eq!(context.get_memory().get_region_name(0x11000), Some("RAM"));
+    }
+
+    #[test]
+    fn course_exit_records_the_exit_status() {
+        let mut context = CpuContext::create(CpuConfiguration::default());
+        let mut handler = CourseSyscallHandler::create(FileTable::create(Sandbox::create(".")));
+
+        context.set_register(0, 0x1234);
+        context.set_register(SYSTEM_CALL_REGISTER, EXIT_SYSTEM_CALL);
+        handler.handle(SYSTEM_CALL_IMMEDIATE, &mut context).unwrap();
+
+        assert!(context.is_halted());
+        assert_eq!(context.get_exit_status(), Some(0x34));
+    }
+
+    #[test]
+    fn program_break_grows_and_shrinks_the_heap() {
+        let mut context = CpuContext::create(CpuConfiguration::default());
+        let mut handler = LinuxSyscallHandler::create(Sandbox::create("."));
+        let image = LoadedImage { entry_point: 0x8000, program_header_address: 0x8034, program_header_entry_size: 32, program_header_count: 1, end_address: 0x9001 };
+
+        handler.prepare(&image, &[]);
+
+        // the heap starts at the next page, and requests below it only return the current break
+        assert_eq!(call(&mut handler, &mut context, BRK_SYSTEM_CALL, &[0]), 0xa000);
+        assert_eq!(call(&mut handler, &mut context, BRK_SYSTEM_CALL, &[0xa100]), 0xa100);
+        assert_eq!(context.get_memory().get_region_name(0xa0ff), Some("heap"));
+        assert_eq!(call(&mut handler, &mut context, BRK_SYSTEM_CALL, &[0x9000]), 0xa100);
+
+        // memory the heap grows back into reads as zero
+        context.write_memory(0xa000, &[0xff; 4]).unwrap();
+        assert_eq!(call(&mut handler, &mut context, BRK_SYSTEM_CALL, &[0xa000]), 0xa000);
+        assert_eq!(call(&mut handler, &mut context, BRK_SYSTEM_CALL, &[0xa004]), 0xa004);
+        assert_eq!(context.read_word(0xa000).unwrap(), 0);
+    }
+
+    #[test]
+    fn writes_go_to_the_descriptor() {
+        let mut context = CpuContext::create(CpuConfiguration { memory_size: 0x10000, ..Default::default() });
+        let mut handler = LinuxSyscallHandler::create(Sandbox::create("."));
+        let output = OutputBuffer::create();
+
+        handler.get_files_mut().redirect(STANDARD_OUTPUT, Stream::Buffer(output.clone()));
+        context.write_memory(0x2000, b"Hello, world\n").unwrap();
+
+        assert_eq!(call(&mut handler, &mut context, WRITE_SYSTEM_CALL, &[STANDARD_OUTPUT, 0x2000, 7]), 7);
+
+        // writev with two struct iovec entries
+        context.write_memory(0x3000, &[0x07, 0x20, 0, 0, 0x05, 0, 0, 0, 0x0c, 0x20, 0, 0, 0x01, 0, 0, 0]).unwrap();
+        assert_eq!(call(&mut handler, &mut context, WRITEV_SYSTEM_CALL, &[STANDARD_OUTPUT, 0x3000, 2]), 6);
+
+        assert_eq!(output.get_text(), "Hello, world\n");
+        assert_eq!(call(&mut handler, &mut context, WRITE_SYSTEM_CALL, &[7, 0x2000, 1]), -EBADF as u32);
+        assert_eq!(call(&mut handler, &mut context, WRITE_SYSTEM_CALL, &[STANDARD_OUTPUT, 0xfff0, 0x20]), -EFAULT as u32);
+    }
+
+    #[test]
+    fn files_are_opened_read_and_closed_inside_the_sandbox() {
+        let directory = std::env::temp_dir().join(format!("rusty_arm_syscall_{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("input.txt"), b"contents").unwrap();
+
+        let mut context = CpuContext::create(CpuConfiguration::default());
+        let mut handler = LinuxSyscallHandler::create(Sandbox::create(&directory));
+
+        context.write_memory(0x2000, b"/input.txt\0").unwrap();
+        context.write_memory(0x2100, b"../input.txt\0").unwrap();
+
+        let descriptor = call(&mut handler, &mut context, OPEN_SYSTEM_CALL, &[0x2000, O_RDONLY]);
+        assert_eq!(descriptor, 3);
+        assert_eq!(call(&mut handler, &mut context, LSEEK_SYSTEM_CALL, &[descriptor, 4, SEEK_SET]), 4);
+        assert_eq!(call(&mut handler, &mut context, READ_SYSTEM_CALL, &[descriptor, 0x3000, 0x100]), 4);
+        assert_eq!(context.read_memory(0x3000, 4).unwrap(), b"ents");
+        assert_eq!(call(&mut handler, &mut context, CLOSE_SYSTEM_CALL, &[descriptor]), 0);
+        assert_eq!(call(&mut handler, &mut context, CLOSE_SYSTEM_CALL, &[descriptor]), -EBADF as u32);
+
+        assert_eq!(call(&mut handler, &mut context, OPEN_SYSTEM_CALL, &[0x2100, O_RDONLY]), -EACCES as u32);
+        assert_eq!(call(&mut handler, &mut context, 0x1234, &[]), -ENOSYS as u32);
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn course_write_takes_a_length_prefixed_utf16_string() {
+        let mut context = CpuContext::create(CpuConfiguration::default());
+        let mut handler = CourseSyscallHandler::create(FileTable::create(Sandbox::create(".")));
+        let output = OutputBuffer::create();
+
+        handler.get_files_mut().redirect(STANDARD_OUTPUT, Stream::Buffer(output.clone()));
+        context.write_memory(0x2000, &[0x03, 0, 0, 0, b'H', 0, 0xe9, 0, b'\n', 0]).unwrap();
+
+        // nothing is returned, so r0 still holds the descriptor
+        context.set_register(0, STANDARD_OUTPUT);
+        context.set_register(1, 0x2000);
+        context.set_register(SYSTEM_CALL_REGISTER, WRITE_SYSTEM_CALL);
+        handler.handle(SYSTEM_CALL_IMMEDIATE, &mut context).unwrap();
+
+        assert_eq!(output.get_text(), "H\u{e9}\n");
+        assert_eq!(context.get_register(0), STANDARD_OUTPUT);
+
+        // anything but exit and write is an error rather than a negative return value
+        context.set_register(SYSTEM_CALL_REGISTER, READ_SYSTEM_CALL);
+        assert!(matches!(handler.handle(SYSTEM_CALL_IMMEDIATE, &mut context), Err(EmulatorError::UnsupportedSystemCall(READ_SYSTEM_CALL))));
+    }
+
+    #[test]
+    fn linux_write_takes_bytes() {
+        let mut context = CpuContext::create(CpuConfiguration::default());
+        let mut handler = LinuxSyscallHandler::create(Sandbox::create("."));
+        let output = OutputBuffer::create();
+
+        handler.get_files_mut().redirect(STANDARD_OUTPUT, Stream::Buffer(output.clone()));
+
+        // the same memory as for the course ABI is written as it is, and the length is returned
+        context.write_memory(0x2000, &[0x03, 0, 0, 0, b'H', 0]).unwrap();
+        assert_eq!(call(&mut handler, &mut context, WRITE_SYSTEM_CALL, &[STANDARD_OUTPUT, 0x2000, 6]), 6);
+        assert_eq!(output.get_contents(), [0x03, 0, 0, 0, b'H', 0]);
+    }
+}